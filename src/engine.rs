@@ -1,11 +1,12 @@
 use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
-use cgmath::{Vector3, Zero};
+use cgmath::Matrix4;
 use vulkano::device::DeviceOwned;
 use vulkano::image::ImageUsage;
-use vulkano::image::view::ImageView;
 use vulkano::swapchain::PresentMode;
 use vulkano::sync::GpuFuture;
 use vulkano_util::context::{VulkanoConfig, VulkanoContext};
@@ -17,13 +18,24 @@ use winit::event_loop::EventLoop;
 use winit::window::{Fullscreen, Window};
 
 pub mod world;
+pub mod replay;
+pub mod robot;
+pub mod particles;
+
+// Simulation modules come from the library, `headless` uses them on their own
+pub use dengine::engine::{export, physics, scene};
 
 mod logic;
 use logic::*;
 
-use world::World;
+#[cfg(test)]
+mod verification;
+
+use world::{CameraControllerType, FollowController, FreeFlyController, OrbitController, World};
 use world::context::EngineContext;
 use world::context::Feature;
+use particles::ParticleMode;
+use physics::body::BodyHandle;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub struct EngineSettings {
@@ -46,6 +58,14 @@ impl EngineSettings {
     }
 }
 
+// Camera controllers `C` cycles through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CameraMode {
+    FreeFly,
+    Orbit,
+    Follow
+}
+
 pub struct EngineApplication {
     world: &'static mut World,
     pub settings: EngineSettings,
@@ -55,7 +75,8 @@ pub struct EngineApplication {
     engine_pipeline: EnginePipeline,
     place_over_frame: PlaceOverFrame,
 
-    render_target_id: usize
+    render_target_id: usize,
+    camera_mode: CameraMode
 }
 
 impl EngineApplication {
@@ -106,12 +127,15 @@ impl EngineApplication {
             renderer.compute_queue(),
             graphics.memory_allocator.clone(),
             graphics.command_buffer_allocator.clone(),
-            graphics.descriptor_set_allocator.clone()
+            graphics.descriptor_set_allocator.clone(),
+            world.get_particles().capacity()
         );
+        world.get_particles().set_mode(ParticleMode::Gpu);
 
         let place_over_frame = PlaceOverFrame::new(
             renderer.graphics_queue(),
             renderer.swapchain_format(),
+            graphics.memory_allocator.clone(),
             graphics.command_buffer_allocator.clone(),
             graphics.descriptor_set_allocator.clone()
         );
@@ -124,12 +148,13 @@ impl EngineApplication {
             place_over_frame,
             context,
             windows,
-            render_target_id
+            render_target_id,
+            camera_mode: CameraMode::FreeFly
         }))
     }
 
     pub fn match_input(&mut self) {
-        let inputs = &mut self.context.keyboard;
+        let inputs = &self.context.input.keyboard;
 
         if inputs.is_key_just_released(VirtualKeyCode::F11) {
             let window = self.windows.get_primary_window().unwrap();
@@ -153,32 +178,78 @@ impl EngineApplication {
             graphics.turn_feature(Feature::Depth)
         }
 
-        if inputs.is_key_pressed(VirtualKeyCode::Space) {
-            let camera = self.world.get_camera();
-
-            camera.velocity += Vector3::new(0.0, 0.10, 0.0)
-        }
-        if inputs.is_key_pressed(VirtualKeyCode::LShift) {
-            let camera = self.world.get_camera();
-
-            camera.velocity -= Vector3::new(0.0, 0.10, 0.0)
+        // Physics debug overlay
+        if inputs.is_key_just_released(VirtualKeyCode::F3) {
+            graphics.turn_feature(Feature::PhysicsDebug);
+            self.world.set_debug_visible(graphics.is_feature_enabled(Feature::PhysicsDebug));
         }
 
-        if inputs.is_key_pressed(VirtualKeyCode::W) {
-            let camera = self.world.get_camera();
-            //camera.velocity += 0.1 * camera.rotation;
-            //println!("{:?}", camera.velocity);
-        }
+        let switch_camera = inputs.is_key_just_released(VirtualKeyCode::C);
 
         let exit_keys = HashSet::from([VirtualKeyCode::Escape, VirtualKeyCode::LShift]);
 
         if inputs.is_keys_pressed(exit_keys) {
             self.exit()
         }
+
+        if switch_camera {
+            self.next_camera_controller();
+        }
+    }
+
+    /// Free-fly, then orbit around the point in front, then following the first dynamic body.
+    pub fn next_camera_controller(&mut self) {
+        let camera = self.world.get_camera().clone();
+        let (mode, controller): (CameraMode, CameraControllerType) = match self.camera_mode {
+            CameraMode::FreeFly => (CameraMode::Orbit, Box::new(OrbitController::from_camera(&camera, 5.0))),
+            CameraMode::Orbit => {
+                let physics = self.world.get_physics();
+                match physics.bodies().iter().position(|b| b.is_dynamic()) {
+                    Some(i) => (CameraMode::Follow, Box::new(FollowController::new(BodyHandle(i), 6.0))),
+                    None => (CameraMode::FreeFly, Box::new(FreeFlyController::new()))
+                }
+            },
+            CameraMode::Follow => (CameraMode::FreeFly, Box::new(FreeFlyController::new()))
+        };
+
+        println!("Camera: {}", controller.name());
+        self.camera_mode = mode;
+        self.world.set_camera_controller(controller);
+    }
+
+    /// Records every input event of the session into `path`.
+    pub fn start_recording(&mut self, path: &Path, seed: u64) -> io::Result<()> {
+        let timestep = 1.0 / self.settings.fps_limit as f64;
+        self.world.start_input_recording(&mut self.context.input, path, seed, timestep)
+    }
+
+    /// Plays back a recorded session, live input is ignored until it ends.
+    pub fn start_replay(&mut self, path: &Path) -> io::Result<()> {
+        self.world.start_input_replay(&mut self.context.input, path)
+    }
+
+    /// Keeps the last `seconds` of ticks for stepping back while paused, every tick is a full
+    /// copy of the world so keep it short for large scenes.
+    pub fn enable_history(&mut self, seconds: f64) {
+        let ticks = (seconds * self.settings.fps_limit as f64).round() as usize;
+        self.world.set_history_capacity(ticks);
+    }
+
+    /// Solves soft bodies with `use_backend` set on the compute queue.
+    pub fn enable_soft_body_compute(&mut self) {
+        let solver = self.engine_pipeline.soft_body_solver();
+        self.world.get_physics().set_soft_body_backend(Some(Box::new(solver)));
+    }
+
+    /// Simulates fluids with `use_backend` set on the compute queue.
+    pub fn enable_fluid_compute(&mut self) {
+        let solver = self.engine_pipeline.fluid_solver();
+        self.world.get_physics().set_fluid_backend(Some(Box::new(solver)));
     }
 
     pub fn update_world(&mut self, delta: f64) {
-        self.world.update(&self.context, delta);
+        self.world.process_tick(&mut self.context.input, delta);
+        self.context.profiler.record(self.world.get_physics().step_stats());
     }
 
     pub fn get_world(&mut self) -> &mut World {
@@ -193,7 +264,12 @@ impl EngineApplication {
         let display_size = format!("{}:{}", size.width, size.height);
         let aspect_ratio = renderer.aspect_ratio();
 
-        let title = format!("{}; v{}; Size: {}; AR: {}", self.settings.window_title, VERSION, display_size, aspect_ratio).leak();
+        let stats = self.world.get_physics().stats();
+        let bodies = format!("{}/{}", stats.awake_bodies, stats.sleeping_bodies);
+
+        let particles = self.world.get_particles().alive();
+
+        let title = format!("{}; v{}; Size: {}; AR: {}; Awake/Sleeping: {}; Particles: {}", self.settings.window_title, VERSION, display_size, aspect_ratio, bodies, particles).leak();
         window.set_title(title);
     }
 
@@ -213,10 +289,18 @@ impl EngineApplication {
         self.windows.get_primary_window().take().unwrap()
     }
 
-    pub fn compute(&self, image_target: Arc<ImageView>) -> Box<dyn GpuFuture> {
-        self.engine_pipeline.compute(
-            image_target
-        )
+    pub fn compute(&mut self) -> Box<dyn GpuFuture> {
+        self.engine_pipeline.compute(self.world.get_particles())
+    }
+
+    // The camera projection follows the size of the window
+    fn camera_view_projection(&mut self) -> [[f32; 4]; 4] {
+        let size = self.get_window().inner_size();
+        let camera = self.world.get_camera();
+        camera.set_viewport(size.width as f64, size.height as f64);
+
+        let m: Matrix4<f32> = camera.view_projection().cast().unwrap();
+        m.into()
     }
 
     pub fn compute_then_render(&mut self) {
@@ -234,14 +318,20 @@ impl EngineApplication {
         // Retrieve the target image.
         let image = self.windows.get_primary_renderer_mut().unwrap().get_additional_image_view(self.render_target_id);
 
-        let after_compute = self.compute(image.clone()).join(before_pipeline_future);
+        let after_compute = self.compute().join(before_pipeline_future);
 
         let clear_color = self.context.graphics.clear_color.clone();
 
+        let view_projection = self.camera_view_projection();
+        let debug_lines = match self.context.graphics.is_feature_enabled(Feature::PhysicsDebug) {
+            true => self.world.get_debug_draw().lines().clone(),
+            false => Vec::new()
+        };
+
         // Render the image over the swapchain image, inputting the previous future.
         let after_renderpass_future =
             self.place_over_frame
-                .render(after_compute, clear_color, image, self.windows.get_primary_renderer_mut().unwrap().swapchain_image_view());
+                .render(after_compute, clear_color, image, self.windows.get_primary_renderer_mut().unwrap().swapchain_image_view(), &debug_lines, view_projection);
 
         self.windows.get_primary_renderer_mut().unwrap().present(after_renderpass_future, true);
     }
@@ -0,0 +1,489 @@
+use std::f64::consts::FRAC_PI_2;
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Vector2, Vector3, Vector4, Zero, ortho, perspective};
+use super::Transform;
+use crate::engine::physics::PhysicsWorld;
+use crate::engine::physics::body::BodyHandle;
+use crate::engine::physics::query;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // Vertical field of view in degrees
+    Perspective { fov: f64 },
+    // Visible height in world units
+    Orthographic { height: f64 }
+}
+
+#[derive(Clone)]
+pub struct Camera {
+    pub transform: Transform,
+    pub velocity: Vector3<f64>,
+    pub projection: Projection,
+    pub near: f64,
+    pub far: f64,
+    // Slows the camera down when nothing drives it
+    pub damping: Damping,
+
+    // Window size in pixels
+    viewport: Vector2<f64>
+}
+
+/// Exponential approach to a target, parameterised by the time it takes to halve the gap.
+/// It is solved exactly, so one update of 2 dt ends where two updates of dt do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Damping {
+    pub half_life: f64
+}
+
+impl Damping {
+    pub fn new(half_life: f64) -> Self {
+        Self {half_life}
+    }
+
+    /// Share of the gap to the target left after `delta`.
+    pub fn remaining(&self, delta: f64) -> f64 {
+        match self.half_life > 0.0 {
+            true => 0.5_f64.powf(delta / self.half_life),
+            false => 0.0
+        }
+    }
+
+    pub fn approach(&self, value: Vector3<f64>, target: Vector3<f64>, delta: f64) -> Vector3<f64> {
+        target + (value - target) * self.remaining(delta)
+    }
+
+    /// Value after `delta` and its integral over the same time, e.g. velocity and distance moved.
+    pub fn integrate(&self, value: Vector3<f64>, target: Vector3<f64>, delta: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let remaining = self.remaining(delta);
+        let decay_time = self.half_life.max(0.0) / std::f64::consts::LN_2;
+
+        (target + (value - target) * remaining, target * delta + (value - target) * decay_time * (1.0 - remaining))
+    }
+}
+
+impl Camera {
+    pub fn new(transform: Transform, fov: f64) -> Self {
+        let velocity = Vector3::zero();
+        let projection = Projection::Perspective {fov};
+        let viewport = Vector2::new(1.0, 1.0);
+
+        let damping = Damping::new(0.1);
+
+        Self {transform, velocity, projection, near: 0.1, far: 1000.0, damping, viewport}
+    }
+
+    pub fn orthographic(transform: Transform, height: f64) -> Self {
+        let mut camera = Self::new(transform, 70.0);
+        camera.projection = Projection::Orthographic {height};
+        camera
+    }
+
+    /// Coasts to a stop.
+    pub fn update(&mut self, delta: f64) {
+        let damping = self.damping;
+        self.move_towards(Vector3::zero(), damping, delta);
+    }
+
+    /// Changes the velocity towards `target` and moves by the distance covered on the way.
+    pub fn move_towards(&mut self, target: Vector3<f64>, damping: Damping, delta: f64) {
+        let (velocity, distance) = damping.integrate(self.velocity, target, delta);
+        self.velocity = velocity;
+        self.transform.position += distance;
+    }
+
+    /// Called when the window is resized, the aspect ratio follows it.
+    pub fn set_viewport(&mut self, width: f64, height: f64) {
+        self.viewport = Vector2::new(width.max(1.0), height.max(1.0));
+    }
+
+    pub fn get_viewport(&self) -> Vector2<f64> {
+        self.viewport
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.viewport.x / self.viewport.y
+    }
+
+    /// Looks along the direction of the transform, or -z when it has none.
+    pub fn forward(&self) -> Vector3<f64> {
+        match self.transform.direction {
+            d if d.magnitude2() > 0.0 => d.normalize(),
+            _ => Vector3::new(0.0, 0.0, -1.0)
+        }
+    }
+
+    pub fn set_direction(&mut self, direction: Vector3<f64>) {
+        self.transform.direction = direction;
+    }
+
+    pub fn look_at(&mut self, target: Vector3<f64>) {
+        self.transform.direction = target - self.transform.position;
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f64> {
+        let forward = self.forward();
+        // Straight up or down needs another up vector
+        let up = match forward.y.abs() > 0.999 {
+            true => Vector3::new(0.0, 0.0, -forward.y.signum()),
+            false => Vector3::unit_y()
+        };
+
+        let p = self.transform.position;
+        Matrix4::look_to_rh(Point3::new(p.x, p.y, p.z), forward, up)
+    }
+
+    /// Projection into Vulkan clip space, y points down and depth goes from 0 to 1.
+    pub fn projection_matrix(&self) -> Matrix4<f64> {
+        let aspect_ratio = self.aspect_ratio();
+        let projection = match self.projection {
+            Projection::Perspective {fov} => perspective(Deg(fov), aspect_ratio, self.near, self.far),
+            Projection::Orthographic {height} => {
+                let (x, y) = (0.5 * height * aspect_ratio, 0.5 * height);
+                ortho(-x, x, -y, y, self.near, self.far)
+            }
+        };
+
+        // From OpenGL conventions
+        let correction = Matrix4::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, -1.0, 0.0, 0.0,
+            0.0, 0.0, 0.5, 0.0,
+            0.0, 0.0, 0.5, 1.0
+        );
+
+        correction * projection
+    }
+
+    pub fn view_projection(&self) -> Matrix4<f64> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// Pixel position of a world point from the top left of the window,
+    /// None when it is behind the camera or outside the near and far planes.
+    pub fn world_to_screen(&self, point: Vector3<f64>) -> Option<Vector2<f64>> {
+        let clip = self.view_projection() * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+        if !(0.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+
+        Some(Vector2::new((ndc.x + 1.0) * 0.5 * self.viewport.x, (ndc.y + 1.0) * 0.5 * self.viewport.y))
+    }
+
+    /// World point under a pixel at `depth`, 0 on the near plane and 1 on the far one.
+    pub fn screen_to_world(&self, screen: Vector2<f64>, depth: f64) -> Option<Vector3<f64>> {
+        let inverse = self.view_projection().invert()?;
+        let ndc = Vector4::new(
+            2.0 * screen.x / self.viewport.x - 1.0,
+            2.0 * screen.y / self.viewport.y - 1.0,
+            depth,
+            1.0
+        );
+
+        let world = inverse * ndc;
+        if world.w.abs() < 1.0e-12 {
+            return None;
+        }
+        Some(world.truncate() / world.w)
+    }
+
+    /// Ray from the near plane through a pixel, as origin and unit direction.
+    pub fn screen_ray(&self, screen: Vector2<f64>) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let near = self.screen_to_world(screen, 0.0)?;
+        let far = self.screen_to_world(screen, 1.0)?;
+        Some((near, (far - near).normalize()))
+    }
+}
+
+/// What the player asks of the camera in one update.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraInput {
+    // Right, up and forward, from -1 to 1
+    pub movement: Vector3<f64>,
+    // Mouse movement in pixels, only while looking around
+    pub look: Vector2<f64>,
+    // Wheel lines, positive zooms in
+    pub zoom: f64,
+    pub boost: bool
+}
+
+impl CameraInput {
+    pub fn new() -> Self {
+        Self {movement: Vector3::zero(), look: Vector2::zero(), zoom: 0.0, boost: false}
+    }
+}
+
+pub trait CameraController {
+    fn name(&self) -> &'static str;
+    fn update(&mut self, camera: &mut Camera, input: &CameraInput, physics: &PhysicsWorld, delta: f64);
+}
+
+pub type CameraControllerType = Box<dyn CameraController + Send + Sync>;
+
+// Just short of straight up and down, the view matrix needs a horizon
+const MAX_PITCH: f64 = FRAC_PI_2 - 0.01;
+
+// Yaw around Y from -Z towards +X, pitch up from the horizon
+fn direction(yaw: f64, pitch: f64) -> Vector3<f64> {
+    Vector3::new(yaw.sin() * pitch.cos(), pitch.sin(), -yaw.cos() * pitch.cos())
+}
+
+fn yaw_pitch(direction: Vector3<f64>) -> (f64, f64) {
+    let d = direction.normalize();
+    (d.x.atan2(-d.z), d.y.clamp(-1.0, 1.0).asin())
+}
+
+/// Flies in the view direction, looks around with the mouse.
+pub struct FreeFlyController {
+    // Right, up and forward speed at full input
+    pub max_speed: Vector3<f64>,
+    // Getting up to speed while moving, and slowing down after letting go
+    pub acceleration: Damping,
+    pub deceleration: Damping,
+    pub boost: f64,
+    // Radians per pixel
+    pub sensitivity: f64,
+
+    // Private
+    yaw: f64,
+    pitch: f64,
+    initialized: bool
+}
+
+impl FreeFlyController {
+    pub fn new() -> Self {
+        Self {
+            max_speed: Vector3::new(10.0, 5.0, 10.0),
+            acceleration: Damping::new(0.08),
+            deceleration: Damping::new(0.15),
+            boost: 3.0,
+            sensitivity: 0.003,
+            yaw: 0.0,
+            pitch: 0.0,
+            initialized: false
+        }
+    }
+
+    pub fn with_speed(mut self, max_speed: Vector3<f64>) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    pub fn with_damping(mut self, acceleration: Damping, deceleration: Damping) -> Self {
+        self.acceleration = acceleration;
+        self.deceleration = deceleration;
+        self
+    }
+
+    pub fn with_sensitivity(mut self, sensitivity: f64) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+}
+
+impl CameraController for FreeFlyController {
+    fn name(&self) -> &'static str {
+        "Free-fly"
+    }
+
+    fn update(&mut self, camera: &mut Camera, input: &CameraInput, _physics: &PhysicsWorld, delta: f64) {
+        if !self.initialized {
+            (self.yaw, self.pitch) = yaw_pitch(camera.forward());
+            self.initialized = true;
+        }
+
+        self.yaw += input.look.x * self.sensitivity;
+        self.pitch = (self.pitch - input.look.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        let forward = direction(self.yaw, self.pitch);
+        camera.set_direction(forward);
+
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let boost = if input.boost { self.boost } else { 1.0 };
+        let (m, speed) = (input.movement, self.max_speed * boost);
+        let target = right * m.x * speed.x + Vector3::unit_y() * m.y * speed.y + forward * m.z * speed.z;
+
+        // Speeding up along the input, slowing down against it or without it
+        let damping = match target.dot(target - camera.velocity) > 0.0 {
+            true => self.acceleration,
+            false => self.deceleration
+        };
+        camera.move_towards(target, damping, delta);
+    }
+}
+
+/// Circles a point, the mouse turns around it and the wheel zooms.
+pub struct OrbitController {
+    pub target: Vector3<f64>,
+    pub distance: f64,
+    pub min_distance: f64,
+    pub max_distance: f64,
+    // Distance factor per wheel line
+    pub zoom_speed: f64,
+    pub zoom_damping: Damping,
+    pub sensitivity: f64,
+
+    // Private
+    current_distance: f64,
+    yaw: f64,
+    pitch: f64
+}
+
+impl OrbitController {
+    pub fn new(target: Vector3<f64>, distance: f64) -> Self {
+        Self {
+            target,
+            distance,
+            min_distance: 0.5,
+            max_distance: 500.0,
+            zoom_speed: 1.15,
+            zoom_damping: Damping::new(0.05),
+            sensitivity: 0.005,
+            current_distance: distance,
+            yaw: 0.0,
+            pitch: -0.4
+        }
+    }
+
+    /// Keeps the current view of the camera, orbiting the point it looks at.
+    pub fn from_camera(camera: &Camera, distance: f64) -> Self {
+        let forward = camera.forward();
+        let mut orbit = Self::new(camera.transform.get_position() + forward * distance, distance);
+        (orbit.yaw, orbit.pitch) = yaw_pitch(forward);
+        orbit
+    }
+}
+
+impl CameraController for OrbitController {
+    fn name(&self) -> &'static str {
+        "Orbit"
+    }
+
+    fn update(&mut self, camera: &mut Camera, input: &CameraInput, _physics: &PhysicsWorld, delta: f64) {
+        self.yaw += input.look.x * self.sensitivity;
+        self.pitch = (self.pitch - input.look.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        self.distance = (self.distance * self.zoom_speed.powf(-input.zoom)).clamp(self.min_distance, self.max_distance);
+
+        let remaining = self.zoom_damping.remaining(delta);
+        self.current_distance = self.distance + (self.current_distance - self.distance) * remaining;
+
+        let forward = direction(self.yaw, self.pitch);
+        camera.transform.position = self.target - forward * self.current_distance;
+        camera.set_direction(forward);
+        camera.velocity = Vector3::zero();
+    }
+}
+
+/// Third person camera behind a body, pulled in front of whatever is between them.
+pub struct FollowController {
+    pub target: BodyHandle,
+    pub distance: f64,
+    // Point looked at above the body
+    pub height: f64,
+    // Easing towards the wanted position
+    pub smoothing: Damping,
+    // Distance kept from obstacles
+    pub clearance: f64,
+    pub sensitivity: f64,
+
+    // Private
+    yaw: f64,
+    pitch: f64
+}
+
+impl FollowController {
+    pub fn new(target: BodyHandle, distance: f64) -> Self {
+        Self {target, distance, height: 1.0, smoothing: Damping::new(0.1), clearance: 0.2, sensitivity: 0.005, yaw: 0.0, pitch: -0.3}
+    }
+}
+
+impl CameraController for FollowController {
+    fn name(&self) -> &'static str {
+        "Follow"
+    }
+
+    fn update(&mut self, camera: &mut Camera, input: &CameraInput, physics: &PhysicsWorld, delta: f64) {
+        let body = match physics.get_body(self.target) {
+            Some(body) => body,
+            None => return
+        };
+
+        self.yaw += input.look.x * self.sensitivity;
+        self.pitch = (self.pitch - input.look.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        self.distance = (self.distance * 1.15_f64.powf(-input.zoom)).max(self.clearance);
+
+        let pivot = body.position + Vector3::unit_y() * self.height;
+        let forward = direction(self.yaw, self.pitch);
+
+        // Closest obstacle between the pivot and the wanted position, other than the target
+        let target = self.target;
+        let hit = query::raycast(physics.bodies(), pivot, -forward, self.distance + self.clearance, |h| h != target);
+        let (distance, blocked) = match hit {
+            Some(hit) => ((hit.distance - self.clearance).max(0.0), true),
+            None => (self.distance, false)
+        };
+        let wanted = pivot - forward * distance;
+
+        // Obstacles pull the camera in right away, otherwise it eases after the target
+        let previous = camera.transform.position;
+        let mut position = self.smoothing.approach(previous, wanted, delta);
+        if blocked && (position - pivot).magnitude() > distance {
+            position = wanted;
+        }
+
+        camera.transform.position = position;
+        camera.look_at(pivot);
+        camera.velocity = if delta > 0.0 { (position - previous) / delta } else { Vector3::zero() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3, Zero};
+    use super::{Camera, Damping};
+    use crate::engine::world::Transform;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn close(a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        (a - b).magnitude() < 1.0e-12
+    }
+
+    #[test]
+    fn one_long_step_matches_two_short_ones() {
+        let damping = Damping::new(0.3);
+        let (value, target) = (Vector3::new(4.0, 0.0, -2.0), Vector3::new(1.0, 1.0, 0.0));
+
+        let (long_value, long_distance) = damping.integrate(value, target, 2.0 * DT);
+        let (half_value, first) = damping.integrate(value, target, DT);
+        let (short_value, second) = damping.integrate(half_value, target, DT);
+        assert!(close(long_value, short_value), "{:?} and {:?}", long_value, short_value);
+        assert!(close(long_distance, first + second), "{:?} and {:?}", long_distance, first + second);
+
+        let mut long = Camera::new(Transform::new(Vector3::zero()), 70.0);
+        long.velocity = Vector3::new(3.0, -1.0, 0.5);
+        let mut short = long.clone();
+
+        long.update(2.0 * DT);
+        short.update(DT);
+        short.update(DT);
+        assert!(close(long.velocity, short.velocity));
+        assert!(close(long.transform.position, short.transform.position));
+    }
+
+    #[test]
+    fn half_the_gap_is_left_after_one_half_life() {
+        let damping = Damping::new(0.25);
+        assert!((damping.remaining(0.25) - 0.5).abs() < 1.0e-15);
+        assert!((damping.remaining(0.5) - 0.25).abs() < 1.0e-15);
+
+        let value = damping.approach(Vector3::new(10.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0), 0.25);
+        assert!(close(value, Vector3::new(6.0, 0.0, 0.0)), "{:?}", value);
+
+        // No half-life snaps to the target
+        let snapped = Damping::new(0.0).approach(Vector3::new(10.0, 0.0, 0.0), Vector3::zero(), DT);
+        assert_eq!(snapped, Vector3::zero());
+    }
+}
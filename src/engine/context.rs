@@ -1,12 +1,18 @@
 use std::collections::HashSet;
+use std::io;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::device::Queue;
 use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano_util::context::VulkanoContext;
-use winit::event::{KeyboardInput, VirtualKeyCode};
+use winit::event::{KeyboardInput, MouseButton, VirtualKeyCode};
+
+use crate::engine::physics::profiler::PhysicsProfiler;
+use crate::engine::replay::{InputRecorder, ReplayPlayer};
 
 pub struct GraphicsContext {
     pub queue: Arc<Queue>,
@@ -23,6 +29,8 @@ pub struct GraphicsContext {
 #[derive(Eq, Hash, PartialEq)]
 pub enum Feature {
     Depth,
+    // Collider wireframes, contacts, joints and velocities over the frame
+    PhysicsDebug,
 }
 
 impl GraphicsContext {
@@ -95,6 +103,18 @@ impl TimeContext {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Pressed(VirtualKeyCode),
+    Released(VirtualKeyCode),
+    MousePressed(MouseButton),
+    MouseReleased(MouseButton),
+    // Raw mouse movement in pixels
+    MouseMoved(f64, f64),
+    // Wheel lines, positive away from the user
+    Scrolled(f64)
+}
+
 pub struct KeyboardContext {
     pressed_keys: HashSet<VirtualKeyCode>,
     just_released_keys: HashSet<VirtualKeyCode>
@@ -140,30 +160,153 @@ impl KeyboardContext {
     pub fn release_key(&mut self, key: Option<VirtualKeyCode>) {
         self.pressed_keys.remove(&key.unwrap());
     }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(key) => {
+                self.pressed_keys.insert(key);
+            },
+            InputEvent::Released(key) => {
+                self.pressed_keys.remove(&key);
+                self.just_released_keys.insert(key);
+            },
+            _ => ()
+        }
+    }
 }
 
-pub struct EngineContext {
+pub struct MouseContext {
+    pressed_buttons: HashSet<MouseButton>,
+    motion: (f64, f64),
+    scroll: f64
+}
+
+impl MouseContext {
+    pub fn new() -> Self {
+        Self {pressed_buttons: HashSet::new(), motion: (0.0, 0.0), scroll: 0.0}
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    /// Movement since the last tick.
+    pub fn motion(&self) -> (f64, f64) {
+        self.motion
+    }
+
+    /// Wheel lines since the last tick.
+    pub fn scroll(&self) -> f64 {
+        self.scroll
+    }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::MousePressed(button) => {
+                self.pressed_buttons.insert(button);
+            },
+            InputEvent::MouseReleased(button) => {
+                self.pressed_buttons.remove(&button);
+            },
+            InputEvent::MouseMoved(x, y) => {
+                self.motion.0 += x;
+                self.motion.1 += y;
+            },
+            InputEvent::Scrolled(lines) => self.scroll += lines,
+            _ => ()
+        }
+    }
+}
+
+pub enum InputMode {
+    Live,
+    Recording(InputRecorder),
+    Playback(ReplayPlayer)
+}
+
+/// Keyboard, mouse and the tick count the world is driven by, without any window.
+pub struct InputContext {
     pub time: TimeContext,
     pub keyboard: KeyboardContext,
-    pub graphics: GraphicsContext
+    pub mouse: MouseContext,
+    pub mode: InputMode
 }
 
-impl EngineContext {
-    pub fn new(window_context: &'static mut VulkanoContext) -> &'static mut Self {
-        let time = TimeContext::new();
-        let keyboard = KeyboardContext::new();
+impl InputContext {
+    pub fn new() -> Self {
+        Self {time: TimeContext::new(), keyboard: KeyboardContext::new(), mouse: MouseContext::new(), mode: InputMode::Live}
+    }
 
-        let graphics = GraphicsContext::new(window_context);
+    /// Input from the window, ignored while a replay is playing.
+    pub fn handle_input(&mut self, event: InputEvent) {
+        match &mut self.mode {
+            InputMode::Playback(_) => return,
+            InputMode::Recording(recorder) => {
+                if let Err(e) = recorder.record(self.time.ticks, event) {
+                    println!("Failed to record input: {e}");
+                }
+            },
+            InputMode::Live => ()
+        }
+
+        self.keyboard.apply(event);
+        self.mouse.apply(event);
+    }
+
+    /// Feeds recorded events of the current tick, call before the world update.
+    pub fn play_inputs(&mut self) {
+        if let InputMode::Playback(player) = &mut self.mode {
+            for event in player.events_for(self.time.ticks) {
+                self.keyboard.apply(event);
+                self.mouse.apply(event);
+            }
+
+            if player.is_finished() {
+                println!("Replay finished at tick {}", self.time.ticks);
+                self.mode = InputMode::Live;
+            }
+        }
+    }
 
-        Box::leak(Box::new(Self {time, keyboard, graphics}))
+    pub fn start_recording(&mut self, path: &Path, seed: u64, timestep: f64) -> io::Result<()> {
+        let recorder = InputRecorder::create(path, self.time.ticks, seed, timestep)?;
+        self.mode = InputMode::Recording(recorder);
+        Ok(())
     }
 
+    pub fn start_playback(&mut self, player: ReplayPlayer) {
+        self.time.ticks = player.start_tick();
+        self.mode = InputMode::Playback(player);
+    }
+
+    /// Ends the tick.
     pub fn update(&mut self) {
         // Update time context
         self.time.frame_time = Instant::now();
         self.time.ticks += 1;
 
-        // Update keyboard context
+        // Update keyboard and mouse context
         self.keyboard.just_released_keys.clear();
+        self.mouse.motion = (0.0, 0.0);
+        self.mouse.scroll = 0.0;
+    }
+}
+
+pub struct EngineContext {
+    pub input: InputContext,
+    pub graphics: GraphicsContext,
+    // Statistics of the last physics steps, exportable as CSV
+    pub profiler: PhysicsProfiler
+}
+
+impl EngineContext {
+    pub fn new(window_context: &'static mut VulkanoContext) -> &'static mut Self {
+        let input = InputContext::new();
+        let graphics = GraphicsContext::new(window_context);
+
+        // Ten seconds at 60 steps per second
+        let profiler = PhysicsProfiler::new(600);
+
+        Box::leak(Box::new(Self {input, graphics, profiler}))
     }
-}
\ No newline at end of file
+}
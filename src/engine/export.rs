@@ -0,0 +1,333 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use cgmath::{Quaternion, Vector3};
+use serde_json::{Map, Value};
+
+use super::physics::PhysicsWorld;
+use super::physics::body::BodyHandle;
+
+// Simulation data as CSV or JSON Lines, one row per recorded body and sample
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportField {
+    Position,
+    Rotation,
+    Velocity,
+    AngularVelocity,
+    // Translational and rotational
+    KineticEnergy,
+    // Relative to the origin in the gravity of the settings
+    PotentialEnergy,
+    // Normal and friction force from all contacts of the last step
+    ContactForce
+}
+
+impl ExportField {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::Position, Self::Rotation, Self::Velocity, Self::AngularVelocity,
+            Self::KineticEnergy, Self::PotentialEnergy, Self::ContactForce
+        ]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Position => "position",
+            Self::Rotation => "rotation",
+            Self::Velocity => "velocity",
+            Self::AngularVelocity => "angular_velocity",
+            Self::KineticEnergy => "kinetic_energy",
+            Self::PotentialEnergy => "potential_energy",
+            Self::ContactForce => "contact_force"
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        let name = self.name();
+        match self {
+            Self::Position | Self::Velocity | Self::AngularVelocity | Self::ContactForce => {
+                ["x", "y", "z"].iter().map(|c| format!("{}_{}", name, c)).collect()
+            },
+            Self::Rotation => ["w", "x", "y", "z"].iter().map(|c| format!("{}_{}", name, c)).collect(),
+            Self::KineticEnergy | Self::PotentialEnergy => vec![name.to_string()]
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    // Empty - every body
+    pub bodies: Vec<BodyHandle>,
+    pub fields: Vec<ExportField>,
+    // Samples per simulated second, 0 - every tick
+    pub sample_rate: f64,
+    pub gravity: Vector3<f64>
+}
+
+impl ExportSettings {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            bodies: Vec::new(),
+            fields: ExportField::all(),
+            sample_rate: 0.0,
+            gravity: Vector3::new(0.0, -9.81, 0.0)
+        }
+    }
+
+    pub fn with_bodies(mut self, bodies: Vec<BodyHandle>) -> Self {
+        self.bodies = bodies;
+        self
+    }
+
+    pub fn with_fields(mut self, fields: Vec<ExportField>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vector3<f64>) -> Self {
+        self.gravity = gravity;
+        self
+    }
+}
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn vector(v: Vector3<f64>) -> Vec<f64> {
+    vec![v.x, v.y, v.z]
+}
+
+fn quaternion(q: Quaternion<f64>) -> Vec<f64> {
+    vec![q.s, q.v.x, q.v.y, q.v.z]
+}
+
+/// Streams the chosen quantities of the chosen bodies to a file while the world runs.
+pub struct DataRecorder {
+    pub settings: ExportSettings,
+
+    // Private
+    writer: BufWriter<File>,
+    time: f64,
+    next_sample: f64,
+    rows: usize
+}
+
+impl DataRecorder {
+    pub fn create(path: &Path, settings: ExportSettings) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        if settings.format == ExportFormat::Csv {
+            let mut header = vec!["tick".to_string(), "time".to_string(), "body".to_string()];
+            header.extend(settings.fields.iter().flat_map(|f| f.columns()));
+            writeln!(writer, "{}", header.join(","))?;
+        }
+
+        Ok(Self {settings, writer, time: 0.0, next_sample: 0.0, rows: 0})
+    }
+
+    /// Rows written so far.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Called once per tick after the physics advanced by `delta`.
+    pub fn record(&mut self, physics: &PhysicsWorld, delta: f64) -> io::Result<()> {
+        self.time += delta;
+        let time = self.time;
+
+        if self.settings.sample_rate > 0.0 {
+            if time + 1.0e-9 < self.next_sample {
+                return Ok(());
+            }
+            while self.next_sample <= time + 1.0e-9 {
+                self.next_sample += 1.0 / self.settings.sample_rate;
+            }
+        }
+
+        let handles: Vec<BodyHandle> = match self.settings.bodies.is_empty() {
+            true => (0..physics.bodies().len()).map(BodyHandle).collect(),
+            false => self.settings.bodies.clone()
+        };
+
+        for handle in handles {
+            let body = match physics.bodies().get(handle.0) {
+                Some(body) => body,
+                None => continue
+            };
+
+            let values: Vec<(ExportField, Vec<f64>)> = self.settings.fields.iter().map(|field| {
+                let value = match field {
+                    ExportField::Position => vector(body.position),
+                    ExportField::Rotation => quaternion(body.rotation),
+                    ExportField::Velocity => vector(body.velocity),
+                    ExportField::AngularVelocity => vector(body.angular_velocity),
+                    ExportField::KineticEnergy => vec![body.kinetic_energy()],
+                    ExportField::PotentialEnergy => vec![-body.mass() * cgmath::dot(self.settings.gravity, body.position)],
+                    ExportField::ContactForce => vector(physics.contact_force(handle))
+                };
+                (*field, value)
+            }).collect();
+
+            match self.settings.format {
+                ExportFormat::Csv => {
+                    let mut row = vec![physics.ticks().to_string(), time.to_string(), handle.0.to_string()];
+                    row.extend(values.iter().flat_map(|(_, v)| v.iter().map(|x| x.to_string())));
+                    writeln!(self.writer, "{}", row.join(","))?;
+                },
+                ExportFormat::JsonLines => {
+                    let mut row = Map::new();
+                    row.insert("tick".to_string(), Value::from(physics.ticks()));
+                    row.insert("time".to_string(), Value::from(time));
+                    row.insert("body".to_string(), Value::from(handle.0));
+
+                    for (field, value) in values {
+                        let value = match value.len() {
+                            1 => Value::from(value[0]),
+                            _ => Value::from(value)
+                        };
+                        row.insert(field.name().to_string(), value);
+                    }
+
+                    writeln!(self.writer, "{}", serde_json::to_string(&row).map_err(invalid_data)?)?;
+                }
+            }
+
+            self.rows += 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use cgmath::Vector3;
+    use serde_json::Value;
+    use super::{DataRecorder, ExportField, ExportFormat, ExportSettings};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::body::{BodyHandle, RigidBody};
+    use crate::engine::physics::collider::Collider;
+
+    const TIMESTEP: f64 = 1.0 / 60.0;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dengine_{}_{}", std::process::id(), name))
+    }
+
+    fn falling_balls() -> PhysicsWorld {
+        let mut physics = PhysicsWorld::new();
+        for i in 0..3 {
+            physics.add_body(RigidBody::dynamic(Vector3::new(3.0 * i as f64, 10.0, 0.0)).with_collider(Collider::sphere(0.5), 1.0));
+        }
+        physics
+    }
+
+    // Steps the world for `seconds` and returns the file contents
+    fn record(physics: &mut PhysicsWorld, path: &PathBuf, settings: ExportSettings, seconds: f64) -> String {
+        let mut recorder = DataRecorder::create(path, settings).unwrap();
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            physics.step(TIMESTEP);
+            recorder.record(physics, TIMESTEP).unwrap();
+        }
+
+        let rows = recorder.finish().unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(contents.lines().filter(|l| !l.starts_with("tick")).count(), rows);
+        contents
+    }
+
+    #[test]
+    fn csv_has_a_column_per_component() {
+        let mut physics = falling_balls();
+        let path = temp_file("export.csv");
+        let settings = ExportSettings::new(ExportFormat::Csv)
+            .with_fields(vec![ExportField::Position, ExportField::Rotation, ExportField::KineticEnergy]);
+        let contents = record(&mut physics, &path, settings, 0.5);
+
+        let mut lines = contents.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header, [
+            "tick", "time", "body", "position_x", "position_y", "position_z",
+            "rotation_w", "rotation_x", "rotation_y", "rotation_z", "kinetic_energy"
+        ]);
+
+        // Every body on every tick
+        let rows: Vec<Vec<f64>> = lines.map(|l| l.split(',').map(|v| v.parse().unwrap()).collect()).collect();
+        assert_eq!(rows.len(), 30 * 3);
+        assert!(rows.iter().all(|r| r.len() == header.len()));
+
+        let last = &rows[rows.len() - 1];
+        let body = &physics.bodies()[2];
+        assert_eq!(last[0], physics.ticks() as f64);
+        assert_eq!(last[2], 2.0);
+        assert!((last[4] - body.position.y).abs() < 1.0e-9);
+        assert!((last[10] - body.kinetic_energy()).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn json_lines_key_the_chosen_fields() {
+        let mut physics = falling_balls();
+        let path = temp_file("export.jsonl");
+        let settings = ExportSettings::new(ExportFormat::JsonLines)
+            .with_bodies(vec![BodyHandle(1)])
+            .with_fields(vec![ExportField::Velocity, ExportField::PotentialEnergy]);
+        let contents = record(&mut physics, &path, settings, 0.5);
+
+        let rows: Vec<Value> = contents.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(rows.len(), 30);
+
+        for row in rows.iter() {
+            let keys: Vec<&String> = row.as_object().unwrap().keys().collect();
+            assert_eq!(keys.len(), 5, "keys {:?}", keys);
+            assert_eq!(row["body"], 1);
+            assert_eq!(row["velocity"].as_array().unwrap().len(), 3);
+            assert!(row["potential_energy"].is_f64());
+        }
+
+        let body = &physics.bodies()[1];
+        let last = &rows[rows.len() - 1];
+        assert!((last["velocity"][1].as_f64().unwrap() - body.velocity.y).abs() < 1.0e-9);
+        assert!((last["time"].as_f64().unwrap() - 0.5).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn sample_rate_skips_ticks_between_samples() {
+        let mut physics = falling_balls();
+        let path = temp_file("sampled.csv");
+        let settings = ExportSettings::new(ExportFormat::Csv)
+            .with_bodies(vec![BodyHandle(0)])
+            .with_fields(vec![ExportField::Position])
+            .with_sample_rate(10.0);
+        let contents = record(&mut physics, &path, settings, 2.0);
+
+        // The first tick, then every sixth at ten samples a second and sixty ticks
+        let ticks: Vec<u64> = contents.lines().skip(1).map(|l| l.split(',').next().unwrap().parse().unwrap()).collect();
+        assert_eq!(ticks.len(), 21);
+        assert_eq!(ticks[0], 1);
+        assert!(ticks[1..].iter().enumerate().all(|(i, t)| *t == 6 * (i as u64 + 1)), "ticks {:?}", ticks);
+    }
+}
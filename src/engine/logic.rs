@@ -1,24 +1,31 @@
 use std::sync::{Arc, Mutex};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
 use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferInheritanceInfo, CommandBufferLevel, CommandBufferUsage, PrimaryCommandBufferAbstract, RenderPassBeginInfo, SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents};
 use vulkano::command_buffer::sys::CommandBufferBeginInfo;
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::Queue;
 use vulkano::format::Format;
 use vulkano::image::view::ImageView;
-use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::pipeline::{ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::{ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo};
 use vulkano::pipeline::compute::ComputePipelineCreateInfo;
 use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
 use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
 use vulkano::pipeline::graphics::multisample::MultisampleState;
 use vulkano::pipeline::graphics::rasterization::RasterizationState;
-use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition, VertexInputState};
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::sync::GpuFuture;
+use cgmath::Vector3;
+use super::physics::softbody::{SoftBody, SoftBodyBackend};
+use super::physics::fluid::{Fluid, FluidBackend, Neighbours};
+use super::particles::ParticleSystem;
+use super::physics::debug::DebugLine;
 
 pub struct EnginePipeline {
     queue: Arc<Queue>,
@@ -26,6 +33,11 @@ pub struct EnginePipeline {
     memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+
+    // Five vec4 per particle, see `ParticleData`
+    particles: Subbuffer<[[f32; 4]]>,
+    particles_set: Arc<PersistentDescriptorSet>,
+    capacity: usize
 }
 
 impl EnginePipeline {
@@ -33,7 +45,8 @@ impl EnginePipeline {
         queue: Arc<Queue>,
         memory_allocator: Arc<StandardMemoryAllocator>,
         command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
-        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        capacity: usize
     ) -> Self {
         let pipeline = {
             let device = queue.device();
@@ -57,11 +70,48 @@ impl EnginePipeline {
             )
         }.unwrap();
 
-        Self {queue, pipeline, memory_allocator, command_buffer_allocator, descriptor_set_allocator}
+        // Dead particles, age equals lifetime
+        let particles = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vec![[0.0f32; 4]; capacity.max(1) * 5]
+        ).unwrap();
+
+        let particles_set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, particles.clone())],
+            []
+        ).unwrap();
+
+        Self {queue, pipeline, memory_allocator, command_buffer_allocator, descriptor_set_allocator, particles, particles_set, capacity}
     }
 
-    pub fn compute(&self, image_view: Arc<ImageView>) -> Box<dyn GpuFuture> {
-        let image_extent = image_view.image().extent();
+    /// Uploads the particles spawned since the last frame and runs the particle update.
+    pub fn compute(&self, particles: &mut ParticleSystem) -> Box<dyn GpuFuture> {
+        // The previous frame was waited for on present, the buffer is free
+        let spawned = particles.take_spawned();
+        if !spawned.is_empty() {
+            let mut data = self.particles.write().unwrap();
+            for (slot, particle) in spawned.into_iter().filter(|(slot, _)| *slot < self.capacity) {
+                data[slot * 5..slot * 5 + 5].copy_from_slice(&particle.to_vec4());
+            }
+        }
+
+        let gravity = particles.gravity;
+        let params = cs::Params {
+            gravity: [gravity.x as f32, gravity.y as f32, gravity.z as f32, 0.0],
+            delta: particles.take_elapsed() as f32,
+            drag: particles.drag as f32,
+            count: self.capacity as u32
+        };
 
         let mut builder = AutoCommandBufferBuilder::primary(
             &self.command_buffer_allocator.clone(),
@@ -72,7 +122,11 @@ impl EnginePipeline {
         builder
             .bind_pipeline_compute(self.pipeline.clone())
             .unwrap()
-            .dispatch([image_extent[0] / 8, image_extent[1] / 8, 1])
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, self.particles_set.clone())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, params)
+            .unwrap()
+            .dispatch([(self.capacity as u32 + 63) / 64, 1, 1])
             .unwrap();
 
         let command_buffer = builder.build().unwrap();
@@ -80,6 +134,405 @@ impl EnginePipeline {
         let finished = command_buffer.execute(self.queue.clone()).unwrap();
         finished.then_signal_fence_and_flush().unwrap().boxed()
     }
+
+    /// Compute shader passes for fluids, see `PhysicsWorld::set_fluid_backend`.
+    pub fn fluid_solver(&self) -> FluidCompute {
+        FluidCompute::new(
+            self.queue.clone(),
+            self.memory_allocator.clone(),
+            self.command_buffer_allocator.clone(),
+            self.descriptor_set_allocator.clone()
+        )
+    }
+
+    /// Compute shader solver for soft bodies, see `PhysicsWorld::set_soft_body_backend`.
+    pub fn soft_body_solver(&self) -> SoftBodyCompute {
+        SoftBodyCompute::new(
+            self.queue.clone(),
+            self.memory_allocator.clone(),
+            self.command_buffer_allocator.clone(),
+            self.descriptor_set_allocator.clone()
+        )
+    }
+}
+
+// Host visible so the solvers can read the results back
+fn storage_buffer<T: vulkano::buffer::BufferContents + Copy>(memory_allocator: &Arc<StandardMemoryAllocator>, data: Vec<T>) -> Subbuffer<[T]> {
+    Buffer::from_iter(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        data
+    ).unwrap()
+}
+
+// Jacobi version of the distance solve, every invocation gathers the
+// corrections of one particle from its constraints
+pub struct SoftBodyCompute {
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    buffers: Mutex<Option<SoftBodyBuffers>>,
+
+    pub iterations: usize
+}
+
+// Kept between solves, only reallocated when a body does not fit
+struct SoftBodyBuffers {
+    front: Subbuffer<[[f32; 4]]>,
+    back: Subbuffer<[[f32; 4]]>,
+    previous: Subbuffer<[[f32; 4]]>,
+    velocities: Subbuffer<[[f32; 4]]>,
+    accelerations: Subbuffer<[[f32; 4]]>,
+    offsets: Subbuffer<[u32]>,
+    links: Subbuffer<[[f32; 4]]>,
+    // Front to back and back to front
+    sets: [Arc<PersistentDescriptorSet>; 2]
+}
+
+impl SoftBodyCompute {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>
+    ) -> Self {
+        let pipeline = {
+            let device = queue.device();
+
+            let cs = soft_cs::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap();
+            let stage = PipelineShaderStageCreateInfo::new(cs);
+            let layout = PipelineLayout::new(
+                device.clone(),
+                PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                    .into_pipeline_layout_create_info(device.clone())
+                    .unwrap(),
+            ).unwrap();
+
+            ComputePipeline::new(
+                device.clone(),
+                None,
+                ComputePipelineCreateInfo::stage_layout(stage, layout)
+            )
+        }.unwrap();
+
+        Self {queue, pipeline, memory_allocator, command_buffer_allocator, descriptor_set_allocator, buffers: Mutex::new(None), iterations: 4}
+    }
+
+    fn allocate(&self, particles: usize, links: usize) -> SoftBodyBuffers {
+        let vectors = |count: usize| storage_buffer(&self.memory_allocator, vec![[0.0f32; 4]; count]);
+        let front = vectors(particles);
+        let back = vectors(particles);
+        let previous = vectors(particles);
+        let velocities = vectors(particles);
+        let accelerations = vectors(particles);
+        let offsets = storage_buffer(&self.memory_allocator, vec![0u32; particles + 1]);
+        let links = vectors(links);
+
+        let layout = self.pipeline.layout().set_layouts()[0].clone();
+        let descriptor_set = |input: &Subbuffer<[[f32; 4]]>, output: &Subbuffer<[[f32; 4]]>| {
+            PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, input.clone()),
+                    WriteDescriptorSet::buffer(1, output.clone()),
+                    WriteDescriptorSet::buffer(2, offsets.clone()),
+                    WriteDescriptorSet::buffer(3, links.clone()),
+                    WriteDescriptorSet::buffer(4, previous.clone()),
+                    WriteDescriptorSet::buffer(5, velocities.clone()),
+                    WriteDescriptorSet::buffer(6, accelerations.clone())
+                ],
+                []
+            ).unwrap()
+        };
+        let sets = [descriptor_set(&front, &back), descriptor_set(&back, &front)];
+
+        SoftBodyBuffers {front, back, previous, velocities, accelerations, offsets, links, sets}
+    }
+}
+
+impl SoftBodyBackend for SoftBodyCompute {
+    fn solve_substeps(&self, body: &mut SoftBody, accelerations: &[Vector3<f64>], substeps: usize, delta: f64) {
+        let count = body.particles.len();
+        if count == 0 {
+            return;
+        }
+
+        // Constraints of every particle in CSR layout: other index (as bits), rest length, compliance
+        let mut adjacency = vec![Vec::new(); count];
+        for constraint in body.distances.iter() {
+            adjacency[constraint.a].push([f32::from_bits(constraint.b as u32), constraint.rest as f32, constraint.compliance as f32, 0.0]);
+            adjacency[constraint.b].push([f32::from_bits(constraint.a as u32), constraint.rest as f32, constraint.compliance as f32, 0.0]);
+        }
+
+        let mut offsets = vec![0u32];
+        for links in adjacency.iter() {
+            offsets.push(offsets[offsets.len() - 1] + links.len() as u32);
+        }
+        let links: Vec<[f32; 4]> = adjacency.into_iter().flatten().collect();
+
+        let mut buffers = self.buffers.lock().unwrap();
+        let fits = buffers.as_ref()
+            .map(|b| b.front.len() >= count as u64 && b.links.len() >= links.len().max(1) as u64)
+            .unwrap_or(false);
+        if !fits {
+            *buffers = Some(self.allocate(count.next_power_of_two(), links.len().max(1).next_power_of_two()));
+        }
+        let buffers = buffers.as_ref().unwrap();
+
+        // Position and inverse mass, velocity, acceleration
+        {
+            let mut front = buffers.front.write().unwrap();
+            let mut velocities = buffers.velocities.write().unwrap();
+            let mut gravity = buffers.accelerations.write().unwrap();
+
+            for (i, (particle, acceleration)) in body.particles.iter().zip(accelerations.iter()).enumerate() {
+                front[i] = [particle.position.x as f32, particle.position.y as f32, particle.position.z as f32, particle.inv_mass() as f32];
+                velocities[i] = [particle.velocity.x as f32, particle.velocity.y as f32, particle.velocity.z as f32, 0.0];
+                gravity[i] = [acceleration.x as f32, acceleration.y as f32, acceleration.z as f32, 0.0];
+            }
+        }
+        buffers.offsets.write().unwrap()[..offsets.len()].copy_from_slice(&offsets);
+        buffers.links.write().unwrap()[..links.len()].copy_from_slice(&links);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        ).unwrap();
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap();
+
+        // Every pass ping-pongs between the buffers. Prediction, an even number of distance
+        // passes and the velocity pass leave each substep's result in the front buffer
+        let iterations = (self.iterations.max(1) + 1) / 2 * 2;
+        let keep = (1.0 - body.damping * delta).max(0.0);
+        let mut source = 0;
+
+        for _ in 0..substeps.max(1) {
+            let passes = std::iter::once(0).chain(std::iter::repeat(1).take(iterations)).chain(std::iter::once(2));
+            for pass in passes {
+                let params = soft_cs::Params {count: count as u32, pass, dt: delta as f32, keep: keep as f32};
+                builder
+                    .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, buffers.sets[source].clone())
+                    .unwrap()
+                    .push_constants(self.pipeline.layout().clone(), 0, params)
+                    .unwrap()
+                    .dispatch([(count as u32 + 63) / 64, 1, 1])
+                    .unwrap();
+                source = 1 - source;
+            }
+        }
+
+        let command_buffer = builder.build().unwrap();
+        command_buffer.execute(self.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let positions = buffers.front.read().unwrap();
+        let velocities = buffers.velocities.read().unwrap();
+        for (i, particle) in body.particles.iter_mut().enumerate() {
+            if particle.inv_mass() > 0.0 {
+                particle.position = Vector3::new(positions[i][0] as f64, positions[i][1] as f64, positions[i][2] as f64);
+                particle.velocity = Vector3::new(velocities[i][0] as f64, velocities[i][1] as f64, velocities[i][2] as f64);
+            }
+        }
+    }
+}
+
+// Density, force and integration passes of the SPH solver, one invocation per particle over
+// the neighbour lists built on the CPU
+pub struct FluidCompute {
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    buffers: Mutex<Option<FluidBuffers>>
+}
+
+// Kept between steps, only reallocated when a fluid does not fit
+struct FluidBuffers {
+    particles: Subbuffer<[[f32; 4]]>,
+    offsets: Subbuffer<[u32]>,
+    indices: Subbuffer<[u32]>,
+    accelerations: Subbuffer<[[f32; 4]]>,
+    external: Subbuffer<[[f32; 4]]>,
+    set: Arc<PersistentDescriptorSet>
+}
+
+impl FluidCompute {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>
+    ) -> Self {
+        let pipeline = {
+            let device = queue.device();
+
+            let cs = fluid_cs::load(device.clone())
+                .unwrap()
+                .entry_point("main")
+                .unwrap();
+            let stage = PipelineShaderStageCreateInfo::new(cs);
+            let layout = PipelineLayout::new(
+                device.clone(),
+                PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                    .into_pipeline_layout_create_info(device.clone())
+                    .unwrap(),
+            ).unwrap();
+
+            ComputePipeline::new(
+                device.clone(),
+                None,
+                ComputePipelineCreateInfo::stage_layout(stage, layout)
+            )
+        }.unwrap();
+
+        Self {queue, pipeline, memory_allocator, command_buffer_allocator, descriptor_set_allocator, buffers: Mutex::new(None)}
+    }
+
+    fn allocate(&self, particles: usize, indices: usize) -> FluidBuffers {
+        let vectors = |count: usize| storage_buffer(&self.memory_allocator, vec![[0.0f32; 4]; count]);
+        let particles_buffer = vectors(2 * particles);
+        let offsets = storage_buffer(&self.memory_allocator, vec![0u32; particles + 1]);
+        let indices = storage_buffer(&self.memory_allocator, vec![0u32; indices]);
+        let accelerations = vectors(particles);
+        let external = vectors(particles);
+
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, particles_buffer.clone()),
+                WriteDescriptorSet::buffer(1, offsets.clone()),
+                WriteDescriptorSet::buffer(2, indices.clone()),
+                WriteDescriptorSet::buffer(3, accelerations.clone()),
+                WriteDescriptorSet::buffer(4, external.clone())
+            ],
+            []
+        ).unwrap();
+
+        FluidBuffers {particles: particles_buffer, offsets, indices, accelerations, external, set}
+    }
+}
+
+impl FluidBackend for FluidCompute {
+    fn simulate(&self, fluid: &mut Fluid, neighbours: &Neighbours, accelerations: &[Vector3<f64>], substeps: usize, delta: f64) {
+        let count = fluid.particles.len();
+        if count == 0 {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap();
+        let fits = buffers.as_ref()
+            .map(|b| b.accelerations.len() >= count as u64 && b.indices.len() >= neighbours.indices.len().max(1) as u64)
+            .unwrap_or(false);
+        if !fits {
+            *buffers = Some(self.allocate(count.next_power_of_two(), neighbours.indices.len().max(1).next_power_of_two()));
+        }
+        let buffers = buffers.as_ref().unwrap();
+
+        // Position and density, velocity and pressure
+        {
+            let mut particles = buffers.particles.write().unwrap();
+            let mut external = buffers.external.write().unwrap();
+
+            for (i, (p, a)) in fluid.particles.iter().zip(accelerations.iter()).enumerate() {
+                particles[2 * i] = [p.position.x as f32, p.position.y as f32, p.position.z as f32, 0.0];
+                particles[2 * i + 1] = [p.velocity.x as f32, p.velocity.y as f32, p.velocity.z as f32, 0.0];
+                external[i] = [a.x as f32, a.y as f32, a.z as f32, 0.0];
+            }
+        }
+        {
+            let mut offsets = buffers.offsets.write().unwrap();
+            for (slot, offset) in offsets.iter_mut().zip(neighbours.offsets.iter()) {
+                *slot = *offset as u32;
+            }
+
+            let mut indices = buffers.indices.write().unwrap();
+            for (slot, index) in indices.iter_mut().zip(neighbours.indices.iter()) {
+                *slot = *index as u32;
+            }
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        ).unwrap();
+
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline.layout().clone(), 0, buffers.set.clone())
+            .unwrap();
+
+        // Densities have to be complete before any force is summed, and forces before anything moves
+        let settings = &fluid.settings;
+        for _ in 0..substeps.max(1) {
+            for pass in 0..3 {
+                let params = fluid_cs::Params {
+                    count: count as u32,
+                    pass: pass as u32,
+                    h: settings.smoothing_radius as f32,
+                    mass: settings.particle_mass as f32,
+                    rest_density: settings.rest_density as f32,
+                    stiffness: settings.stiffness as f32,
+                    viscosity: settings.viscosity as f32,
+                    surface_tension: settings.surface_tension as f32,
+                    surface_threshold: settings.surface_threshold as f32,
+                    dt: delta as f32
+                };
+
+                builder
+                    .push_constants(self.pipeline.layout().clone(), 0, params)
+                    .unwrap()
+                    .dispatch([(count as u32 + 63) / 64, 1, 1])
+                    .unwrap();
+            }
+        }
+
+        let command_buffer = builder.build().unwrap();
+        command_buffer.execute(self.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let particles = buffers.particles.read().unwrap();
+        for (i, particle) in fluid.particles.iter_mut().enumerate() {
+            let (p, v) = (particles[2 * i], particles[2 * i + 1]);
+            particle.position = Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64);
+            particle.velocity = Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64);
+        }
+
+        let densities: Vec<f64> = particles.iter().take(2 * count).step_by(2).map(|p| p[3] as f64).collect();
+        let accelerations: Vec<Vector3<f64>> = buffers.accelerations.read().unwrap().iter()
+            .take(count)
+            .map(|a| Vector3::new(a[0] as f64, a[1] as f64, a[2] as f64))
+            .collect();
+
+        fluid.set_accelerations(&densities, &accelerations);
+    }
 }
 
 pub struct PhysicsPipeline {
@@ -182,10 +635,148 @@ impl DrawingPipeline {
     }
 }
 
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct DebugVertex {
+    #[format(R32G32B32A32_SFLOAT)]
+    position: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4]
+}
+
+/// Physics debug lines over the frame, see `Feature::PhysicsDebug`.
+pub struct DebugDrawPipeline {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    subpass: Subpass,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>
+}
+
+impl DebugDrawPipeline {
+    pub fn new(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>
+    ) -> Self {
+        let device = gfx_queue.device();
+
+        let vs = debug_vs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let fs = debug_fs::load(device.clone())
+            .expect("failed to create shader module")
+            .entry_point("main")
+            .expect("shader entry point not found");
+        let vertex_input_state = DebugVertex::per_vertex()
+            .definition(&vs.info().input_interface)
+            .unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        ).unwrap();
+
+        let pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState {
+                    topology: PrimitiveTopology::LineList,
+                    ..Default::default()
+                }),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState::default(),
+                )),
+                dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            }
+        ).unwrap();
+
+        Self {
+            gfx_queue,
+            pipeline,
+            subpass,
+            memory_allocator,
+            command_buffer_allocator
+        }
+    }
+
+    /// Draws `lines` in world space, `view_projection` is column major.
+    pub fn draw(&self, viewport_dimensions: [u32; 2], lines: &[DebugLine], view_projection: [[f32; 4]; 4]) -> Arc<SecondaryAutoCommandBuffer<Arc<StandardCommandBufferAllocator>>> {
+        let inheritance_info = CommandBufferInheritanceInfo {
+            render_pass: Some(self.subpass.clone().into()),
+            ..Default::default()
+        };
+
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            &self.command_buffer_allocator.clone(),
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+            inheritance_info
+        ).unwrap();
+
+        let vertex = |p: Vector3<f64>, color: [f32; 4]| DebugVertex {position: [p.x as f32, p.y as f32, p.z as f32, 1.0], color};
+        let vertices: Vec<DebugVertex> = lines.iter()
+            .flat_map(|l| [vertex(l.start, l.color), vertex(l.end, l.color)])
+            .collect();
+        let vertex_count = vertices.len() as u32;
+
+        let vertex_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices
+        ).unwrap();
+
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [viewport_dimensions[0] as f32, viewport_dimensions[1] as f32],
+                    depth_range: 0.0..=1.0,
+                }].into_iter().collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, debug_vs::PushConstants {view_projection})
+            .unwrap()
+            .bind_vertex_buffers(0, vertex_buffer)
+            .unwrap()
+            .draw(vertex_count, 1, 0, 0)
+            .unwrap();
+
+        builder.build().unwrap()
+    }
+}
+
 pub struct PlaceOverFrame {
     gfx_queue: Arc<Queue>,
     render_pass: Arc<RenderPass>,
     drawing_pipeline: DrawingPipeline,
+    debug_pipeline: DebugDrawPipeline,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
 }
 
@@ -195,6 +786,7 @@ impl PlaceOverFrame {
     pub fn new(
         gfx_queue: Arc<Queue>,
         output_format: Format,
+        memory_allocator: Arc<StandardMemoryAllocator>,
         command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
         descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>
     ) -> Self {
@@ -218,15 +810,23 @@ impl PlaceOverFrame {
 
         let drawing_pipeline = DrawingPipeline::new(
             gfx_queue.clone(),
-            subpass,
+            subpass.clone(),
             command_buffer_allocator.clone(),
             descriptor_set_allocator.clone()
         );
 
+        let debug_pipeline = DebugDrawPipeline::new(
+            gfx_queue.clone(),
+            subpass,
+            memory_allocator,
+            command_buffer_allocator.clone()
+        );
+
         Self {
             gfx_queue,
             render_pass,
             drawing_pipeline,
+            debug_pipeline,
             command_buffer_allocator
         }
     }
@@ -237,6 +837,8 @@ impl PlaceOverFrame {
         clear_color: [f32; 4],
         view: Arc<ImageView>,
         target: Arc<ImageView>,
+        debug_lines: &[DebugLine],
+        view_projection: [[f32; 4]; 4]
     ) -> Box<dyn GpuFuture>
         where
             F: GpuFuture + 'static,
@@ -281,6 +883,12 @@ impl PlaceOverFrame {
         // Execute above commands (subpass).
         command_buffer_builder.execute_commands(cb).unwrap();
 
+        // Debug lines go on top of the image
+        if !debug_lines.is_empty() {
+            let cb = self.debug_pipeline.draw(img_dims, debug_lines, view_projection);
+            command_buffer_builder.execute_commands(cb).unwrap();
+        }
+
         // End render pass.
         command_buffer_builder
             .end_render_pass(Default::default())
@@ -326,6 +934,48 @@ mod fs {
     }
 }
 
+mod debug_vs {
+    use vulkano_shaders::shader;
+    shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec4 position;
+            layout(location = 1) in vec4 color;
+
+            layout(location = 0) out vec4 out_color;
+
+            layout(push_constant) uniform PushConstants {
+                mat4 view_projection;
+            };
+
+            void main() {
+                gl_Position = view_projection * position;
+                out_color = color;
+            }
+        "
+    }
+}
+
+mod debug_fs {
+    use vulkano_shaders::shader;
+    shader! {
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec4 in_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = in_color;
+            }
+        "
+    }
+}
+
 mod cs {
     use vulkano_shaders::shader;
     shader! {
@@ -333,9 +983,246 @@ mod cs {
         src: r"
             #version 460
 
+            layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 velocity;
+                vec4 color_start;
+                vec4 color_end;
+                vec4 color;
+            };
+
+            layout(set = 0, binding = 0) buffer Particles { Particle data[]; } particles;
+
+            layout(push_constant) uniform Params {
+                vec4 gravity;
+                float delta;
+                float drag;
+                uint count;
+            } params;
+
+            // Same as ParticleData::update
             void main() {
+                uint i = gl_GlobalInvocationID.x;
+                if (i >= params.count) {
+                    return;
+                }
+
+                Particle p = particles.data[i];
+                if (p.position.w >= p.velocity.w) {
+                    return;
+                }
 
+                float keep = max(1.0 - params.drag * params.delta, 0.0);
+                p.velocity.xyz = (p.velocity.xyz + params.gravity.xyz * params.delta) * keep;
+                p.position.xyz += p.velocity.xyz * params.delta;
+                p.position.w += params.delta;
+
+                float t = clamp(p.position.w / p.velocity.w, 0.0, 1.0);
+                p.color = mix(p.color_start, p.color_end, t);
+
+                particles.data[i] = p;
             }
         ",
     }
-}
\ No newline at end of file
+}
+
+mod soft_cs {
+    use vulkano_shaders::shader;
+    shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+            // Position and inverse mass
+            layout(set = 0, binding = 0) readonly buffer Input { vec4 data[]; } src;
+            layout(set = 0, binding = 1) writeonly buffer Output { vec4 data[]; } dst;
+            layout(set = 0, binding = 2) readonly buffer Offsets { uint data[]; } offsets;
+            layout(set = 0, binding = 3) readonly buffer Links { vec4 data[]; } links;
+            layout(set = 0, binding = 4) buffer Previous { vec4 data[]; } previous;
+            layout(set = 0, binding = 5) buffer Velocities { vec4 data[]; } velocities;
+            layout(set = 0, binding = 6) readonly buffer Accelerations { vec4 data[]; } accelerations;
+
+            // Pass 0 predicts, 1 solves the distances, 2 derives the velocities
+            layout(push_constant) uniform Params {
+                uint count;
+                uint pass;
+                float dt;
+                float keep;
+            } params;
+
+            void main() {
+                uint i = gl_GlobalInvocationID.x;
+                if (i >= params.count) {
+                    return;
+                }
+
+                vec4 p = src.data[i];
+
+                if (params.pass == 0) {
+                    previous.data[i] = p;
+                    if (p.w > 0.0) {
+                        vec3 v = (velocities.data[i].xyz + accelerations.data[i].xyz * params.dt) * params.keep;
+                        velocities.data[i].xyz = v;
+                        p.xyz += v * params.dt;
+                    }
+                } else if (params.pass == 1) {
+                    uint begin = offsets.data[i];
+                    uint end = offsets.data[i + 1];
+
+                    vec3 correction = vec3(0.0);
+                    for (uint k = begin; k < end; k++) {
+                        vec4 link = links.data[k];
+                        vec4 q = src.data[floatBitsToUint(link.x)];
+
+                        float w = p.w + q.w;
+                        vec3 d = p.xyz - q.xyz;
+                        float len = length(d);
+                        if (w == 0.0 || len < 1e-9) {
+                            continue;
+                        }
+
+                        float alpha = link.z / (params.dt * params.dt);
+                        float dlambda = -(len - link.y) / (w + alpha);
+                        correction += d / len * (p.w * dlambda);
+                    }
+
+                    // Averaged, plain sums overshoot on particles with many constraints
+                    if (end > begin) {
+                        p.xyz += correction / float(end - begin);
+                    }
+                } else {
+                    vec3 v = p.w > 0.0 ? (p.xyz - previous.data[i].xyz) / params.dt : vec3(0.0);
+                    velocities.data[i].xyz = v;
+                }
+
+                dst.data[i] = p;
+            }
+        ",
+    }
+}
+
+mod fluid_cs {
+    use vulkano_shaders::shader;
+    shader! {
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+            // Two vec4 per particle: position and density, velocity and pressure
+            layout(set = 0, binding = 0) buffer Particles { vec4 data[]; } particles;
+            layout(set = 0, binding = 1) readonly buffer Offsets { uint data[]; } offsets;
+            layout(set = 0, binding = 2) readonly buffer Indices { uint data[]; } indices;
+            layout(set = 0, binding = 3) buffer Accelerations { vec4 data[]; } accelerations;
+            layout(set = 0, binding = 4) readonly buffer External { vec4 data[]; } external;
+
+            // Pass 0 sums densities, 1 forces, 2 integrates
+
+            layout(push_constant) uniform Params {
+                uint count;
+                uint pass;
+                float h;
+                float mass;
+                float rest_density;
+                float stiffness;
+                float viscosity;
+                float surface_tension;
+                float surface_threshold;
+                float dt;
+            } params;
+
+            const float PI = 3.14159265358979;
+
+            void main() {
+                uint i = gl_GlobalInvocationID.x;
+                if (i >= params.count) {
+                    return;
+                }
+
+                float h = params.h;
+                float h2 = h * h;
+                float h6 = h2 * h2 * h2;
+                float h9 = h6 * h2 * h;
+                float m = params.mass;
+
+                vec4 a = particles.data[2 * i];
+                vec4 va = particles.data[2 * i + 1];
+                uint begin = offsets.data[i];
+                uint end = offsets.data[i + 1];
+
+                // Symplectic Euler
+                if (params.pass == 2) {
+                    va.xyz += (accelerations.data[i].xyz + external.data[i].xyz) * params.dt;
+                    a.xyz += va.xyz * params.dt;
+                    particles.data[2 * i] = a;
+                    particles.data[2 * i + 1] = va;
+                    return;
+                }
+
+                // The lists reach further than the kernel, pairs out of range are skipped
+                if (params.pass == 0) {
+                    float density = 0.0;
+                    for (uint k = begin; k < end; k++) {
+                        vec3 d = a.xyz - particles.data[2 * indices.data[k]].xyz;
+                        float x = h2 - dot(d, d);
+                        if (x <= 0.0) {
+                            continue;
+                        }
+                        density += m * 315.0 / (64.0 * PI * h9) * x * x * x;
+                    }
+
+                    particles.data[2 * i].w = density;
+                    particles.data[2 * i + 1].w = max(params.stiffness * (density - params.rest_density), 0.0);
+                    return;
+                }
+
+                vec3 pressure = vec3(0.0);
+                vec3 viscosity = vec3(0.0);
+                vec3 normal = vec3(0.0);
+                float curvature = 0.0;
+
+                for (uint k = begin; k < end; k++) {
+                    uint j = indices.data[k];
+                    vec4 b = particles.data[2 * j];
+                    vec4 vb = particles.data[2 * j + 1];
+                    if (j == i || b.w <= 0.0) {
+                        continue;
+                    }
+
+                    vec3 d = a.xyz - b.xyz;
+                    float r = length(d);
+                    float r2 = r * r;
+                    if (r >= h) {
+                        continue;
+                    }
+
+                    if (r > 1e-9) {
+                        vec3 grad = d / r * (-45.0 / (PI * h6) * (h - r) * (h - r));
+                        pressure -= grad * (m * (va.w + vb.w) / (2.0 * b.w));
+                    }
+
+                    viscosity += (vb.xyz - va.xyz) * (m / b.w * 45.0 / (PI * h6) * (h - r));
+
+                    float x = h2 - r2;
+                    float poly6_grad = -945.0 / (32.0 * PI * h9);
+                    normal += d * (m / b.w * poly6_grad * x * x);
+                    curvature += m / b.w * poly6_grad * x * (3.0 * h2 - 7.0 * r2);
+                }
+
+                vec3 force = pressure + viscosity * params.viscosity;
+
+                float len = length(normal);
+                if (len > params.surface_threshold) {
+                    force -= normal / len * (params.surface_tension * curvature);
+                }
+
+                accelerations.data[i] = vec4(a.w > 0.0 ? force / a.w : vec3(0.0), 0.0);
+            }
+        ",
+    }
+}
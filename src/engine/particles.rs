@@ -0,0 +1,355 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::physics::rng::Rng;
+
+// Visual only, particles are not part of snapshots or the state hash
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EmitterHandle(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParticleMode {
+    // Simulated in `ParticleSystem::update`, used headless
+    Cpu,
+    // Simulated by the `EnginePipeline` compute shader every frame
+    Gpu
+}
+
+/// Layout shared with the compute shader, five vec4 per particle.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParticleData {
+    // xyz, age
+    pub position: [f32; 4],
+    // xyz, lifetime
+    pub velocity: [f32; 4],
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+    pub color: [f32; 4]
+}
+
+impl ParticleData {
+    pub fn is_alive(&self) -> bool {
+        self.position[3] < self.velocity[3]
+    }
+
+    pub fn to_vec4(&self) -> [[f32; 4]; 5] {
+        [self.position, self.velocity, self.color_start, self.color_end, self.color]
+    }
+
+    // Same as the compute shader
+    fn update(&mut self, gravity: Vector3<f64>, drag: f64, delta: f64) {
+        if !self.is_alive() {
+            return;
+        }
+
+        let dt = delta as f32;
+        let keep = (1.0 - drag * delta).max(0.0) as f32;
+        let gravity = [gravity.x as f32, gravity.y as f32, gravity.z as f32];
+
+        for k in 0..3 {
+            self.velocity[k] = (self.velocity[k] + gravity[k] * dt) * keep;
+            self.position[k] += self.velocity[k] * dt;
+        }
+        self.position[3] += dt;
+
+        let t = (self.position[3] / self.velocity[3]).clamp(0.0, 1.0);
+        for k in 0..4 {
+            self.color[k] = self.color_start[k] + (self.color_end[k] - self.color_start[k]) * t;
+        }
+    }
+}
+
+pub struct Emitter {
+    // World position, or offset from the object when attached to one
+    pub position: Vector3<f64>,
+    pub object: Option<usize>,
+    pub direction: Vector3<f64>,
+    // Half angle of the velocity cone, in radians
+    pub spread: f64,
+    pub speed: (f64, f64),
+    // Particles per second
+    pub rate: f64,
+    pub lifetime: (f64, f64),
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+    pub enabled: bool,
+
+    // Private
+    origin: Vector3<f64>,
+    accumulator: f64
+}
+
+impl Emitter {
+    pub fn new(position: Vector3<f64>, rate: f64) -> Self {
+        Self {
+            position,
+            object: None,
+            direction: Vector3::unit_y(),
+            spread: 0.3,
+            speed: (1.0, 2.0),
+            rate,
+            lifetime: (1.0, 2.0),
+            color_start: [1.0, 1.0, 1.0, 1.0],
+            color_end: [1.0, 1.0, 1.0, 0.0],
+            enabled: true,
+            origin: position,
+            accumulator: 0.0
+        }
+    }
+
+    /// Follows a world object, `position` becomes the offset from it.
+    pub fn on_object(mut self, object: usize) -> Self {
+        self.object = Some(object);
+        self
+    }
+
+    pub fn with_cone(mut self, direction: Vector3<f64>, spread: f64, speed: (f64, f64)) -> Self {
+        self.direction = direction.normalize();
+        self.spread = spread;
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_lifetime(mut self, min: f64, max: f64) -> Self {
+        self.lifetime = (min, max);
+        self
+    }
+
+    pub fn with_colors(mut self, start: [f32; 4], end: [f32; 4]) -> Self {
+        self.color_start = start;
+        self.color_end = end;
+        self
+    }
+
+    fn random_direction(&self, rng: &mut Rng) -> Vector3<f64> {
+        // Uniform over the spherical cap around the direction
+        let cos_theta = rng.range(self.spread.cos(), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.range(0.0, 2.0 * std::f64::consts::PI);
+
+        let axis = self.direction;
+        let helper = if axis.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let u = axis.cross(helper).normalize();
+        let v = axis.cross(u);
+
+        axis * cos_theta + (u * phi.cos() + v * phi.sin()) * sin_theta
+    }
+
+    fn spawn(&self, rng: &mut Rng) -> ParticleData {
+        let velocity = self.random_direction(rng) * rng.range(self.speed.0, self.speed.1);
+        let lifetime = rng.range(self.lifetime.0, self.lifetime.1);
+        let p = self.origin;
+
+        ParticleData {
+            position: [p.x as f32, p.y as f32, p.z as f32, 0.0],
+            velocity: [velocity.x as f32, velocity.y as f32, velocity.z as f32, lifetime as f32],
+            color_start: self.color_start,
+            color_end: self.color_end,
+            color: self.color_start
+        }
+    }
+}
+
+pub struct ParticleSystem {
+    pub gravity: Vector3<f64>,
+    // Fraction of velocity lost per second
+    pub drag: f64,
+
+    mode: ParticleMode,
+    emitters: Vec<Option<Emitter>>,
+    // Used by the CPU path only
+    particles: Vec<ParticleData>,
+    // Slot bookkeeping for both paths
+    expires: Vec<f64>,
+    cursor: usize,
+    time: f64,
+    rng: Rng,
+
+    // Waiting for the GPU
+    spawned: Vec<(usize, ParticleData)>,
+    elapsed: f64
+}
+
+impl ParticleSystem {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            drag: 0.1,
+            mode: ParticleMode::Cpu,
+            emitters: Vec::new(),
+            particles: vec![ParticleData::default(); capacity],
+            expires: vec![0.0; capacity],
+            cursor: 0,
+            time: 0.0,
+            rng: Rng::new(0),
+            spawned: Vec::new(),
+            elapsed: 0.0
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.expires.len()
+    }
+
+    pub fn mode(&self) -> ParticleMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ParticleMode) {
+        self.mode = mode;
+        self.spawned.clear();
+        self.elapsed = 0.0;
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> EmitterHandle {
+        self.emitters.push(Some(emitter));
+        EmitterHandle(self.emitters.len() - 1)
+    }
+
+    pub fn remove_emitter(&mut self, handle: EmitterHandle) {
+        if let Some(slot) = self.emitters.get_mut(handle.0) {
+            *slot = None;
+        }
+    }
+
+    pub fn get_emitter_mut(&mut self, handle: EmitterHandle) -> Option<&mut Emitter> {
+        self.emitters.get_mut(handle.0).and_then(|e| e.as_mut())
+    }
+
+    pub fn alive(&self) -> usize {
+        self.expires.iter().filter(|t| **t > self.time).count()
+    }
+
+    /// Particle state of the CPU path, empty slots are dead particles.
+    pub fn particles(&self) -> &Vec<ParticleData> {
+        &self.particles
+    }
+
+    // Ring search for an expired slot, None when the system is full
+    fn free_slot(&mut self) -> Option<usize> {
+        let capacity = self.capacity();
+        for i in 0..capacity {
+            let slot = (self.cursor + i) % capacity;
+            if self.expires[slot] <= self.time {
+                self.cursor = (slot + 1) % capacity;
+                return Some(slot);
+            }
+        }
+
+        None
+    }
+
+    /// `origin` maps an object index to its position, for attached emitters.
+    pub fn update(&mut self, delta: f64, origin: impl Fn(usize) -> Option<Vector3<f64>>) {
+        self.time += delta;
+
+        for i in 0..self.emitters.len() {
+            let emitter = match self.emitters[i].as_mut() {
+                Some(emitter) if emitter.enabled => emitter,
+                _ => continue
+            };
+
+            emitter.origin = match emitter.object {
+                Some(object) => origin(object).unwrap_or(Vector3::zero()) + emitter.position,
+                None => emitter.position
+            };
+
+            emitter.accumulator += emitter.rate * delta;
+            let count = emitter.accumulator.floor();
+            emitter.accumulator -= count;
+
+            for _ in 0..count as usize {
+                let slot = match self.free_slot() {
+                    Some(slot) => slot,
+                    None => break
+                };
+
+                let emitter = self.emitters[i].as_ref().unwrap();
+                let particle = emitter.spawn(&mut self.rng);
+                self.expires[slot] = self.time + particle.velocity[3] as f64;
+
+                match self.mode {
+                    ParticleMode::Cpu => self.particles[slot] = particle,
+                    ParticleMode::Gpu => self.spawned.push((slot, particle))
+                }
+            }
+        }
+
+        match self.mode {
+            ParticleMode::Cpu => {
+                for particle in self.particles.iter_mut() {
+                    particle.update(self.gravity, self.drag, delta);
+                }
+            },
+            ParticleMode::Gpu => self.elapsed += delta
+        }
+    }
+
+    /// Particles spawned since the last GPU update, with their slots.
+    pub fn take_spawned(&mut self) -> Vec<(usize, ParticleData)> {
+        std::mem::take(&mut self.spawned)
+    }
+
+    /// Simulated time since the last GPU update.
+    pub fn take_elapsed(&mut self) -> f64 {
+        std::mem::take(&mut self.elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+    use super::{Emitter, ParticleSystem};
+
+    #[test]
+    fn particles_spawn_at_the_emitter_rate() {
+        let mut particles = ParticleSystem::new(100);
+        particles.add_emitter(Emitter::new(Vector3::zero(), 30.0).with_lifetime(10.0, 10.0));
+
+        for _ in 0..10 {
+            particles.update(0.1, |_| None);
+        }
+        assert_eq!(particles.alive(), 30);
+
+        // Full systems drop new particles instead of replacing live ones
+        let mut full = ParticleSystem::new(20);
+        full.add_emitter(Emitter::new(Vector3::zero(), 30.0).with_lifetime(10.0, 10.0));
+        for _ in 0..10 {
+            full.update(0.1, |_| None);
+        }
+        assert_eq!(full.alive(), 20);
+    }
+
+    #[test]
+    fn particles_expire_after_their_lifetime() {
+        let mut particles = ParticleSystem::new(10);
+        let emitter = particles.add_emitter(Emitter::new(Vector3::zero(), 10.0).with_lifetime(0.5, 0.5));
+        particles.update(0.1, |_| None);
+        particles.get_emitter_mut(emitter).unwrap().enabled = false;
+        assert_eq!(particles.alive(), 1);
+
+        particles.update(0.3, |_| None);
+        assert_eq!(particles.alive(), 1);
+        assert_eq!(particles.particles().iter().filter(|p| p.is_alive()).count(), 1);
+
+        particles.update(0.2, |_| None);
+        assert_eq!(particles.alive(), 0);
+        assert!(particles.particles().iter().all(|p| !p.is_alive()));
+    }
+
+    #[test]
+    fn particle_color_blends_over_its_life() {
+        let mut particles = ParticleSystem::new(10);
+        let emitter = particles.add_emitter(Emitter::new(Vector3::zero(), 4.0)
+            .with_lifetime(1.0, 1.0)
+            .with_colors([1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0]));
+
+        // Spawned and aged by the same update
+        particles.update(0.25, |_| None);
+        particles.get_emitter_mut(emitter).unwrap().enabled = false;
+        particles.update(0.25, |_| None);
+
+        let particle = particles.particles().iter().find(|p| p.is_alive()).unwrap();
+        let expected = [0.5, 0.0, 0.5, 0.5];
+        assert!(particle.color.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1.0e-6), "color {:?}", particle.color);
+    }
+}
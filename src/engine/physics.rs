@@ -0,0 +1,951 @@
+use std::collections::HashSet;
+use std::time::Instant;
+use cgmath::{InnerSpace, Quaternion, Vector3};
+
+pub mod body;
+pub mod collider;
+pub mod contact;
+pub mod joint;
+pub mod island;
+pub mod force;
+pub mod rng;
+pub mod determinism;
+pub mod softbody;
+pub mod fluid;
+pub mod nbody;
+pub mod query;
+pub mod character;
+pub mod mesh;
+pub mod vehicle;
+pub mod fracture;
+pub mod debug;
+pub mod profiler;
+
+use body::{BodyHandle, RigidBody};
+use collider::{Aabb, ContactPoint, Shape, compute_aabb, collide};
+use contact::{Contact, ContactSettings};
+use joint::{Joint, JointHandle, JointMotor, JointState};
+use island::Island;
+use force::{ForceHandle, ForceType, Gravity};
+use rng::Rng;
+use determinism::{FloatMode, StateHasher, StepMode};
+use softbody::{SoftBackendType, SoftBody, SoftBodyHandle};
+use fluid::{Fluid, FluidBackendType, FluidHandle};
+use nbody::{ForceModel, NBodyStats};
+use query::RayHit;
+use vehicle::{Vehicle, VehicleHandle};
+use fracture::{Destructible, DestructibleHandle, FractureEvent};
+use profiler::StepTimings;
+
+pub struct PhysicsSettings {
+    pub step_mode: StepMode,
+    pub float_mode: FloatMode,
+    pub force_model: ForceModel,
+    pub solver_iterations: usize,
+    pub joint_bias: f64,
+    pub contact: ContactSettings,
+    // Sweeps fast bodies against the static geometry
+    pub ccd_enabled: bool,
+
+    // Sleeping
+    pub sleep_enabled: bool,
+    pub sleep_linear_threshold: f64,
+    pub sleep_angular_threshold: f64,
+    pub time_to_sleep: f64
+}
+
+impl PhysicsSettings {
+    pub fn new() -> Self {
+        Self {
+            step_mode: StepMode::Variable,
+            float_mode: FloatMode::Native,
+            force_model: ForceModel::Generators,
+            solver_iterations: 10,
+            joint_bias: 0.2,
+            contact: ContactSettings::new(),
+            ccd_enabled: true,
+            sleep_enabled: true,
+            sleep_linear_threshold: 0.05,
+            sleep_angular_threshold: 0.05,
+            time_to_sleep: 0.5
+        }
+    }
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PhysicsStats {
+    pub tick: u64,
+    // Length of the step in seconds
+    pub delta: f64,
+    pub bodies: usize,
+    pub awake_bodies: usize,
+    pub sleeping_bodies: usize,
+    pub islands: usize,
+    pub sleeping_islands: usize,
+    pub pairs: usize,
+    pub contacts: usize,
+    pub timings: StepTimings
+}
+
+// Everything needed to continue the simulation from a tick
+#[derive(Clone)]
+pub struct PhysicsSnapshot {
+    bodies: Vec<RigidBody>,
+    joints: Vec<Joint>,
+    soft_bodies: Vec<SoftBody>,
+    fluids: Vec<Fluid>,
+    vehicles: Vec<Vehicle>,
+    destructibles: Vec<Destructible>,
+    // Generator slot, its serial and its state
+    forces: Vec<(ForceHandle, u64, Vec<f64>)>,
+    rng: Rng,
+    accumulator: f64,
+    ticks: u64
+}
+
+impl PhysicsSnapshot {
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+pub struct PhysicsWorld {
+    pub settings: PhysicsSettings,
+
+    // Private
+    bodies: Vec<RigidBody>,
+    joints: Vec<Joint>,
+    contacts: Vec<Contact>,
+    islands: Vec<Island>,
+    soft_bodies: Vec<SoftBody>,
+    soft_backend: Option<SoftBackendType>,
+    fluids: Vec<Fluid>,
+    fluid_backend: Option<FluidBackendType>,
+    vehicles: Vec<Vehicle>,
+    destructibles: Vec<Destructible>,
+    fracture_events: Vec<FractureEvent>,
+    forces: Vec<Option<ForceType>>,
+    // Unique per added generator, slots restart after `clear_forces`
+    force_serials: Vec<u64>,
+    next_force_serial: u64,
+    rng: Rng,
+    stats: PhysicsStats,
+    step_stats: Vec<PhysicsStats>,
+    nbody_stats: NBodyStats,
+
+    accumulator: f64,
+    ticks: u64
+}
+
+impl PhysicsWorld {
+    pub fn new() -> Self {
+        let mut physics = Self {
+            settings: PhysicsSettings::new(),
+            bodies: Vec::new(),
+            joints: Vec::new(),
+            contacts: Vec::new(),
+            islands: Vec::new(),
+            soft_bodies: Vec::new(),
+            soft_backend: None,
+            fluids: Vec::new(),
+            fluid_backend: None,
+            vehicles: Vec::new(),
+            destructibles: Vec::new(),
+            fracture_events: Vec::new(),
+            forces: Vec::new(),
+            force_serials: Vec::new(),
+            next_force_serial: 0,
+            rng: Rng::new(0),
+            stats: PhysicsStats::default(),
+            step_stats: Vec::new(),
+            nbody_stats: NBodyStats::new(),
+            accumulator: 0.0,
+            ticks: 0
+        };
+
+        physics.add_force(Gravity::new(Vector3::new(0.0, -9.81, 0.0)).build());
+        physics
+    }
+
+    pub fn add_body(&mut self, body: RigidBody) -> BodyHandle {
+        self.bodies.push(body);
+        BodyHandle(self.bodies.len() - 1)
+    }
+
+    pub fn get_body(&self, handle: BodyHandle) -> Option<&RigidBody> {
+        self.bodies.get(handle.0)
+    }
+
+    pub fn get_body_mut(&mut self, handle: BodyHandle) -> Option<&mut RigidBody> {
+        self.bodies.get_mut(handle.0)
+    }
+
+    pub fn bodies(&self) -> &Vec<RigidBody> {
+        &self.bodies
+    }
+
+    /// Closest body hit by the ray within `max_distance`.
+    pub fn raycast(&self, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<RayHit> {
+        query::raycast(&self.bodies, origin, direction, max_distance, |_| true)
+    }
+
+    /// Contacts of a body that is not in the world, e.g. a trigger volume or a character probe.
+    pub fn overlap(&self, probe: &RigidBody) -> Vec<(BodyHandle, ContactPoint)> {
+        query::overlap(&self.bodies, probe, |_| true)
+    }
+
+    /// None when the joint refers to a body that does not exist.
+    pub fn add_joint(&mut self, mut joint: Joint) -> Option<JointHandle> {
+        let count = self.bodies.len();
+        if joint.body_a.0 >= count || joint.body_b.is_some_and(|b| b.0 >= count) {
+            return None;
+        }
+
+        joint.capture_rest_rotation(&self.bodies);
+
+        // Joined bodies must simulate together
+        self.bodies[joint.body_a.0].wake_up();
+        if let Some(b) = joint.body_b {
+            self.bodies[b.0].wake_up();
+        }
+
+        self.joints.push(joint);
+        Some(JointHandle(self.joints.len() - 1))
+    }
+
+    pub fn get_joint(&self, handle: JointHandle) -> Option<&Joint> {
+        self.joints.get(handle.0)
+    }
+
+    pub fn get_joint_mut(&mut self, handle: JointHandle) -> Option<&mut Joint> {
+        self.joints.get_mut(handle.0)
+    }
+
+    pub fn joints(&self) -> &Vec<Joint> {
+        &self.joints
+    }
+
+    /// Replaces the motor of a joint, waking its bodies.
+    pub fn set_joint_motor(&mut self, handle: JointHandle, motor: Option<JointMotor>) {
+        if let Some(joint) = self.joints.get_mut(handle.0) {
+            joint.motor = motor;
+            self.wake_joint(handle);
+        }
+    }
+
+    /// Moves the target of a position or PD motor, e.g. from `Object::on_physics_update`.
+    pub fn set_joint_target(&mut self, handle: JointHandle, target: f64) {
+        if let Some(motor) = self.joints.get_mut(handle.0).and_then(|j| j.motor.as_mut()) {
+            motor.set_target(target);
+            self.wake_joint(handle);
+        }
+    }
+
+    /// Angle or offset, speed and motor force of a hinge or prismatic joint.
+    pub fn joint_state(&self, handle: JointHandle) -> Option<JointState> {
+        let joint = self.joints.get(handle.0)?;
+        Some(JointState {
+            position: joint.position(&self.bodies)?,
+            speed: joint.speed(&self.bodies)?,
+            motor_force: joint.motor_force()
+        })
+    }
+
+    fn wake_joint(&mut self, handle: JointHandle) {
+        let joint = &self.joints[handle.0];
+        self.bodies[joint.body_a.0].wake_up();
+        if let Some(b) = joint.body_b {
+            self.bodies[b.0].wake_up();
+        }
+    }
+
+    pub fn add_soft_body(&mut self, body: SoftBody) -> SoftBodyHandle {
+        self.soft_bodies.push(body);
+        SoftBodyHandle(self.soft_bodies.len() - 1)
+    }
+
+    pub fn get_soft_body(&self, handle: SoftBodyHandle) -> Option<&SoftBody> {
+        self.soft_bodies.get(handle.0)
+    }
+
+    pub fn get_soft_body_mut(&mut self, handle: SoftBodyHandle) -> Option<&mut SoftBody> {
+        self.soft_bodies.get_mut(handle.0)
+    }
+
+    pub fn soft_bodies(&self) -> &Vec<SoftBody> {
+        &self.soft_bodies
+    }
+
+    /// Solver used by soft bodies with `use_backend` set, e.g. the compute shader path.
+    pub fn set_soft_body_backend(&mut self, backend: Option<SoftBackendType>) {
+        self.soft_backend = backend;
+    }
+
+    pub fn add_fluid(&mut self, fluid: Fluid) -> FluidHandle {
+        self.fluids.push(fluid);
+        FluidHandle(self.fluids.len() - 1)
+    }
+
+    pub fn get_fluid(&self, handle: FluidHandle) -> Option<&Fluid> {
+        self.fluids.get(handle.0)
+    }
+
+    pub fn get_fluid_mut(&mut self, handle: FluidHandle) -> Option<&mut Fluid> {
+        self.fluids.get_mut(handle.0)
+    }
+
+    pub fn fluids(&self) -> &Vec<Fluid> {
+        &self.fluids
+    }
+
+    /// Density and force passes for fluids with `use_backend` set.
+    pub fn set_fluid_backend(&mut self, backend: Option<FluidBackendType>) {
+        self.fluid_backend = backend;
+    }
+
+    pub fn add_vehicle(&mut self, vehicle: Vehicle) -> VehicleHandle {
+        self.vehicles.push(vehicle);
+        VehicleHandle(self.vehicles.len() - 1)
+    }
+
+    pub fn get_vehicle(&self, handle: VehicleHandle) -> Option<&Vehicle> {
+        self.vehicles.get(handle.0)
+    }
+
+    pub fn get_vehicle_mut(&mut self, handle: VehicleHandle) -> Option<&mut Vehicle> {
+        self.vehicles.get_mut(handle.0)
+    }
+
+    pub fn vehicles(&self) -> &Vec<Vehicle> {
+        &self.vehicles
+    }
+
+    pub fn add_destructible(&mut self, destructible: Destructible) -> DestructibleHandle {
+        self.destructibles.push(destructible);
+        DestructibleHandle(self.destructibles.len() - 1)
+    }
+
+    pub fn get_destructible(&self, handle: DestructibleHandle) -> Option<&Destructible> {
+        self.destructibles.get(handle.0)
+    }
+
+    /// Bodies that broke during the last step.
+    pub fn fracture_events(&self) -> &Vec<FractureEvent> {
+        &self.fracture_events
+    }
+
+    pub fn add_force(&mut self, generator: ForceType) -> ForceHandle {
+        self.forces.push(Some(generator));
+        self.force_serials.push(self.next_force_serial);
+        self.next_force_serial += 1;
+        ForceHandle(self.forces.len() - 1)
+    }
+
+    pub fn remove_force(&mut self, handle: ForceHandle) -> Option<ForceType> {
+        self.forces.get_mut(handle.0).and_then(|f| f.take())
+    }
+
+    pub fn get_force_mut(&mut self, handle: ForceHandle) -> Option<&mut ForceType> {
+        self.forces.get_mut(handle.0).and_then(|f| f.as_mut())
+    }
+
+    /// Uniform acceleration of all the gravity generators.
+    pub fn gravity(&self) -> Vector3<f64> {
+        self.forces.iter().flatten().map(|f| f.gravity()).sum()
+    }
+
+    /// Removes every generator, including the default gravity.
+    pub fn clear_forces(&mut self) {
+        self.forces.clear();
+        self.force_serials.clear();
+    }
+
+    pub fn get_rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Fixed timestep, quantized state and a fresh random sequence. Two worlds built the
+    /// same way and stepped the same number of ticks end up with equal `state_hash`.
+    pub fn set_deterministic(&mut self, timestep: f64, seed: u64) {
+        self.settings.step_mode = StepMode::Fixed {timestep, max_substeps: 8};
+        self.settings.float_mode = FloatMode::Quantized {bits: 32};
+        self.accumulator = 0.0;
+        self.set_seed(seed);
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        self.hash_state(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(self.ticks);
+        hasher.write_u64(self.rng.state());
+        hasher.write_u64(self.bodies.len() as u64);
+
+        for body in self.bodies.iter() {
+            body.hash_state(hasher);
+        }
+
+        for soft in self.soft_bodies.iter() {
+            soft.hash_state(hasher);
+        }
+
+        for fluid in self.fluids.iter() {
+            fluid.hash_state(hasher);
+        }
+
+        for vehicle in self.vehicles.iter() {
+            vehicle.hash_state(hasher);
+        }
+
+        for destructible in self.destructibles.iter() {
+            hasher.write_bool(destructible.is_broken());
+        }
+    }
+
+    pub fn snapshot(&self) -> PhysicsSnapshot {
+        PhysicsSnapshot {
+            bodies: self.bodies.clone(),
+            joints: self.joints.clone(),
+            soft_bodies: self.soft_bodies.clone(),
+            fluids: self.fluids.clone(),
+            vehicles: self.vehicles.clone(),
+            destructibles: self.destructibles.clone(),
+            forces: self.forces.iter().zip(self.force_serials.iter()).enumerate()
+                .filter_map(|(i, (f, serial))| f.as_ref().map(|f| (ForceHandle(i), *serial, f.save_state())))
+                .collect(),
+            rng: self.rng.clone(),
+            accumulator: self.accumulator,
+            ticks: self.ticks
+        }
+    }
+
+    /// Contacts and islands are rebuilt by the next step.
+    pub fn restore(&mut self, snapshot: &PhysicsSnapshot) {
+        self.bodies = snapshot.bodies.clone();
+        self.joints = snapshot.joints.clone();
+        self.soft_bodies = snapshot.soft_bodies.clone();
+        self.fluids = snapshot.fluids.clone();
+        self.vehicles = snapshot.vehicles.clone();
+        self.destructibles = snapshot.destructibles.clone();
+        self.fracture_events.clear();
+        self.rng = snapshot.rng.clone();
+        self.accumulator = snapshot.accumulator;
+        self.ticks = snapshot.ticks;
+
+        // Generators added or removed since then keep their own state
+        for (handle, serial, state) in snapshot.forces.iter() {
+            if self.force_serials.get(handle.0) == Some(serial) {
+                if let Some(generator) = self.get_force_mut(*handle) {
+                    generator.load_state(state);
+                }
+            }
+        }
+
+        self.contacts.clear();
+        self.islands.clear();
+    }
+
+    pub fn contacts(&self) -> &Vec<Contact> {
+        &self.contacts
+    }
+
+    /// Average contact force on the body during the last step.
+    pub fn contact_force(&self, handle: BodyHandle) -> Vector3<f64> {
+        if self.stats.delta <= 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+
+        let impulse = self.contacts.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, c| {
+            if c.body_b == handle {
+                sum + c.total_impulse()
+            } else if c.body_a == handle {
+                sum - c.total_impulse()
+            } else {
+                sum
+            }
+        });
+
+        impulse / self.stats.delta
+    }
+
+    pub fn islands(&self) -> &Vec<Island> {
+        &self.islands
+    }
+
+    pub fn stats(&self) -> &PhysicsStats {
+        &self.stats
+    }
+
+    /// Statistics of the steps of the last `update` or `step` call.
+    pub fn step_stats(&self) -> &Vec<PhysicsStats> {
+        &self.step_stats
+    }
+
+    /// Energy and momentum of the n-body force model, measured after every step.
+    pub fn nbody_stats(&self) -> &NBodyStats {
+        &self.nbody_stats
+    }
+
+    pub fn reset_nbody_stats(&mut self) {
+        self.nbody_stats.reset_reference();
+    }
+
+    pub fn wake_up(&mut self, handle: BodyHandle) {
+        if let Some(body) = self.bodies.get_mut(handle.0) {
+            body.wake_up();
+        }
+    }
+
+    fn is_active(&self, index: usize) -> bool {
+        let body = &self.bodies[index];
+        (body.is_dynamic() && !body.is_sleeping()) || body.is_moving_kinematic()
+    }
+
+    // Islands never contain kinematic bodies, so whatever a moving one touches is woken here
+    fn wake_kinematic_contacts(&mut self) {
+        let mut touched: Vec<usize> = Vec::new();
+
+        for contact in self.contacts.iter() {
+            let (a, b) = (contact.body_a.0, contact.body_b.0);
+            if self.bodies[a].is_moving_kinematic() { touched.push(b); }
+            if self.bodies[b].is_moving_kinematic() { touched.push(a); }
+        }
+
+        for joint in self.joints.iter().filter(|j| j.enabled) {
+            if let Some(b) = joint.body_b {
+                if self.bodies[joint.body_a.0].is_moving_kinematic() { touched.push(b.0); }
+                if self.bodies[b.0].is_moving_kinematic() { touched.push(joint.body_a.0); }
+            }
+        }
+
+        for index in touched {
+            self.bodies[index].wake_up();
+        }
+    }
+
+    // Sort and sweep along X
+    fn broadphase(&self) -> Vec<(usize, usize)> {
+        let mut entries: Vec<(usize, Aabb)> = self.bodies.iter()
+            .enumerate()
+            .filter_map(|(i, body)| compute_aabb(body).map(|aabb| (i, aabb)))
+            .collect();
+
+        entries.sort_by(|a, b| a.1.min.x.total_cmp(&b.1.min.x).then(a.0.cmp(&b.0)));
+
+        // Joined bodies that should not collide with each other
+        let excluded: HashSet<(usize, usize)> = self.joints.iter()
+            .filter(|j| !j.collide_connected)
+            .filter_map(|j| j.body_b.map(|b| (j.body_a.0.min(b.0), j.body_a.0.max(b.0))))
+            .collect();
+
+        let mut pairs = Vec::new();
+        for i in 0..entries.len() {
+            let (a, aabb_a) = entries[i];
+
+            for (b, aabb_b) in entries.iter().skip(i + 1) {
+                if aabb_b.min.x > aabb_a.max.x {
+                    break;
+                }
+
+                if !(self.is_active(a) || self.is_active(*b)) {
+                    continue;
+                }
+
+                if !self.bodies[a].is_dynamic() && !self.bodies[*b].is_dynamic() {
+                    continue;
+                }
+
+                let pair = (a.min(*b), a.max(*b));
+                if aabb_a.overlaps(aabb_b) && !excluded.contains(&pair) {
+                    pairs.push(pair);
+                }
+            }
+        }
+
+        pairs.sort();
+        pairs
+    }
+
+    fn narrowphase(&self, pairs: &[(usize, usize)]) -> Vec<Contact> {
+        let mut contacts = Vec::new();
+
+        for (a, b) in pairs.iter() {
+            for point in collide(&self.bodies[*a], &self.bodies[*b]) {
+                contacts.push(Contact::new(BodyHandle(*a), BodyHandle(*b), point));
+            }
+        }
+
+        contacts
+    }
+
+    /// Advances the simulation by `delta` according to the step mode.
+    pub fn update(&mut self, delta: f64) {
+        self.step_stats.clear();
+
+        match self.settings.step_mode {
+            StepMode::Variable => self.run_step(delta),
+            StepMode::Fixed {timestep, max_substeps} => {
+                self.accumulator += delta;
+
+                let mut substeps = 0;
+                while self.accumulator >= timestep && substeps < max_substeps {
+                    self.run_step(timestep);
+                    self.accumulator -= timestep;
+                    substeps += 1;
+                }
+
+                // Drop the time we could not catch up with
+                if substeps == max_substeps {
+                    self.accumulator = self.accumulator.min(timestep);
+                }
+            }
+        }
+    }
+
+    // Bodies moving further than their inner radius in a step are cast against the non-dynamic
+    // bodies and stopped at the first hit. Returns the bodies that were moved here
+    fn continuous_collision(&mut self, delta: f64) -> Vec<bool> {
+        let mut swept = vec![false; self.bodies.len()];
+        if !self.settings.ccd_enabled {
+            return swept;
+        }
+
+        for (i, moved) in swept.iter_mut().enumerate() {
+            let body = &self.bodies[i];
+            if !body.is_dynamic() || body.is_sleeping() {
+                continue;
+            }
+
+            let radius = match body.shape() {
+                Some(Shape::Sphere {radius}) | Some(Shape::Capsule {radius, ..}) => *radius,
+                Some(Shape::Cuboid {half_extents}) => half_extents.x.min(half_extents.y).min(half_extents.z),
+                _ => continue
+            };
+
+            let speed = body.velocity.magnitude();
+            if speed * delta <= radius {
+                continue;
+            }
+
+            let direction = body.velocity / speed;
+            let bodies = &self.bodies;
+            let hit = query::raycast(bodies, body.position, direction, speed * delta + radius, |h| {
+                h.0 != i && !bodies[h.0].is_dynamic()
+            });
+
+            if let Some(hit) = hit {
+                let body = &mut self.bodies[i];
+                body.position += direction * (hit.distance - radius).max(0.0);
+
+                let into = body.velocity.dot(hit.normal);
+                if into < 0.0 {
+                    body.velocity -= hit.normal * into;
+                }
+
+                body.integrate_rotation(delta);
+                *moved = true;
+            }
+        }
+
+        swept
+    }
+
+    // Breaks the destructibles hit hard enough in this step, the first piece takes over the body
+    fn fracture(&mut self) {
+        self.fracture_events.clear();
+
+        for (i, destructible) in self.destructibles.iter_mut().enumerate() {
+            if destructible.is_broken() {
+                continue;
+            }
+
+            let body = destructible.body;
+            let contacts = self.contacts.iter().filter(|c| c.body_a == body || c.body_b == body);
+            let total: f64 = contacts.clone().map(|c| c.impulse).sum();
+            let strongest = contacts.max_by(|a, b| a.impulse.total_cmp(&b.impulse));
+
+            let point = match strongest {
+                Some(contact) if total >= destructible.threshold => contact.point,
+                _ => continue
+            };
+
+            let pieces = destructible.fracture(&self.bodies[body.0], point, &mut self.rng);
+            let mut handles = Vec::new();
+
+            for (k, (piece, cell)) in pieces.into_iter().enumerate() {
+                let handle = if k == 0 {
+                    self.bodies[body.0] = piece;
+                    body
+                } else {
+                    self.bodies.push(piece);
+                    BodyHandle(self.bodies.len() - 1)
+                };
+                handles.push((handle, cell));
+            }
+
+            if !handles.is_empty() {
+                self.fracture_events.push(FractureEvent {destructible: DestructibleHandle(i), point, pieces: handles});
+            }
+        }
+    }
+
+    /// Advances the simulation by exactly one step of `delta`.
+    pub fn step(&mut self, delta: f64) {
+        self.step_stats.clear();
+        self.run_step(delta);
+    }
+
+    fn run_step(&mut self, delta: f64) {
+        if delta <= 0.0 {
+            return;
+        }
+
+        let mut timings = StepTimings::default();
+        let start = Instant::now();
+        let mut stage = Instant::now();
+
+        // Integrate forces
+        let gravity = self.gravity();
+        for generator in self.forces.iter_mut().flatten() {
+            generator.set_world_gravity(gravity);
+            generator.apply(&mut self.bodies, &mut self.rng, delta);
+        }
+
+        for vehicle in self.vehicles.iter_mut() {
+            vehicle.update(&mut self.bodies, delta);
+        }
+
+        for joint in self.joints.iter_mut() {
+            joint.apply_motor(&mut self.bodies);
+        }
+
+        for body in self.bodies.iter_mut().filter(|b| b.is_dynamic() && !b.is_sleeping()) {
+            body.integrate_velocity(delta);
+        }
+        timings.forces = stage.elapsed();
+
+        // Collisions
+        stage = Instant::now();
+        let pairs = self.broadphase();
+        timings.broadphase = stage.elapsed();
+
+        stage = Instant::now();
+        self.contacts = self.narrowphase(&pairs);
+        timings.narrowphase = stage.elapsed();
+
+        // Islands
+        stage = Instant::now();
+        let links: Vec<(BodyHandle, BodyHandle)> = self.forces.iter()
+            .flatten()
+            .flat_map(|f| f.links())
+            .collect();
+
+        self.wake_kinematic_contacts();
+        self.islands = island::build_islands(&self.bodies, &self.contacts, &self.joints, &links);
+        island::wake_islands(&mut self.islands, &mut self.bodies);
+        timings.islands = stage.elapsed();
+
+        // Solver
+        stage = Instant::now();
+        for contact in self.contacts.iter_mut() {
+            contact.prepare(&self.bodies, &self.settings.contact, delta);
+        }
+
+        let awake: Vec<bool> = self.joints.iter()
+            .map(|j| self.is_active(j.body_a.0) || j.body_b.map(|b| self.is_active(b.0)).unwrap_or(false))
+            .collect();
+
+        for _ in 0..self.settings.solver_iterations {
+            for (joint, _) in self.joints.iter_mut().zip(awake.iter()).filter(|(_, awake)| **awake) {
+                joint.solve(&mut self.bodies, self.settings.joint_bias, delta);
+            }
+
+            for contact in self.contacts.iter_mut() {
+                contact.solve(&mut self.bodies);
+            }
+        }
+        timings.solver = stage.elapsed();
+
+        // Integrate velocities
+        stage = Instant::now();
+        let start_poses: Vec<(Vector3<f64>, Quaternion<f64>)> = match self.soft_bodies.is_empty() {
+            true => Vec::new(),
+            false => self.bodies.iter().map(|b| (b.position, b.rotation)).collect()
+        };
+
+        let swept = match self.settings.force_model {
+            ForceModel::Generators => self.continuous_collision(delta),
+            ForceModel::NBody(_) => vec![false; self.bodies.len()]
+        };
+
+        let nbody = match &self.settings.force_model {
+            ForceModel::NBody(settings) => Some(settings),
+            ForceModel::Generators => None
+        };
+
+        if let Some(settings) = nbody {
+            nbody::integrate(&mut self.bodies, settings, delta);
+        }
+
+        for (i, body) in self.bodies.iter_mut().enumerate() {
+            let integrated = (nbody.is_some() && body.is_dynamic()) || swept[i];
+            if body.body_type() != body::BodyType::Static && !body.is_sleeping() && !integrated {
+                body.integrate_position(delta);
+            }
+            body.clear_forces();
+        }
+        timings.integration = stage.elapsed();
+
+        // Soft bodies
+        stage = Instant::now();
+        let forces = &self.forces;
+        let acceleration = |position, velocity| {
+            forces.iter()
+                .flatten()
+                .fold(Vector3::new(0.0, 0.0, 0.0), |acc, f| acc + f.particle_acceleration(position, velocity))
+        };
+
+        for soft in self.soft_bodies.iter_mut() {
+            soft.step(delta, &acceleration, &mut self.bodies, &start_poses, self.soft_backend.as_ref());
+        }
+
+        for fluid in self.fluids.iter_mut() {
+            fluid.step(delta, &acceleration, &mut self.bodies, self.fluid_backend.as_ref());
+        }
+
+        self.fracture();
+        timings.deformables = stage.elapsed();
+
+        // Sleeping
+        stage = Instant::now();
+        if self.settings.sleep_enabled {
+            let (linear, angular) = (self.settings.sleep_linear_threshold, self.settings.sleep_angular_threshold);
+            for body in self.bodies.iter_mut().filter(|b| b.is_dynamic() && !b.is_sleeping()) {
+                body.update_sleep_timer(linear, angular, delta);
+            }
+
+            island::sleep_islands(&mut self.islands, &mut self.bodies, self.settings.time_to_sleep);
+        }
+        timings.sleeping = stage.elapsed();
+
+        if let FloatMode::Quantized {bits} = self.settings.float_mode {
+            for body in self.bodies.iter_mut() {
+                body.quantize(bits);
+            }
+
+            for soft in self.soft_bodies.iter_mut() {
+                soft.quantize(bits);
+            }
+
+            for fluid in self.fluids.iter_mut() {
+                fluid.quantize(bits);
+            }
+
+            for vehicle in self.vehicles.iter_mut() {
+                vehicle.quantize(bits);
+            }
+        }
+
+        if let ForceModel::NBody(settings) = &self.settings.force_model {
+            nbody::measure(&self.bodies, settings, &mut self.nbody_stats);
+        }
+
+        self.ticks += 1;
+        timings.total = start.elapsed();
+        self.update_stats(delta, pairs.len(), timings);
+    }
+
+    fn update_stats(&mut self, delta: f64, pairs: usize, timings: StepTimings) {
+        let dynamic = self.bodies.iter().filter(|b| b.is_dynamic());
+        let sleeping = dynamic.clone().filter(|b| b.is_sleeping()).count();
+        let total = dynamic.count();
+
+        self.stats = PhysicsStats {
+            tick: self.ticks,
+            delta,
+            bodies: self.bodies.len(),
+            awake_bodies: total - sleeping,
+            sleeping_bodies: sleeping,
+            islands: self.islands.len(),
+            sleeping_islands: self.islands.iter().filter(|i| i.sleeping).count(),
+            pairs,
+            contacts: self.contacts.len(),
+            timings
+        };
+        self.step_stats.push(self.stats.clone());
+    }
+}
+
+impl Default for PhysicsWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+    use super::PhysicsWorld;
+    use super::body::{BodyHandle, BodyType, RigidBody};
+    use super::collider::Collider;
+    use super::joint::Joint;
+
+    const TIMESTEP: f64 = 1.0 / 240.0;
+
+    fn run(physics: &mut PhysicsWorld, seconds: f64) {
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            physics.step(TIMESTEP);
+        }
+    }
+
+    #[test]
+    fn moving_kinematic_wakes_sleeping_body() {
+        let mut physics = PhysicsWorld::new();
+        physics.add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+        let crate_body = physics.add_body(RigidBody::dynamic(Vector3::new(0.0, 0.5, 0.0)).with_collider(Collider::cuboid(0.5, 0.5, 0.5), 1.0));
+
+        run(&mut physics, 3.0);
+        assert!(physics.bodies()[crate_body.0].is_sleeping(), "the crate never fell asleep");
+
+        // A door sliding along the floor into the sleeping crate
+        let mut door = RigidBody::new(BodyType::Kinematic, Vector3::new(-3.0, 0.5, 0.0)).with_collider(Collider::cuboid(0.2, 0.5, 1.0), 1.0);
+        door.velocity = Vector3::new(1.0, 0.0, 0.0);
+        physics.add_body(door);
+
+        run(&mut physics, 4.0);
+
+        let body = &physics.bodies()[crate_body.0];
+        assert!(body.position.x > 1.0, "the crate was not pushed, it is at {:?}", body.position);
+    }
+
+    #[test]
+    fn joints_need_existing_bodies() {
+        let mut physics = PhysicsWorld::new();
+        let a = physics.add_body(RigidBody::dynamic(Vector3::zero()).with_collider(Collider::sphere(0.5), 1.0));
+        let b = physics.add_body(RigidBody::dynamic(Vector3::new(2.0, 0.0, 0.0)).with_collider(Collider::sphere(0.5), 1.0));
+
+        assert!(physics.add_joint(Joint::distance(a, BodyHandle(7), 2.0)).is_none());
+        assert!(physics.add_joint(Joint::distance(BodyHandle(2), b, 2.0)).is_none());
+        assert!(physics.joints().is_empty());
+
+        let joint = physics.add_joint(Joint::distance(a, b, 2.0)).unwrap();
+        assert!(physics.get_joint(joint).is_some());
+    }
+}
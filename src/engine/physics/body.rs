@@ -0,0 +1,390 @@
+use std::borrow::Cow;
+use cgmath::{InnerSpace, Matrix, Matrix3, Quaternion, Rotation, SquareMatrix, Vector3, Zero};
+use super::collider::{Collider, Shape};
+use super::determinism::{quantize_quaternion, quantize_vector, StateHasher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BodyHandle(pub usize);
+
+// Velocity change a particle has to give a sleeping body to wake it
+const PARTICLE_WAKE_SPEED: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    Dynamic,
+    Kinematic,
+    Static
+}
+
+#[derive(Clone)]
+pub struct RigidBody {
+    pub position: Vector3<f64>,
+    pub rotation: Quaternion<f64>,
+    pub velocity: Vector3<f64>,
+    pub angular_velocity: Vector3<f64>,
+
+    pub restitution: f64,
+    pub friction: f64,
+
+    // Private
+    body_type: BodyType,
+    collider: Option<Collider>,
+    mass: f64,
+    inv_mass: f64,
+    inv_inertia: Vector3<f64>,
+
+    force: Vector3<f64>,
+    torque: Vector3<f64>,
+
+    sleeping: bool,
+    sleep_timer: f64
+}
+
+impl RigidBody {
+    pub fn new(body_type: BodyType, position: Vector3<f64>) -> Self {
+        let rotation = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+
+        let (mass, inv_mass, inv_inertia) = match body_type {
+            BodyType::Dynamic => (1.0, 1.0, Vector3::new(1.0, 1.0, 1.0)),
+            _ => (0.0, 0.0, Vector3::zero())
+        };
+
+        Self {
+            position,
+            rotation,
+            velocity: Vector3::zero(),
+            angular_velocity: Vector3::zero(),
+            restitution: 0.2,
+            friction: 0.5,
+            body_type,
+            collider: None,
+            mass,
+            inv_mass,
+            inv_inertia,
+            force: Vector3::zero(),
+            torque: Vector3::zero(),
+            sleeping: false,
+            sleep_timer: 0.0
+        }
+    }
+
+    pub fn dynamic(position: Vector3<f64>) -> Self {
+        Self::new(BodyType::Dynamic, position)
+    }
+
+    pub fn fixed(position: Vector3<f64>) -> Self {
+        Self::new(BodyType::Static, position)
+    }
+
+    /// Attaches a collider and recomputes mass properties from its volume.
+    pub fn with_collider(mut self, collider: Collider, density: f64) -> Self {
+        if self.body_type == BodyType::Dynamic {
+            let (mass, inertia) = collider.mass_properties(density);
+            self.set_mass_properties(mass, inertia);
+        }
+
+        self.collider = Some(collider);
+        self
+    }
+
+    pub fn set_mass_properties(&mut self, mass: f64, inertia: Vector3<f64>) {
+        if self.body_type != BodyType::Dynamic || mass <= 0.0 {
+            return;
+        }
+
+        let inv = |x: f64| if x > 0.0 { 1.0 / x } else { 0.0 };
+
+        self.mass = mass;
+        self.inv_mass = 1.0 / mass;
+        self.inv_inertia = Vector3::new(inv(inertia.x), inv(inertia.y), inv(inertia.z));
+    }
+
+    pub fn body_type(&self) -> BodyType {
+        self.body_type
+    }
+
+    /// Kinematic body with a velocity, it pushes and wakes what it touches.
+    pub fn is_moving_kinematic(&self) -> bool {
+        self.body_type == BodyType::Kinematic && (self.velocity.magnitude2() > 0.0 || self.angular_velocity.magnitude2() > 0.0)
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.body_type == BodyType::Dynamic
+    }
+
+    pub fn collider(&self) -> Option<&Collider> {
+        self.collider.as_ref()
+    }
+
+    pub fn shape(&self) -> Option<&Shape> {
+        self.collider.as_ref().map(|c| &c.shape)
+    }
+
+    /// The body moved onto the pose of its collider, what the collision code works with.
+    pub(crate) fn collider_frame(&self) -> Cow<'_, RigidBody> {
+        match self.collider.as_ref().filter(|c| c.has_offset()) {
+            Some(collider) => {
+                let mut frame = self.clone();
+                frame.position = self.local_to_world(collider.offset);
+                frame.rotation = self.rotation * collider.rotation;
+                frame.collider = Some(Collider::new(collider.shape.clone()));
+                Cow::Owned(frame)
+            },
+            None => Cow::Borrowed(self)
+        }
+    }
+
+    pub fn mass(&self) -> f64 {
+        self.mass
+    }
+
+    pub fn inv_mass(&self) -> f64 {
+        self.inv_mass
+    }
+
+    pub fn inertia(&self) -> Vector3<f64> {
+        let inv = |x: f64| if x > 0.0 { 1.0 / x } else { 0.0 };
+        Vector3::new(inv(self.inv_inertia.x), inv(self.inv_inertia.y), inv(self.inv_inertia.z))
+    }
+
+    pub fn force(&self) -> Vector3<f64> {
+        self.force
+    }
+
+    pub fn torque(&self) -> Vector3<f64> {
+        self.torque
+    }
+
+    // Forces
+    pub fn apply_force(&mut self, force: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.force += force;
+        self.wake_up();
+    }
+
+    pub fn apply_force_at_point(&mut self, force: Vector3<f64>, point: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.force += force;
+        self.torque += (point - self.position).cross(force);
+        self.wake_up();
+    }
+
+    pub fn apply_torque(&mut self, torque: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.torque += torque;
+        self.wake_up();
+    }
+
+    pub fn apply_impulse(&mut self, impulse: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.velocity += impulse * self.inv_mass;
+        self.wake_up();
+    }
+
+    /// Impulse applied at `offset` from the center of mass, in world space.
+    pub fn apply_impulse_at(&mut self, impulse: Vector3<f64>, offset: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.velocity += impulse * self.inv_mass;
+        self.angular_velocity += self.inv_inertia_world(offset.cross(impulse));
+        self.wake_up();
+    }
+
+    /// Push of a soft body or fluid particle, resting particles leave a sleeping body asleep.
+    pub(crate) fn apply_particle_impulse(&mut self, impulse: Vector3<f64>, offset: Vector3<f64>) {
+        if self.sleeping && impulse.magnitude() * self.inv_mass < PARTICLE_WAKE_SPEED {
+            return;
+        }
+
+        self.apply_impulse_at(impulse, offset);
+    }
+
+    /// Angular impulse in world space.
+    pub fn apply_angular_impulse(&mut self, impulse: Vector3<f64>) {
+        if !self.is_dynamic() {
+            return;
+        }
+
+        self.angular_velocity += self.inv_inertia_world(impulse);
+        self.wake_up();
+    }
+
+    /// Position level impulse, moves the body right away and spreads the
+    /// matching velocity change over `delta`.
+    pub(crate) fn apply_correction(&mut self, correction: Vector3<f64>, offset: Vector3<f64>, delta: f64) {
+        let linear = correction * self.inv_mass;
+        let angular = self.inv_inertia_world(offset.cross(correction));
+
+        self.position += linear;
+        let spin = Quaternion::new(0.0, angular.x, angular.y, angular.z) * self.rotation;
+        self.rotation = (self.rotation + spin * 0.5).normalize();
+
+        self.velocity += linear / delta;
+        self.angular_velocity += angular / delta;
+    }
+
+    pub(crate) fn clear_forces(&mut self) {
+        self.force = Vector3::zero();
+        self.torque = Vector3::zero();
+    }
+
+    // Kinematics
+    pub fn velocity_at(&self, offset: Vector3<f64>) -> Vector3<f64> {
+        self.velocity + self.angular_velocity.cross(offset)
+    }
+
+    pub fn local_to_world(&self, point: Vector3<f64>) -> Vector3<f64> {
+        self.position + self.rotation.rotate_vector(point)
+    }
+
+    pub fn world_to_local(&self, point: Vector3<f64>) -> Vector3<f64> {
+        self.rotation.conjugate().rotate_vector(point - self.position)
+    }
+
+    pub fn inv_inertia_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        let local = self.rotation.conjugate().rotate_vector(v);
+        let scaled = Vector3::new(local.x * self.inv_inertia.x, local.y * self.inv_inertia.y, local.z * self.inv_inertia.z);
+        self.rotation.rotate_vector(scaled)
+    }
+
+    /// Inverse effective mass along `axis` for a point at `offset` from the center of mass.
+    pub fn effective_inv_mass(&self, offset: Vector3<f64>, axis: Vector3<f64>) -> f64 {
+        let rn = offset.cross(axis);
+        self.inv_mass + self.inv_inertia_world(rn).cross(offset).dot(axis)
+    }
+
+    pub fn kinetic_energy(&self) -> f64 {
+        if !self.is_dynamic() {
+            return 0.0;
+        }
+
+        let w = self.rotation.conjugate().rotate_vector(self.angular_velocity);
+        let inertia = self.inertia();
+        let rotational = inertia.x * w.x * w.x + inertia.y * w.y * w.y + inertia.z * w.z * w.z;
+
+        0.5 * self.mass * self.velocity.magnitude2() + 0.5 * rotational
+    }
+
+    pub(crate) fn integrate_velocity(&mut self, delta: f64) {
+        self.velocity += self.force * self.inv_mass * delta;
+        self.angular_velocity += self.inv_inertia_world(self.torque) * delta;
+    }
+
+    pub(crate) fn integrate_position(&mut self, delta: f64) {
+        self.position += self.velocity * delta;
+        self.integrate_rotation(delta);
+    }
+
+    pub(crate) fn integrate_rotation(&mut self, delta: f64) {
+        let w = self.angular_velocity;
+        let spin = Quaternion::new(0.0, w.x, w.y, w.z) * self.rotation;
+        self.rotation = (self.rotation + spin * (0.5 * delta)).normalize();
+    }
+
+    // Sleeping
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    pub fn wake_up(&mut self) {
+        if self.sleeping {
+            self.sleeping = false;
+            self.sleep_timer = 0.0;
+        }
+    }
+
+    pub(crate) fn sleep(&mut self) {
+        self.sleeping = true;
+        self.velocity = Vector3::zero();
+        self.angular_velocity = Vector3::zero();
+    }
+
+    pub(crate) fn sleep_timer(&self) -> f64 {
+        self.sleep_timer
+    }
+
+    pub(crate) fn update_sleep_timer(&mut self, linear_threshold: f64, angular_threshold: f64, delta: f64) {
+        let linear = self.velocity.magnitude2();
+        let angular = self.angular_velocity.magnitude2();
+
+        if linear > linear_threshold * linear_threshold || angular > angular_threshold * angular_threshold {
+            self.sleep_timer = 0.0;
+        } else {
+            self.sleep_timer += delta;
+        }
+    }
+
+    // Determinism
+    pub(crate) fn quantize(&mut self, bits: i32) {
+        self.position = quantize_vector(self.position, bits);
+        self.rotation = quantize_quaternion(self.rotation, bits);
+        self.velocity = quantize_vector(self.velocity, bits);
+        self.angular_velocity = quantize_vector(self.angular_velocity, bits);
+    }
+
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_vector(self.position);
+        hasher.write_quaternion(self.rotation);
+        hasher.write_vector(self.velocity);
+        hasher.write_vector(self.angular_velocity);
+        hasher.write_bool(self.sleeping);
+        hasher.write_f64(self.sleep_timer);
+    }
+}
+
+/// Principal moments of a symmetric inertia tensor and the rotation from the principal axes
+/// to the axes of the tensor. Cyclic Jacobi sweeps, a 3x3 settles in a few.
+pub fn principal_axes(inertia: Matrix3<f64>) -> (Vector3<f64>, Quaternion<f64>) {
+    let mut a = inertia;
+    let mut axes = Matrix3::identity();
+    let scale = a.x.x.abs() + a.y.y.abs() + a.z.z.abs();
+
+    for _ in 0..16 {
+        let off = a[1][0].abs() + a[2][0].abs() + a[2][1].abs();
+        if off <= 1.0e-15 * scale {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[q][p] == 0.0 {
+                continue;
+            }
+
+            // Rotation in the p-q plane that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[q][p]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            let mut rotation = Matrix3::identity();
+            rotation[p][p] = c;
+            rotation[q][q] = c;
+            rotation[q][p] = s;
+            rotation[p][q] = -s;
+
+            a = rotation.transpose() * a * rotation;
+            axes = axes * rotation;
+        }
+    }
+
+    // Proper rotation, the moments do not care about the sign of an axis
+    if axes.determinant() < 0.0 {
+        axes.z = -axes.z;
+    }
+
+    (Vector3::new(a.x.x, a.y.y, a.z.z), Quaternion::from(axes).normalize())
+}
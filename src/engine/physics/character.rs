@@ -0,0 +1,402 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::body::{BodyHandle, BodyType, RigidBody};
+use super::collider::{Collider, ContactPoint};
+use super::query::{overlap, raycast};
+
+const MAX_DEPENETRATION: usize = 8;
+
+/// Capsule that moves through the world with collide-and-slide, Y is up.
+/// It is kinematic: bodies block it but are not pushed.
+#[derive(Clone, Debug)]
+pub struct CharacterController {
+    // Capsule center
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    pub radius: f64,
+    pub half_height: f64,
+
+    pub max_speed: f64,
+    pub acceleration: f64,
+    // Fraction of the acceleration available in the air
+    pub air_control: f64,
+    pub gravity: f64,
+    pub jump_speed: f64,
+    // Steepest walkable ground, in radians
+    pub max_slope: f64,
+    // Edges up to this high above the feet are climbed, at most the radius
+    pub step_height: f64,
+    // Gap kept to the geometry
+    pub skin: f64,
+    // Jumps are still allowed this long after walking off a ledge
+    pub coyote_time: f64,
+    // Keeps the character on the ground when walking down slopes and stairs
+    pub snap_distance: f64,
+
+    // Private
+    movement: Vector3<f64>,
+    jump_requested: bool,
+    jumping: bool,
+    grounded: bool,
+    ground_normal: Vector3<f64>,
+    ground_body: Option<BodyHandle>,
+    air_time: f64
+}
+
+impl CharacterController {
+    pub fn new(position: Vector3<f64>, radius: f64, half_height: f64) -> Self {
+        Self {
+            position,
+            velocity: Vector3::zero(),
+            radius,
+            half_height,
+            max_speed: 5.0,
+            acceleration: 40.0,
+            air_control: 0.3,
+            gravity: 20.0,
+            jump_speed: 6.0,
+            max_slope: 45f64.to_radians(),
+            step_height: 0.3,
+            skin: 0.01,
+            coyote_time: 0.1,
+            snap_distance: 0.2,
+            movement: Vector3::zero(),
+            jump_requested: false,
+            jumping: false,
+            grounded: false,
+            ground_normal: Vector3::unit_y(),
+            ground_body: None,
+            // Spawned in the air, no coyote jump before the first landing
+            air_time: f64::INFINITY
+        }
+    }
+
+    /// Desired horizontal direction, its length is the fraction of `max_speed`.
+    pub fn set_movement(&mut self, direction: Vector3<f64>) {
+        let horizontal = Vector3::new(direction.x, 0.0, direction.z);
+        self.movement = if horizontal.magnitude2() > 1.0 { horizontal.normalize() } else { horizontal };
+    }
+
+    /// Jumps on the next update if the character is on the ground, or left it less than `coyote_time` ago.
+    pub fn jump(&mut self) {
+        self.jump_requested = true;
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    pub fn ground_normal(&self) -> Option<Vector3<f64>> {
+        if self.grounded { Some(self.ground_normal) } else { None }
+    }
+
+    pub fn ground_body(&self) -> Option<BodyHandle> {
+        self.ground_body
+    }
+
+    pub fn eye_position(&self) -> Vector3<f64> {
+        self.position + Vector3::new(0.0, self.half_height, 0.0)
+    }
+
+    fn is_walkable(&self, normal: Vector3<f64>) -> bool {
+        normal.y >= self.max_slope.cos()
+    }
+
+    // Normal of the walkable surface the contact stands for, edges of low steps count
+    // when the top next to them is walkable
+    fn support(&self, bodies: &[RigidBody], contact: &ContactPoint) -> Option<Vector3<f64>> {
+        let normal = -contact.normal;
+        if self.is_walkable(normal) {
+            return Some(normal);
+        }
+
+        let feet = self.position.y - self.half_height - self.radius;
+        let center = self.position.y - self.half_height;
+        if contact.point.y > center || contact.point.y - feet > self.step_height {
+            return None;
+        }
+
+        let outward = Vector3::new(contact.point.x - self.position.x, 0.0, contact.point.z - self.position.z);
+        if outward.magnitude2() < 1.0e-12 {
+            return None;
+        }
+
+        let origin = contact.point + outward.normalize() * self.skin + Vector3::unit_y() * self.skin;
+        raycast(bodies, origin, -Vector3::unit_y(), 2.0 * self.skin, |_| true)
+            .map(|hit| hit.normal)
+            .filter(|n| self.is_walkable(*n))
+    }
+
+    // The capsule grown by the skin, resting contacts have zero depth
+    fn probe(&self, position: Vector3<f64>) -> RigidBody {
+        RigidBody::new(BodyType::Kinematic, position).with_collider(Collider::capsule(self.radius + self.skin, self.half_height), 1.0)
+    }
+
+    // Pushes the capsule out of the geometry, returns the normals that block the motion
+    fn depenetrate(&mut self, bodies: &[RigidBody], grounded: bool) -> Vec<Vector3<f64>> {
+        let mut normals = Vec::new();
+
+        for _ in 0..MAX_DEPENETRATION {
+            let contacts = overlap(bodies, &self.probe(self.position), |_| true);
+            let deepest = contacts.iter()
+                .map(|(_, c)| c)
+                .filter(|c| c.depth > 1.0e-9)
+                .max_by(|a, b| a.depth.total_cmp(&b.depth));
+
+            let contact = match deepest {
+                Some(contact) => contact,
+                None => break
+            };
+
+            let normal = -contact.normal;
+            let horizontal = Vector3::new(normal.x, 0.0, normal.z);
+
+            // On the ground, supporting contacts only lift and steep ones only block, so neither
+            // makes the character creep; the ground itself does not stop the motion
+            if grounded && self.support(bodies, contact).is_some() {
+                self.position.y += contact.depth / normal.y.max(0.1);
+            } else if grounded && horizontal.magnitude2() > 1.0e-6 {
+                let horizontal = horizontal.normalize();
+                self.position += horizontal * (contact.depth / normal.dot(horizontal));
+                normals.push(horizontal);
+            } else {
+                self.position += normal * contact.depth;
+                normals.push(normal);
+            }
+        }
+
+        normals
+    }
+
+    // Moves by `motion` in substeps shorter than the radius, sliding along what it hits
+    fn sweep(&mut self, bodies: &[RigidBody], motion: Vector3<f64>, grounded: bool) -> Vec<Vector3<f64>> {
+        let steps = (motion.magnitude() / (self.radius * 0.5)).ceil().max(1.0) as usize;
+        let mut step = motion / steps as f64;
+        let mut normals = Vec::new();
+
+        for _ in 0..steps {
+            self.position += step;
+
+            for normal in self.depenetrate(bodies, grounded) {
+                let into = step.dot(normal);
+                if into < 0.0 {
+                    step -= normal * into;
+                }
+                normals.push(normal);
+            }
+        }
+
+        normals
+    }
+
+    fn clip_velocity(&mut self, normals: &[Vector3<f64>]) {
+        for normal in normals.iter() {
+            let into = self.velocity.dot(*normal);
+            if into < 0.0 {
+                self.velocity -= normal * into;
+            }
+        }
+    }
+
+    // Walkable ground within `distance` below: the body, the normal and the gap to it
+    fn find_ground(&self, bodies: &[RigidBody], distance: f64) -> Option<(BodyHandle, Vector3<f64>, f64)> {
+        let probe = self.probe(self.position - Vector3::unit_y() * distance);
+
+        overlap(bodies, &probe, |_| true).into_iter()
+            .filter_map(|(handle, c)| self.support(bodies, &c).map(|normal| (handle, normal, c.depth)))
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(handle, normal, depth)| (handle, normal, (distance - depth).max(0.0)))
+    }
+
+    pub fn update(&mut self, bodies: &[RigidBody], delta: f64) {
+        if delta <= 0.0 {
+            return;
+        }
+
+        // Ride moving platforms
+        if let Some(body) = self.ground_body.and_then(|h| bodies.get(h.0)).filter(|_| self.grounded) {
+            self.position += body.velocity_at(self.position - body.position) * delta;
+        }
+
+        let target = self.movement * self.max_speed;
+        let horizontal = Vector3::new(self.velocity.x, 0.0, self.velocity.z);
+        let rate = if self.grounded { self.acceleration } else { self.acceleration * self.air_control };
+
+        let mut change = target - horizontal;
+        if change.magnitude() > rate * delta {
+            change = change.normalize() * rate * delta;
+        }
+        self.velocity += change;
+
+        if self.jump_requested && !self.jumping && (self.grounded || self.air_time <= self.coyote_time) {
+            self.velocity.y = self.jump_speed;
+            self.jumping = true;
+            self.grounded = false;
+        }
+        self.jump_requested = false;
+
+        if self.grounded {
+            self.velocity.y = 0.0;
+        } else {
+            self.velocity.y -= self.gravity * delta;
+        }
+
+        let was_grounded = self.grounded;
+        let normals = self.sweep(bodies, self.velocity * delta, was_grounded);
+        self.clip_velocity(&normals);
+
+        // Ground detection, snapping down only while walking
+        let falling = self.velocity.y <= 0.0;
+        let distance = if was_grounded && !self.jumping { self.snap_distance } else { self.skin * 2.0 };
+
+        match self.find_ground(bodies, distance).filter(|_| falling) {
+            Some((body, normal, gap)) => {
+                self.position.y -= gap;
+                self.grounded = true;
+                self.jumping = false;
+                self.ground_normal = normal;
+                self.ground_body = Some(body);
+                self.air_time = 0.0;
+                self.velocity.y = 0.0;
+            },
+            None => {
+                self.grounded = false;
+                self.ground_body = None;
+                self.air_time += delta;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector3};
+    use super::CharacterController;
+    use crate::engine::physics::body::{BodyHandle, BodyType, RigidBody};
+    use crate::engine::physics::collider::Collider;
+
+    const TIMESTEP: f64 = 1.0 / 240.0;
+
+    // Radius 0.3 and half height 0.5, the feet are 0.8 below the center
+    const FEET: f64 = 0.8;
+
+    fn run(character: &mut CharacterController, bodies: &[RigidBody], seconds: f64) {
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            character.update(bodies, TIMESTEP);
+        }
+    }
+
+    fn ground() -> RigidBody {
+        RigidBody::fixed(Vector3::new(0.0, -0.5, 0.0)).with_collider(Collider::cuboid(10.0, 0.5, 10.0), 1.0)
+    }
+
+    // Box of the given height with its near face at x = 1
+    fn step(height: f64) -> RigidBody {
+        RigidBody::fixed(Vector3::new(3.0, height / 2.0, 0.0)).with_collider(Collider::cuboid(2.0, height / 2.0, 5.0), 1.0)
+    }
+
+    // Thin plate through the origin tilted by `angle` about Z, downhill towards +X
+    fn incline(angle: f64) -> RigidBody {
+        let mut body = RigidBody::fixed(Vector3::new(0.0, 0.0, 0.0)).with_collider(Collider::cuboid(3.0, 0.1, 3.0), 1.0);
+        body.rotation = Quaternion::from_angle_z(Rad(angle));
+        body
+    }
+
+    #[test]
+    fn character_spawned_in_the_air_cannot_jump() {
+        let mut character = CharacterController::new(Vector3::new(0.0, 5.0, 0.0), 0.3, 0.5);
+        character.jump();
+        character.update(&[], TIMESTEP);
+        assert!(character.velocity.y < 0.0, "jumped with {}", character.velocity.y);
+
+        // Landing gives the coyote window back
+        let ground = [RigidBody::fixed(Vector3::new(0.0, -0.5, 0.0)).with_collider(Collider::cuboid(5.0, 0.5, 5.0), 1.0)];
+        let mut character = CharacterController::new(Vector3::new(0.0, 0.81, 0.0), 0.3, 0.5);
+        character.update(&ground, TIMESTEP);
+        assert!(character.is_grounded());
+        character.jump();
+        character.update(&ground, TIMESTEP);
+        assert!(character.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn walking_into_a_wall_slides_along_it() {
+        let wall = RigidBody::fixed(Vector3::new(2.5, 1.0, 0.0)).with_collider(Collider::cuboid(0.5, 1.0, 10.0), 1.0);
+        let bodies = [ground(), wall];
+        let mut character = CharacterController::new(Vector3::new(0.0, FEET, 0.0), 0.3, 0.5);
+        character.set_movement(Vector3::new(1.0, 0.0, 1.0));
+
+        run(&mut character, &bodies, 2.0);
+
+        // Stopped by the wall at x = 2, still walking along it
+        assert!(character.is_grounded());
+        assert!(character.position.x < 2.0 - 0.3 + 1.0e-3 && character.position.x > 1.6, "x {}", character.position.x);
+        assert!(character.position.z > 3.0, "z {}", character.position.z);
+        assert!(character.velocity.x.abs() < 1.0e-6, "still pushing into the wall at {:?}", character.velocity);
+        assert!((character.position.y - FEET).abs() < 0.02, "y {}", character.position.y);
+    }
+
+    #[test]
+    fn low_steps_are_climbed_and_high_ones_block() {
+        for (height, climbed) in [(0.2, true), (0.5, false)] {
+            let bodies = [ground(), step(height)];
+            let mut character = CharacterController::new(Vector3::new(-1.0, FEET, 0.0), 0.3, 0.5);
+            character.set_movement(Vector3::unit_x());
+
+            run(&mut character, &bodies, 1.0);
+
+            assert!(character.is_grounded(), "step {} left the character in the air", height);
+            if climbed {
+                assert!(character.position.x > 2.0, "stuck at {:?} before a {} step", character.position, height);
+                assert!((character.position.y - FEET - height).abs() < 0.02, "y {} on a {} step", character.position.y, height);
+                assert_eq!(character.ground_body(), Some(BodyHandle(1)));
+            } else {
+                assert!(character.position.x < 1.0, "went through a {} step to {:?}", height, character.position);
+                assert!((character.position.y - FEET).abs() < 0.02, "climbed a {} step to {}", height, character.position.y);
+            }
+        }
+    }
+
+    #[test]
+    fn slopes_steeper_than_max_slope_are_not_ground() {
+        let floor = RigidBody::fixed(Vector3::new(0.0, -3.5, 0.0)).with_collider(Collider::cuboid(10.0, 0.5, 10.0), 1.0);
+
+        // Lands and stays on a gentle slope
+        let angle = 30f64.to_radians();
+        let bodies = [floor.clone(), incline(angle)];
+        let mut character = CharacterController::new(Vector3::new(0.0, 1.5, 0.0), 0.3, 0.5);
+        run(&mut character, &bodies, 1.0);
+
+        let normal = character.ground_normal().expect("not standing on a 30 degree slope");
+        assert!((normal.y - angle.cos()).abs() < 1.0e-3, "normal {:?}", normal);
+        assert_eq!(character.ground_body(), Some(BodyHandle(1)));
+        let resting = character.position;
+        run(&mut character, &bodies, 1.0);
+        assert!((character.position - resting).magnitude() < 1.0e-3, "slid from {:?} to {:?}", resting, character.position);
+
+        // Slides off a steep one down to the floor
+        let bodies = [floor, incline(60f64.to_radians())];
+        let mut character = CharacterController::new(Vector3::new(0.0, 1.5, 0.0), 0.3, 0.5);
+        run(&mut character, &bodies, 3.0);
+
+        assert_eq!(character.ground_body(), Some(BodyHandle(0)), "ended at {:?}", character.position);
+        assert!((character.position.y - (FEET - 3.0)).abs() < 0.02, "y {}", character.position.y);
+    }
+
+    #[test]
+    fn character_rides_a_moving_platform() {
+        let mut platform = RigidBody::new(BodyType::Kinematic, Vector3::new(0.0, -0.25, 0.0)).with_collider(Collider::cuboid(2.0, 0.25, 2.0), 1.0);
+        platform.velocity = Vector3::new(1.0, 0.0, 0.0);
+        let mut bodies = [platform];
+        let mut character = CharacterController::new(Vector3::new(0.0, FEET, 0.0), 0.3, 0.5);
+
+        for _ in 0..(1.0 / TIMESTEP).round() as usize {
+            let velocity = bodies[0].velocity;
+            bodies[0].position += velocity * TIMESTEP;
+            character.update(&bodies, TIMESTEP);
+        }
+
+        assert_eq!(character.ground_body(), Some(BodyHandle(0)));
+        assert!((character.position.x - bodies[0].position.x).abs() < 1.0e-2, "left behind at {:?}", character.position);
+        assert!((character.position.y - FEET).abs() < 0.02, "y {}", character.position.y);
+    }
+}
@@ -0,0 +1,642 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use cgmath::{InnerSpace, One, Quaternion, Rotation, Vector3, Zero};
+use super::body::RigidBody;
+use super::mesh::{Heightfield, TriMesh, Triangle};
+
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Sphere { radius: f64 },
+    Cuboid { half_extents: Vector3<f64> },
+    // Capsule along the local Y axis
+    Capsule { radius: f64, half_height: f64 },
+    // Infinite plane, only makes sense on static bodies
+    Plane { normal: Vector3<f64>, offset: f64 },
+    // Terrain and level geometry for static bodies, shared by the snapshots
+    Heightfield { field: Arc<Heightfield> },
+    TriMesh { mesh: Arc<TriMesh> }
+}
+
+#[derive(Clone, Debug)]
+pub struct Collider {
+    pub shape: Shape,
+    // Pose of the shape relative to the body
+    pub offset: Vector3<f64>,
+    pub rotation: Quaternion<f64>
+}
+
+impl Collider {
+    pub fn new(shape: Shape) -> Self {
+        Self {shape, offset: Vector3::zero(), rotation: Quaternion::one()}
+    }
+
+    /// Moves the shape away from the center of mass. The mass properties from `with_collider`
+    /// stay those of the centered shape, set them explicitly for offset shapes.
+    pub fn with_offset(mut self, offset: Vector3<f64>, rotation: Quaternion<f64>) -> Self {
+        self.offset = offset;
+        self.rotation = rotation.normalize();
+        self
+    }
+
+    pub fn has_offset(&self) -> bool {
+        self.offset != Vector3::zero() || self.rotation != Quaternion::one()
+    }
+
+    pub fn sphere(radius: f64) -> Self {
+        Self::new(Shape::Sphere {radius})
+    }
+
+    pub fn cuboid(hx: f64, hy: f64, hz: f64) -> Self {
+        Self::new(Shape::Cuboid {half_extents: Vector3::new(hx, hy, hz)})
+    }
+
+    pub fn capsule(radius: f64, half_height: f64) -> Self {
+        Self::new(Shape::Capsule {radius, half_height})
+    }
+
+    pub fn plane(normal: Vector3<f64>, offset: f64) -> Self {
+        Self::new(Shape::Plane {normal: normal.normalize(), offset})
+    }
+
+    pub fn heightfield(field: Heightfield) -> Self {
+        Self::new(Shape::Heightfield {field: Arc::new(field)})
+    }
+
+    pub fn trimesh(mesh: TriMesh) -> Self {
+        Self::new(Shape::TriMesh {mesh: Arc::new(mesh)})
+    }
+
+    pub fn volume(&self) -> f64 {
+        match &self.shape {
+            Shape::Sphere {radius} => 4.0 / 3.0 * PI * radius.powi(3),
+            Shape::Cuboid {half_extents: h} => 8.0 * h.x * h.y * h.z,
+            Shape::Capsule {radius, half_height} => PI * radius * radius * (2.0 * half_height) + 4.0 / 3.0 * PI * radius.powi(3),
+            Shape::Plane {..} | Shape::Heightfield {..} | Shape::TriMesh {..} => 0.0
+        }
+    }
+
+    /// Mass and principal moments of inertia for the given density.
+    pub fn mass_properties(&self, density: f64) -> (f64, Vector3<f64>) {
+        let mass = self.volume() * density;
+
+        let inertia = match &self.shape {
+            Shape::Sphere {radius} => {
+                let i = 0.4 * mass * radius * radius;
+                Vector3::new(i, i, i)
+            },
+            Shape::Cuboid {half_extents: h} => {
+                let (x, y, z) = (h.x * h.x, h.y * h.y, h.z * h.z);
+                Vector3::new(mass / 3.0 * (y + z), mass / 3.0 * (x + z), mass / 3.0 * (x + y))
+            },
+            Shape::Capsule {radius, half_height} => {
+                let height = 2.0 * half_height + 2.0 * radius;
+                let side = mass * (3.0 * radius * radius + height * height) / 12.0;
+                Vector3::new(side, 0.5 * mass * radius * radius, side)
+            },
+            Shape::Plane {..} | Shape::Heightfield {..} | Shape::TriMesh {..} => Vector3::zero()
+        };
+
+        (mass, inertia)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f64>, max: Vector3<f64>) -> Self {
+        Self {min, max}
+    }
+
+    pub fn from_center(center: Vector3<f64>, half: Vector3<f64>) -> Self {
+        Self {min: center - half, max: center + half}
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x &&
+        self.min.y <= other.max.y && self.max.y >= other.min.y &&
+        self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z))
+        )
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Box around the eight transformed corners.
+    pub fn transformed(&self, f: impl Fn(Vector3<f64>) -> Vector3<f64>) -> Aabb {
+        let first = f(self.min);
+        let mut aabb = Aabb::new(first, first);
+
+        for i in 1..8 {
+            let corner = Vector3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z }
+            );
+            let p = f(corner);
+            aabb = aabb.merge(&Aabb::new(p, p));
+        }
+
+        aabb
+    }
+}
+
+pub fn compute_aabb(body: &RigidBody) -> Option<Aabb> {
+    let frame = body.collider_frame();
+    let body = frame.as_ref();
+    let shape = body.shape()?;
+    let position = body.position;
+
+    let aabb = match shape {
+        Shape::Sphere {radius} => Aabb::from_center(position, Vector3::new(*radius, *radius, *radius)),
+        Shape::Cuboid {half_extents} => {
+            let ux = body.rotation.rotate_vector(Vector3::unit_x()) * half_extents.x;
+            let uy = body.rotation.rotate_vector(Vector3::unit_y()) * half_extents.y;
+            let uz = body.rotation.rotate_vector(Vector3::unit_z()) * half_extents.z;
+
+            let half = Vector3::new(
+                ux.x.abs() + uy.x.abs() + uz.x.abs(),
+                ux.y.abs() + uy.y.abs() + uz.y.abs(),
+                ux.z.abs() + uy.z.abs() + uz.z.abs()
+            );
+            Aabb::from_center(position, half)
+        },
+        Shape::Capsule {radius, half_height} => {
+            let tip = body.rotation.rotate_vector(Vector3::new(0.0, *half_height, 0.0));
+            let half = Vector3::new(tip.x.abs() + radius, tip.y.abs() + radius, tip.z.abs() + radius);
+            Aabb::from_center(position, half)
+        },
+        Shape::Plane {..} => {
+            let big = 1.0e12;
+            Aabb::from_center(Vector3::zero(), Vector3::new(big, big, big))
+        },
+        Shape::Heightfield {field} => field.aabb().transformed(|p| body.local_to_world(p)),
+        Shape::TriMesh {mesh} => mesh.aabb().transformed(|p| body.local_to_world(p))
+    };
+
+    Some(aabb)
+}
+
+/// Signed distance from `point` to the surface of the body collider and the outward normal.
+pub fn point_distance(body: &RigidBody, point: Vector3<f64>) -> Option<(f64, Vector3<f64>)> {
+    let frame = body.collider_frame();
+    let body = frame.as_ref();
+    let shape = body.shape()?;
+
+    let sphere = |center: Vector3<f64>, radius: f64| {
+        let d = point - center;
+        let length = d.magnitude();
+        let normal = if length > 1.0e-9 { d / length } else { Vector3::unit_y() };
+        (length - radius, normal)
+    };
+
+    let result = match shape {
+        Shape::Sphere {radius} => sphere(body.position, *radius),
+        Shape::Plane {normal, offset} => (point.dot(*normal) - offset, *normal),
+        Shape::Capsule {radius, half_height} => {
+            let (p, q) = capsule_segment(body, *half_height);
+            sphere(closest_on_segment(p, q, point), *radius)
+        },
+        Shape::Cuboid {half_extents: h} => {
+            let local = body.world_to_local(point);
+            let q = Vector3::new(local.x.abs() - h.x, local.y.abs() - h.y, local.z.abs() - h.z);
+
+            if q.x > 0.0 || q.y > 0.0 || q.z > 0.0 {
+                let clamped = Vector3::new(local.x.clamp(-h.x, h.x), local.y.clamp(-h.y, h.y), local.z.clamp(-h.z, h.z));
+                let d = local - clamped;
+                let distance = d.magnitude();
+                (distance, body.rotation.rotate_vector(d / distance))
+            } else {
+                let faces = [q.x, q.y, q.z];
+                let axis = (0..3).max_by(|a, b| faces[*a].total_cmp(&faces[*b])).unwrap();
+                let mut normal = Vector3::zero();
+                normal[axis] = if local[axis] >= 0.0 { 1.0 } else { -1.0 };
+                (faces[axis], body.rotation.rotate_vector(normal))
+            }
+        },
+        Shape::Heightfield {field} => {
+            // Distance to the plane of the triangle underneath
+            let local = body.world_to_local(point);
+            let (height, normal) = field.height_at(local.x, local.z)?;
+            ((local.y - height) * normal.y, body.rotation.rotate_vector(normal))
+        },
+        Shape::TriMesh {mesh} => {
+            // Negative behind the closest face
+            let local = body.world_to_local(point);
+            let (closest, face) = mesh.closest_point(local);
+            let d = local - closest;
+            let distance = d.magnitude();
+            let outside = d.dot(face) >= 0.0;
+
+            let normal = if outside && distance > 1.0e-9 { d / distance } else { face };
+            (if outside { distance } else { -distance }, body.rotation.rotate_vector(normal))
+        }
+    };
+
+    Some(result)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ContactPoint {
+    // Normal points from the first body to the second
+    pub point: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    pub depth: f64
+}
+
+impl ContactPoint {
+    fn flipped(self) -> Self {
+        Self {point: self.point, normal: -self.normal, depth: self.depth}
+    }
+}
+
+fn sphere_sphere(ca: Vector3<f64>, ra: f64, cb: Vector3<f64>, rb: f64) -> Option<ContactPoint> {
+    let d = cb - ca;
+    let distance = d.magnitude();
+
+    if distance >= ra + rb {
+        return None;
+    }
+
+    let normal = if distance > 1.0e-9 { d / distance } else { Vector3::unit_y() };
+    let point = ca + normal * (ra - 0.5 * (ra + rb - distance));
+
+    Some(ContactPoint {point, normal, depth: ra + rb - distance})
+}
+
+fn sphere_plane(center: Vector3<f64>, radius: f64, normal: Vector3<f64>, offset: f64) -> Option<ContactPoint> {
+    let distance = center.dot(normal) - offset;
+
+    if distance >= radius {
+        return None;
+    }
+
+    // Normal from the sphere towards the plane
+    Some(ContactPoint {point: center - normal * distance, normal: -normal, depth: radius - distance})
+}
+
+fn sphere_cuboid(center: Vector3<f64>, radius: f64, cuboid: &RigidBody, half: Vector3<f64>) -> Option<ContactPoint> {
+    let local = cuboid.world_to_local(center);
+    let clamped = Vector3::new(
+        local.x.clamp(-half.x, half.x),
+        local.y.clamp(-half.y, half.y),
+        local.z.clamp(-half.z, half.z)
+    );
+
+    let d = local - clamped;
+    let distance = d.magnitude();
+
+    if distance >= radius {
+        return None;
+    }
+
+    if distance > 1.0e-9 {
+        // Center outside the box
+        let normal = cuboid.rotation.rotate_vector(-d / distance);
+        let point = cuboid.local_to_world(clamped);
+        return Some(ContactPoint {point, normal, depth: radius - distance});
+    }
+
+    // Center inside the box, push out through the closest face
+    let faces = [half.x - local.x.abs(), half.y - local.y.abs(), half.z - local.z.abs()];
+    let axis = (0..3).min_by(|a, b| faces[*a].total_cmp(&faces[*b])).unwrap();
+    let mut local_normal = Vector3::zero();
+    local_normal[axis] = if local[axis] >= 0.0 { 1.0 } else { -1.0 };
+
+    let normal = cuboid.rotation.rotate_vector(-local_normal);
+    Some(ContactPoint {point: center, normal, depth: radius + faces[axis]})
+}
+
+fn closest_on_segment(a: Vector3<f64>, b: Vector3<f64>, p: Vector3<f64>) -> Vector3<f64> {
+    let ab = b - a;
+    let length = ab.magnitude2();
+
+    if length < 1.0e-12 {
+        return a;
+    }
+
+    let t = ((p - a).dot(ab) / length).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+fn closest_between_segments(p1: Vector3<f64>, q1: Vector3<f64>, p2: Vector3<f64>, q2: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.magnitude2();
+    let e = d2.magnitude2();
+    let f = d2.dot(r);
+
+    let (s, t) = if a < 1.0e-12 && e < 1.0e-12 {
+        (0.0, 0.0)
+    } else if a < 1.0e-12 {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        if e < 1.0e-12 {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+            let mut s = if denom.abs() > 1.0e-12 { ((b * f - c * e) / denom).clamp(0.0, 1.0) } else { 0.0 };
+            let mut t = (b * s + f) / e;
+
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+            (s, t)
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+pub(crate) fn capsule_segment(body: &RigidBody, half_height: f64) -> (Vector3<f64>, Vector3<f64>) {
+    let tip = body.rotation.rotate_vector(Vector3::new(0.0, half_height, 0.0));
+    (body.position - tip, body.position + tip)
+}
+
+fn cuboid_corners(body: &RigidBody, half: Vector3<f64>) -> [Vector3<f64>; 8] {
+    let mut corners = [Vector3::zero(); 8];
+
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let sx = if i & 1 == 0 { -1.0 } else { 1.0 };
+        let sy = if i & 2 == 0 { -1.0 } else { 1.0 };
+        let sz = if i & 4 == 0 { -1.0 } else { 1.0 };
+        *corner = body.local_to_world(Vector3::new(sx * half.x, sy * half.y, sz * half.z));
+    }
+
+    corners
+}
+
+fn cuboid_plane(body: &RigidBody, half: Vector3<f64>, normal: Vector3<f64>, offset: f64) -> Vec<ContactPoint> {
+    cuboid_corners(body, half).iter()
+        .filter_map(|corner| {
+            let distance = corner.dot(normal) - offset;
+            if distance < 0.0 {
+                Some(ContactPoint {point: *corner, normal: -normal, depth: -distance})
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn cuboid_axes(body: &RigidBody) -> [Vector3<f64>; 3] {
+    [
+        body.rotation.rotate_vector(Vector3::unit_x()),
+        body.rotation.rotate_vector(Vector3::unit_y()),
+        body.rotation.rotate_vector(Vector3::unit_z())
+    ]
+}
+
+fn project_cuboid(axes: &[Vector3<f64>; 3], half: Vector3<f64>, axis: Vector3<f64>) -> f64 {
+    half.x * axes[0].dot(axis).abs() + half.y * axes[1].dot(axis).abs() + half.z * axes[2].dot(axis).abs()
+}
+
+// Corners of `incident` behind the face of `reference` with outward normal `face`
+fn clip_to_face(reference: &RigidBody, half_r: Vector3<f64>, face: Vector3<f64>, incident: &RigidBody, half_i: Vector3<f64>) -> Vec<ContactPoint> {
+    let local_face = reference.rotation.conjugate().rotate_vector(face);
+    let plane = reference.position.dot(face) + project_cuboid(&cuboid_axes(reference), half_r, face);
+
+    cuboid_corners(incident, half_i).iter()
+        .filter_map(|corner| {
+            let depth = plane - corner.dot(face);
+            if depth <= 0.0 {
+                return None;
+            }
+
+            // Keep the point inside the reference face
+            let local = reference.world_to_local(*corner);
+            let mut clamped = Vector3::new(
+                local.x.clamp(-half_r.x, half_r.x),
+                local.y.clamp(-half_r.y, half_r.y),
+                local.z.clamp(-half_r.z, half_r.z)
+            );
+            for i in 0..3 {
+                if local_face[i].abs() > 0.5 {
+                    clamped[i] = local[i];
+                }
+            }
+
+            Some(ContactPoint {point: reference.local_to_world(clamped), normal: face, depth})
+        })
+        .collect()
+}
+
+// Separating axis test, contacts are generated from the best face axis
+fn cuboid_cuboid(a: &RigidBody, half_a: Vector3<f64>, b: &RigidBody, half_b: Vector3<f64>) -> Vec<ContactPoint> {
+    let axes_a = cuboid_axes(a);
+    let axes_b = cuboid_axes(b);
+    let d = b.position - a.position;
+
+    let overlap = |axis: Vector3<f64>| {
+        project_cuboid(&axes_a, half_a, axis) + project_cuboid(&axes_b, half_b, axis) - d.dot(axis).abs()
+    };
+
+    for axis_a in axes_a.iter() {
+        for axis_b in axes_b.iter() {
+            let axis = axis_a.cross(*axis_b);
+            if axis.magnitude2() > 1.0e-9 && overlap(axis.normalize()) < 0.0 {
+                return Vec::new();
+            }
+        }
+    }
+
+    let mut best: Option<(f64, Vector3<f64>, bool)> = None;
+    for (axis, on_a) in axes_a.iter().map(|x| (*x, true)).chain(axes_b.iter().map(|x| (*x, false))) {
+        let depth = overlap(axis);
+        if depth < 0.0 {
+            return Vec::new();
+        }
+
+        if best.map(|(b, _, _)| depth < b - 1.0e-9).unwrap_or(true) {
+            best = Some((depth, axis, on_a));
+        }
+    }
+
+    let (_, axis, on_a) = best.unwrap();
+    // Normal from `a` to `b`
+    let normal = if d.dot(axis) < 0.0 { -axis } else { axis };
+
+    if on_a {
+        clip_to_face(a, half_a, normal, b, half_b)
+    } else {
+        clip_to_face(b, half_b, -normal, a, half_a).into_iter().map(ContactPoint::flipped).collect()
+    }
+}
+
+// One sided, a center behind the face only collides while the sphere still reaches through it
+fn sphere_triangle(center: Vector3<f64>, radius: f64, triangle: &Triangle) -> Option<ContactPoint> {
+    let face = triangle.normal();
+    let height = (center - triangle.a).dot(face);
+    if height >= radius || height <= -radius {
+        return None;
+    }
+
+    let closest = triangle.closest_point(center);
+    let d = center - closest;
+    let distance = d.magnitude();
+
+    if height >= 0.0 {
+        if distance >= radius {
+            return None;
+        }
+
+        let normal = if distance > 1.0e-9 { d / distance } else { face };
+        return Some(ContactPoint {point: closest, normal: -normal, depth: radius - distance});
+    }
+
+    // Behind the face, only when the center projects onto it
+    if (center - face * height - closest).magnitude2() > 1.0e-12 {
+        return None;
+    }
+
+    Some(ContactPoint {point: closest, normal: -face, depth: radius - height})
+}
+
+fn cuboid_triangle(body: &RigidBody, half: Vector3<f64>, triangle: &Triangle) -> Vec<ContactPoint> {
+    let face = triangle.normal();
+    let corners = cuboid_corners(body, half);
+    let reach = 2.0 * half.x.max(half.y).max(half.z);
+
+    // Corners under the face
+    let mut contacts: Vec<ContactPoint> = corners.iter()
+        .filter_map(|corner| {
+            let height = (corner - triangle.a).dot(face);
+            let projected = corner - face * height;
+            let inside = (triangle.closest_point(projected) - projected).magnitude2() < 1.0e-12;
+
+            if height < 0.0 && height > -reach && inside {
+                Some(ContactPoint {point: *corner, normal: -face, depth: -height})
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Triangle vertices poking into the box, e.g. the box resting on an edge. Each is as deep
+    // as the box reaches past it along the face normal
+    if contacts.is_empty() {
+        let lowest = corners.iter().map(|c| c.dot(face)).fold(f64::INFINITY, f64::min);
+
+        for vertex in [triangle.a, triangle.b, triangle.c] {
+            let local = body.world_to_local(vertex);
+            let depth = vertex.dot(face) - lowest;
+            if local.x.abs() < half.x && local.y.abs() < half.y && local.z.abs() < half.z && depth > 0.0 {
+                contacts.push(ContactPoint {point: vertex, normal: -face, depth});
+            }
+        }
+    }
+
+    contacts
+}
+
+// Convex shapes against the triangles of a static mesh
+fn mesh_contacts(a: &RigidBody, mesh: &RigidBody, triangles: impl Fn(&Aabb) -> Vec<Triangle>) -> Vec<ContactPoint> {
+    // Static geometry does not collide with itself
+    if !matches!(a.shape(), Some(Shape::Sphere {..} | Shape::Capsule {..} | Shape::Cuboid {..})) {
+        return Vec::new();
+    }
+
+    let bounds = match compute_aabb(a) {
+        Some(aabb) => aabb.transformed(|p| mesh.world_to_local(p)),
+        None => return Vec::new()
+    };
+
+    let mut contacts = Vec::new();
+    for triangle in triangles(&bounds).iter().map(|t| t.map(|p| mesh.local_to_world(p))) {
+        match a.shape() {
+            Some(Shape::Sphere {radius}) => contacts.extend(sphere_triangle(a.position, *radius, &triangle)),
+            Some(Shape::Capsule {radius, half_height}) => {
+                let (p, q) = capsule_segment(a, *half_height);
+                for center in [p, (p + q) * 0.5, q] {
+                    contacts.extend(sphere_triangle(center, *radius, &triangle));
+                }
+            },
+            Some(Shape::Cuboid {half_extents}) => contacts.extend(cuboid_triangle(a, *half_extents, &triangle)),
+            _ => ()
+        }
+    }
+
+    // Neighbouring triangles report the same point on their shared edges
+    let mut merged: Vec<ContactPoint> = Vec::new();
+    for contact in contacts {
+        match merged.iter_mut().find(|c| (c.point - contact.point).magnitude2() < 1.0e-6) {
+            Some(existing) => if contact.depth > existing.depth { *existing = contact },
+            None => merged.push(contact)
+        }
+    }
+
+    merged
+}
+
+/// Narrowphase: contact points between two bodies, normals pointing from `a` to `b`.
+pub fn collide(a: &RigidBody, b: &RigidBody) -> Vec<ContactPoint> {
+    let (frame_a, frame_b) = (a.collider_frame(), b.collider_frame());
+    let (a, b) = (frame_a.as_ref(), frame_b.as_ref());
+
+    let (shape_a, shape_b) = match (a.shape(), b.shape()) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Vec::new()
+    };
+
+    match (shape_a, shape_b) {
+        (Shape::Sphere {radius: ra}, Shape::Sphere {radius: rb}) => {
+            sphere_sphere(a.position, *ra, b.position, *rb).into_iter().collect()
+        },
+        (Shape::Sphere {radius}, Shape::Plane {normal, offset}) => {
+            sphere_plane(a.position, *radius, *normal, *offset).into_iter().collect()
+        },
+        (Shape::Sphere {radius}, Shape::Cuboid {half_extents}) => {
+            sphere_cuboid(a.position, *radius, b, *half_extents).into_iter().collect()
+        },
+        (Shape::Sphere {radius}, Shape::Capsule {radius: rb, half_height}) => {
+            let (p, q) = capsule_segment(b, *half_height);
+            let closest = closest_on_segment(p, q, a.position);
+            sphere_sphere(a.position, *radius, closest, *rb).into_iter().collect()
+        },
+        (Shape::Capsule {radius, half_height}, Shape::Plane {normal, offset}) => {
+            let (p, q) = capsule_segment(a, *half_height);
+            [p, q].iter()
+                .filter_map(|end| sphere_plane(*end, *radius, *normal, *offset))
+                .collect()
+        },
+        (Shape::Capsule {radius: ra, half_height: ha}, Shape::Capsule {radius: rb, half_height: hb}) => {
+            let (p1, q1) = capsule_segment(a, *ha);
+            let (p2, q2) = capsule_segment(b, *hb);
+            let (ca, cb) = closest_between_segments(p1, q1, p2, q2);
+            sphere_sphere(ca, *ra, cb, *rb).into_iter().collect()
+        },
+        (Shape::Capsule {radius, half_height}, Shape::Cuboid {half_extents}) => {
+            let (p, q) = capsule_segment(a, *half_height);
+            [p, (p + q) * 0.5, q].iter()
+                .filter_map(|center| sphere_cuboid(*center, *radius, b, *half_extents))
+                .collect()
+        },
+        (Shape::Cuboid {half_extents}, Shape::Plane {normal, offset}) => {
+            cuboid_plane(a, *half_extents, *normal, *offset)
+        },
+        (Shape::Cuboid {half_extents: ha}, Shape::Cuboid {half_extents: hb}) => {
+            cuboid_cuboid(a, *ha, b, *hb)
+        },
+        (Shape::Plane {..}, Shape::Plane {..}) => Vec::new(),
+        (_, Shape::Heightfield {field}) => mesh_contacts(a, b, |aabb| field.triangles_in(aabb)),
+        (_, Shape::TriMesh {mesh}) => mesh_contacts(a, b, |aabb| mesh.triangles_in(aabb)),
+        _ => collide(b, a).into_iter().map(ContactPoint::flipped).collect()
+    }
+}
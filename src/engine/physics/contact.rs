@@ -0,0 +1,128 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::body::{BodyHandle, RigidBody};
+use super::collider::ContactPoint;
+
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub body_a: BodyHandle,
+    pub body_b: BodyHandle,
+    pub point: Vector3<f64>,
+    // From `body_a` to `body_b`
+    pub normal: Vector3<f64>,
+    pub depth: f64,
+
+    // Accumulated normal impulse of the last step
+    pub impulse: f64,
+    tangents: [Vector3<f64>; 2],
+    tangent_impulse: [f64; 2],
+    bias: f64
+}
+
+impl Contact {
+    pub fn new(body_a: BodyHandle, body_b: BodyHandle, point: ContactPoint) -> Self {
+        Self {
+            body_a,
+            body_b,
+            point: point.point,
+            normal: point.normal,
+            depth: point.depth,
+            impulse: 0.0,
+            tangents: [Vector3::zero(); 2],
+            tangent_impulse: [0.0; 2],
+            bias: 0.0
+        }
+    }
+
+    /// Normal and friction impulse applied to `body_b` in the last step, `body_a` gets the opposite.
+    pub fn total_impulse(&self) -> Vector3<f64> {
+        self.normal * self.impulse + self.tangents[0] * self.tangent_impulse[0] + self.tangents[1] * self.tangent_impulse[1]
+    }
+
+    fn offsets(&self, bodies: &[RigidBody]) -> (Vector3<f64>, Vector3<f64>) {
+        (self.point - bodies[self.body_a.0].position, self.point - bodies[self.body_b.0].position)
+    }
+
+    fn relative_velocity(&self, bodies: &[RigidBody], ra: Vector3<f64>, rb: Vector3<f64>) -> Vector3<f64> {
+        bodies[self.body_b.0].velocity_at(rb) - bodies[self.body_a.0].velocity_at(ra)
+    }
+
+    // Target separating velocity from restitution and penetration
+    pub(crate) fn prepare(&mut self, bodies: &[RigidBody], settings: &ContactSettings, delta: f64) {
+        let (ra, rb) = self.offsets(bodies);
+        let vn = self.relative_velocity(bodies, ra, rb).dot(self.normal);
+
+        let a = &bodies[self.body_a.0];
+        let b = &bodies[self.body_b.0];
+        let restitution = a.restitution.max(b.restitution);
+
+        let bounce = if vn < -settings.restitution_threshold { -restitution * vn } else { 0.0 };
+        let push = settings.baumgarte / delta * (self.depth - settings.slop).max(0.0);
+
+        self.bias = bounce.max(push);
+        self.impulse = 0.0;
+
+        // Friction basis
+        let helper = if self.normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let t1 = self.normal.cross(helper).normalize();
+        self.tangents = [t1, self.normal.cross(t1)];
+        self.tangent_impulse = [0.0; 2];
+    }
+
+    pub(crate) fn solve(&mut self, bodies: &mut [RigidBody]) {
+        let (a, b) = (self.body_a.0, self.body_b.0);
+        let (ra, rb) = self.offsets(bodies);
+
+        // Normal
+        let vn = self.relative_velocity(bodies, ra, rb).dot(self.normal);
+        let kn = bodies[a].effective_inv_mass(ra, self.normal) + bodies[b].effective_inv_mass(rb, self.normal);
+        if kn <= 1.0e-12 {
+            return;
+        }
+
+        let total = (self.impulse + (self.bias - vn) / kn).max(0.0);
+        let applied = total - self.impulse;
+        self.impulse = total;
+
+        bodies[a].apply_impulse_at(-self.normal * applied, ra);
+        bodies[b].apply_impulse_at(self.normal * applied, rb);
+
+        // Friction
+        let friction = (bodies[a].friction * bodies[b].friction).sqrt();
+        let max = friction * self.impulse;
+
+        for i in 0..2 {
+            let tangent = self.tangents[i];
+            let kt = bodies[a].effective_inv_mass(ra, tangent) + bodies[b].effective_inv_mass(rb, tangent);
+            if kt <= 1.0e-12 {
+                continue;
+            }
+
+            let vt = self.relative_velocity(bodies, ra, rb).dot(tangent);
+            let total = (self.tangent_impulse[i] - vt / kt).clamp(-max, max);
+            let applied = total - self.tangent_impulse[i];
+            self.tangent_impulse[i] = total;
+
+            bodies[a].apply_impulse_at(-tangent * applied, ra);
+            bodies[b].apply_impulse_at(tangent * applied, rb);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContactSettings {
+    pub baumgarte: f64,
+    pub slop: f64,
+    pub restitution_threshold: f64
+}
+
+impl ContactSettings {
+    pub fn new() -> Self {
+        Self {baumgarte: 0.1, slop: 0.005, restitution_threshold: 1.0}
+    }
+}
+
+impl Default for ContactSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,271 @@
+use std::f64::consts::PI;
+use cgmath::{InnerSpace, Rotation, Vector3};
+use super::PhysicsWorld;
+use super::body::RigidBody;
+use super::collider::{compute_aabb, Aabb, Shape};
+use super::mesh::Triangle;
+
+const CIRCLE_SEGMENTS: usize = 24;
+// Planes are drawn as a square of this half size around the point closest to the origin
+const PLANE_EXTENT: f64 = 10.0;
+// Mesh colliders are only drawn up to this many triangles
+const MAX_MESH_TRIANGLES: usize = 4096;
+
+pub const SLEEPING_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+pub const COLLIDER_COLOR: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+pub const STATIC_COLOR: [f32; 4] = [0.2, 0.6, 1.0, 1.0];
+pub const AABB_COLOR: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+pub const CONTACT_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+pub const JOINT_COLOR: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+pub const VELOCITY_COLOR: [f32; 4] = [0.2, 1.0, 1.0, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugLine {
+    pub start: Vector3<f64>,
+    pub end: Vector3<f64>,
+    pub color: [f32; 4]
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugDrawOptions {
+    pub colliders: bool,
+    pub aabbs: bool,
+    pub contacts: bool,
+    pub joints: bool,
+    pub velocities: bool,
+    // Length of the velocity lines per m/s, and of the contact normals
+    pub velocity_scale: f64,
+    pub normal_length: f64
+}
+
+impl DebugDrawOptions {
+    pub fn new() -> Self {
+        Self {
+            colliders: true,
+            aabbs: false,
+            contacts: true,
+            joints: true,
+            velocities: true,
+            velocity_scale: 0.1,
+            normal_length: 0.3
+        }
+    }
+}
+
+impl Default for DebugDrawOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lines showing the physics state, rebuilt every frame. Frames can be recorded for
+/// inspection without a window.
+pub struct DebugDraw {
+    pub options: DebugDrawOptions,
+
+    // Private
+    lines: Vec<DebugLine>,
+    recording: Option<Vec<Vec<DebugLine>>>
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {options: DebugDrawOptions::new(), lines: Vec::new(), recording: None}
+    }
+
+    pub fn lines(&self) -> &Vec<DebugLine> {
+        &self.lines
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn line(&mut self, start: Vector3<f64>, end: Vector3<f64>, color: [f32; 4]) {
+        self.lines.push(DebugLine {start, end, color});
+    }
+
+    /// Every frame drawn from now on is kept until `stop_recording`.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<Vec<DebugLine>> {
+        self.recording.take().unwrap_or_default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Replaces the lines with the current state of the world.
+    pub fn draw_world(&mut self, physics: &PhysicsWorld) {
+        self.clear();
+        let bodies = physics.bodies();
+
+        for body in bodies.iter() {
+            if self.options.colliders {
+                self.draw_collider(body);
+            }
+
+            if self.options.aabbs {
+                if let Some(aabb) = compute_aabb(body).filter(|_| !matches!(body.shape(), Some(Shape::Plane {..}))) {
+                    self.draw_aabb(&aabb, AABB_COLOR);
+                }
+            }
+
+            if self.options.velocities && body.is_dynamic() && !body.is_sleeping() {
+                self.line(body.position, body.position + body.velocity * self.options.velocity_scale, VELOCITY_COLOR);
+            }
+        }
+
+        if self.options.contacts {
+            for contact in physics.contacts().iter() {
+                let size = 0.05;
+                for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                    self.line(contact.point - axis * size, contact.point + axis * size, CONTACT_COLOR);
+                }
+                self.line(contact.point, contact.point + contact.normal * self.options.normal_length, CONTACT_COLOR);
+            }
+        }
+
+        if self.options.joints {
+            for joint in physics.joints().iter().filter(|j| j.enabled) {
+                let (pa, _, pb, _) = joint.anchors(bodies);
+                let a = &bodies[joint.body_a.0];
+
+                self.line(a.position, pa, JOINT_COLOR);
+                if let Some(b) = joint.body_b.map(|h| &bodies[h.0]) {
+                    self.line(b.position, pb, JOINT_COLOR);
+                }
+                self.line(pa, pb, JOINT_COLOR);
+
+                // Axes of the first body at its anchor
+                let size = self.options.normal_length;
+                self.line(pa, pa + a.rotation.rotate_vector(Vector3::unit_x()) * size, [1.0, 0.0, 0.0, 1.0]);
+                self.line(pa, pa + a.rotation.rotate_vector(Vector3::unit_y()) * size, [0.0, 1.0, 0.0, 1.0]);
+                self.line(pa, pa + a.rotation.rotate_vector(Vector3::unit_z()) * size, [0.0, 0.0, 1.0, 1.0]);
+            }
+        }
+
+        if let Some(frames) = self.recording.as_mut() {
+            frames.push(self.lines.clone());
+        }
+    }
+
+    fn draw_aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+        let corner = |i: usize| Vector3::new(
+            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if i & 4 == 0 { aabb.min.z } else { aabb.max.z }
+        );
+
+        // Corners that differ in a single bit share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    fn draw_circle(&mut self, body: &RigidBody, center: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>, radius: f64, color: [f32; 4]) {
+        let point = |i: usize| {
+            let angle = 2.0 * PI * i as f64 / CIRCLE_SEGMENTS as f64;
+            body.local_to_world(center + (u * angle.cos() + v * angle.sin()) * radius)
+        };
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    fn draw_triangle(&mut self, triangle: &Triangle, color: [f32; 4]) {
+        self.line(triangle.a, triangle.b, color);
+        self.line(triangle.b, triangle.c, color);
+        self.line(triangle.c, triangle.a, color);
+    }
+
+    fn draw_collider(&mut self, body: &RigidBody) {
+        let frame = body.collider_frame();
+        let body = frame.as_ref();
+        let color = if body.is_sleeping() {
+            SLEEPING_COLOR
+        } else if body.is_dynamic() {
+            COLLIDER_COLOR
+        } else {
+            STATIC_COLOR
+        };
+
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+
+        match body.shape() {
+            Some(Shape::Sphere {radius}) => {
+                let center = Vector3::new(0.0, 0.0, 0.0);
+                self.draw_circle(body, center, x, y, *radius, color);
+                self.draw_circle(body, center, y, z, *radius, color);
+                self.draw_circle(body, center, z, x, *radius, color);
+            },
+            Some(Shape::Cuboid {half_extents}) => {
+                let h = *half_extents;
+                let corner = |i: usize| body.local_to_world(Vector3::new(
+                    if i & 1 == 0 { -h.x } else { h.x },
+                    if i & 2 == 0 { -h.y } else { h.y },
+                    if i & 4 == 0 { -h.z } else { h.z }
+                ));
+
+                for i in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if i & bit == 0 {
+                            self.line(corner(i), corner(i | bit), color);
+                        }
+                    }
+                }
+            },
+            Some(Shape::Capsule {radius, half_height}) => {
+                let (r, h) = (*radius, *half_height);
+                for end in [-h, h] {
+                    let center = y * end;
+                    self.draw_circle(body, center, z, x, r, color);
+                    self.draw_circle(body, center, x, y, r, color);
+                    self.draw_circle(body, center, y, z, r, color);
+                }
+
+                for side in [x, -x, z, -z] {
+                    self.line(body.local_to_world(side * r - y * h), body.local_to_world(side * r + y * h), color);
+                }
+            },
+            Some(Shape::Plane {normal, offset}) => {
+                let center = normal * *offset;
+                let helper = if normal.x.abs() < 0.9 { x } else { y };
+                let u = normal.cross(helper).normalize() * PLANE_EXTENT;
+                let v = normal.cross(u);
+
+                let corners = [center + u + v, center - u + v, center - u - v, center + u - v];
+                for i in 0..4 {
+                    self.line(corners[i], corners[(i + 1) % 4], color);
+                }
+                self.line(center, center + normal * self.options.normal_length, color);
+            },
+            Some(Shape::Heightfield {field}) => {
+                let triangles = field.triangles_in(&field.aabb());
+                for triangle in triangles.iter().take(MAX_MESH_TRIANGLES) {
+                    self.draw_triangle(&triangle.map(|p| body.local_to_world(p)), color);
+                }
+            },
+            Some(Shape::TriMesh {mesh}) => {
+                for i in 0..mesh.triangle_count().min(MAX_MESH_TRIANGLES) {
+                    self.draw_triangle(&mesh.triangle(i).map(|p| body.local_to_world(p)), color);
+                }
+            },
+            None => ()
+        }
+    }
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
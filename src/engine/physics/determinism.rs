@@ -0,0 +1,80 @@
+use cgmath::{Quaternion, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepMode {
+    // Every update is a single step of the given delta
+    Variable,
+    // Updates are split into steps of `timestep`, the remainder is carried over
+    Fixed { timestep: f64, max_substeps: usize }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatMode {
+    Native,
+    // State is snapped to multiples of 2^-bits after every step, which makes it cheaper
+    // to compare and hash. It does not hide rounding differences, values near a rounding
+    // boundary can still snap apart
+    Quantized { bits: i32 }
+}
+
+pub(crate) fn quantize(x: f64, bits: i32) -> f64 {
+    let scale = 2f64.powi(bits);
+    (x * scale).round() / scale
+}
+
+pub(crate) fn quantize_vector(v: Vector3<f64>, bits: i32) -> Vector3<f64> {
+    Vector3::new(quantize(v.x, bits), quantize(v.y, bits), quantize(v.z, bits))
+}
+
+pub(crate) fn quantize_quaternion(q: Quaternion<f64>, bits: i32) -> Quaternion<f64> {
+    Quaternion::from_sv(quantize(q.s, bits), quantize_vector(q.v, bits))
+}
+
+/// FNV-1a over the raw bits of the state, stable across runs and platforms.
+pub struct StateHasher {
+    hash: u64
+}
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self {hash: 0xcbf29ce484222325}
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        // -0.0 and 0.0 compare equal, hash them alike
+        let value = if value == 0.0 { 0.0 } else { value };
+        self.write_u64(value.to_bits());
+    }
+
+    pub fn write_vector(&mut self, v: Vector3<f64>) {
+        self.write_f64(v.x);
+        self.write_f64(v.y);
+        self.write_f64(v.z);
+    }
+
+    pub fn write_quaternion(&mut self, q: Quaternion<f64>) {
+        self.write_f64(q.s);
+        self.write_vector(q.v);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::body::RigidBody;
+use super::collider::{compute_aabb, point_distance, Aabb};
+use super::determinism::{quantize, quantize_vector, StateHasher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FluidHandle(pub usize);
+
+#[derive(Clone, Debug)]
+pub struct FluidParticle {
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+
+    // Private
+    density: f64,
+    pressure: f64,
+    acceleration: Vector3<f64>
+}
+
+impl FluidParticle {
+    pub fn new(position: Vector3<f64>, velocity: Vector3<f64>) -> Self {
+        Self {position, velocity, density: 0.0, pressure: 0.0, acceleration: Vector3::zero()}
+    }
+
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+
+    pub fn pressure(&self) -> f64 {
+        self.pressure
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FluidSettings {
+    // Kernel support radius
+    pub smoothing_radius: f64,
+    pub particle_mass: f64,
+    pub rest_density: f64,
+    // Pressure per unit of density above the rest density
+    pub stiffness: f64,
+    pub viscosity: f64,
+    pub surface_tension: f64,
+    // Color field gradients below this are not at the surface
+    pub surface_threshold: f64,
+    // Kept from the walls, also the collision radius
+    pub particle_radius: f64,
+    pub restitution: f64,
+    pub friction: f64,
+    pub substeps: usize
+}
+
+impl FluidSettings {
+    /// Water at a resolution where `spacing` is the distance between particles at rest.
+    pub fn water(spacing: f64) -> Self {
+        let rest_density = 1000.0;
+
+        Self {
+            smoothing_radius: 2.0 * spacing,
+            particle_mass: rest_density * spacing * spacing * spacing,
+            rest_density,
+            stiffness: 200.0,
+            viscosity: 2.0,
+            surface_tension: 0.0728,
+            surface_threshold: 7.065,
+            particle_radius: spacing / 2.0,
+            restitution: 0.1,
+            friction: 0.1,
+            substeps: 4
+        }
+    }
+}
+
+// Müller et al. 2003 kernels
+struct Kernels {
+    h: f64,
+    h2: f64,
+    poly6: f64,
+    poly6_grad: f64,
+    spiky_grad: f64,
+    viscosity_lap: f64
+}
+
+impl Kernels {
+    fn new(h: f64) -> Self {
+        let h2 = h * h;
+        let h6 = h2 * h2 * h2;
+        let h9 = h6 * h2 * h;
+
+        Self {
+            h,
+            h2,
+            poly6: 315.0 / (64.0 * PI * h9),
+            poly6_grad: -945.0 / (32.0 * PI * h9),
+            spiky_grad: -45.0 / (PI * h6),
+            viscosity_lap: 45.0 / (PI * h6)
+        }
+    }
+}
+
+/// Per particle neighbour lists in CSR layout, ordered by particle index.
+#[derive(Clone, Debug, Default)]
+pub struct Neighbours {
+    pub offsets: Vec<usize>,
+    pub indices: Vec<usize>
+}
+
+impl Neighbours {
+    pub fn of(&self, i: usize) -> &[usize] {
+        &self.indices[self.offsets[i]..self.offsets[i + 1]]
+    }
+}
+
+/// Runs the density, force and integration passes of all substeps of a step, the compute path
+/// in `engine::logic` plugs in here. `neighbours` and the external `accelerations` are built once
+/// at the start of the step and `delta` is the length of a substep.
+pub trait FluidBackend {
+    fn simulate(&self, fluid: &mut Fluid, neighbours: &Neighbours, accelerations: &[Vector3<f64>], substeps: usize, delta: f64);
+}
+
+pub type FluidBackendType = Box<dyn FluidBackend + Sync + Send>;
+
+#[derive(Clone, Debug)]
+pub struct Fluid {
+    pub particles: Vec<FluidParticle>,
+    pub settings: FluidSettings,
+    pub use_backend: bool
+}
+
+impl Fluid {
+    pub fn new(settings: FluidSettings) -> Self {
+        Self {particles: Vec::new(), settings, use_backend: false}
+    }
+
+    /// Box of particles on a grid, `spacing` apart.
+    pub fn block(min: Vector3<f64>, max: Vector3<f64>, spacing: f64, settings: FluidSettings) -> Self {
+        let mut fluid = Self::new(settings);
+
+        let count = |a: f64, b: f64| ((b - a) / spacing).floor().max(0.0) as usize + 1;
+        let (nx, ny, nz) = (count(min.x, max.x), count(min.y, max.y), count(min.z, max.z));
+
+        for y in 0..ny {
+            for z in 0..nz {
+                for x in 0..nx {
+                    let position = min + Vector3::new(x as f64, y as f64, z as f64) * spacing;
+                    fluid.add_particle(position, Vector3::zero());
+                }
+            }
+        }
+
+        fluid
+    }
+
+    pub fn add_particle(&mut self, position: Vector3<f64>, velocity: Vector3<f64>) {
+        self.particles.push(FluidParticle::new(position, velocity));
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let first = self.particles.first().map(|p| p.position).unwrap_or(Vector3::zero());
+        let mut aabb = Aabb::new(first, first);
+
+        for particle in self.particles.iter() {
+            aabb = aabb.merge(&Aabb::new(particle.position, particle.position));
+        }
+
+        let r = self.settings.particle_radius;
+        Aabb::new(aabb.min - Vector3::new(r, r, r), aabb.max + Vector3::new(r, r, r))
+    }
+
+    pub fn kinetic_energy(&self) -> f64 {
+        self.particles.iter().map(|p| 0.5 * self.settings.particle_mass * p.velocity.magnitude2()).sum()
+    }
+
+    pub fn neighbours(&self) -> Neighbours {
+        self.neighbours_within(self.settings.smoothing_radius)
+    }
+
+    /// Uniform grid with cells of `h`, ordered so the sums do not depend on hashing.
+    pub fn neighbours_within(&self, h: f64) -> Neighbours {
+        let cell = |p: Vector3<f64>| ((p.x / h).floor() as i64, (p.y / h).floor() as i64, (p.z / h).floor() as i64);
+
+        let mut grid: BTreeMap<(i64, i64, i64), Vec<usize>> = BTreeMap::new();
+        for (i, particle) in self.particles.iter().enumerate() {
+            grid.entry(cell(particle.position)).or_default().push(i);
+        }
+
+        let mut neighbours = Neighbours {offsets: vec![0], indices: Vec::new()};
+        let mut found = Vec::new();
+        for particle in self.particles.iter() {
+            let (cx, cy, cz) = cell(particle.position);
+
+            found.clear();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(indices) = grid.get(&(cx + dx, cy + dy, cz + dz)) {
+                            found.extend(indices.iter().filter(|j| {
+                                (self.particles[**j].position - particle.position).magnitude2() < h * h
+                            }));
+                        }
+                    }
+                }
+            }
+
+            found.sort();
+            neighbours.indices.extend(found.iter());
+            neighbours.offsets.push(neighbours.indices.len());
+        }
+
+        neighbours
+    }
+
+    fn compute_densities(&mut self, neighbours: &Neighbours, kernels: &Kernels) {
+        let settings = &self.settings;
+
+        let densities: Vec<f64> = (0..self.particles.len())
+            .map(|i| {
+                let pi = self.particles[i].position;
+                neighbours.of(i).iter()
+                    .map(|j| {
+                        let r2 = (pi - self.particles[*j].position).magnitude2();
+                        let x = kernels.h2 - r2;
+                        settings.particle_mass * kernels.poly6 * x * x * x
+                    })
+                    .sum()
+            })
+            .collect();
+
+        for (particle, density) in self.particles.iter_mut().zip(densities) {
+            particle.density = density;
+            // Negative pressure makes particles clump at the surface
+            particle.pressure = (settings.stiffness * (density - settings.rest_density)).max(0.0);
+        }
+    }
+
+    fn compute_forces(&mut self, neighbours: &Neighbours, kernels: &Kernels) {
+        let settings = &self.settings;
+        let m = settings.particle_mass;
+
+        let accelerations: Vec<Vector3<f64>> = (0..self.particles.len())
+            .map(|i| {
+                let a = &self.particles[i];
+                let mut pressure = Vector3::zero();
+                let mut viscosity = Vector3::zero();
+                let mut normal = Vector3::zero();
+                let mut curvature = 0.0;
+
+                for j in neighbours.of(i).iter().filter(|j| **j != i) {
+                    let b = &self.particles[*j];
+                    let d = a.position - b.position;
+                    let r = d.magnitude();
+                    let r2 = r * r;
+                    if b.density <= 0.0 {
+                        continue;
+                    }
+
+                    // Symmetric pressure, equal and opposite between the pair
+                    if r > 1.0e-9 {
+                        let grad = d / r * (kernels.spiky_grad * (kernels.h - r) * (kernels.h - r));
+                        pressure -= grad * (m * (a.pressure + b.pressure) / (2.0 * b.density));
+                    }
+
+                    viscosity += (b.velocity - a.velocity) * (m / b.density * kernels.viscosity_lap * (kernels.h - r));
+
+                    // Color field for the surface tension
+                    let x = kernels.h2 - r2;
+                    normal += d * (m / b.density * kernels.poly6_grad * x * x);
+                    curvature += m / b.density * kernels.poly6_grad * x * (3.0 * kernels.h2 - 7.0 * r2);
+                }
+
+                let mut force = pressure + viscosity * settings.viscosity;
+
+                let length = normal.magnitude();
+                if length > settings.surface_threshold {
+                    force -= normal / length * (settings.surface_tension * curvature);
+                }
+
+                if a.density > 0.0 { force / a.density } else { Vector3::zero() }
+            })
+            .collect();
+
+        for (particle, acceleration) in self.particles.iter_mut().zip(accelerations) {
+            particle.acceleration = acceleration;
+        }
+    }
+
+    // Pushes particles out of rigid colliders, dynamic bodies receive the reaction
+    fn solve_collisions(&mut self, bodies: &mut [RigidBody]) {
+        let bounds = self.aabb();
+        let settings = &self.settings;
+
+        for body in bodies.iter_mut() {
+            match compute_aabb(body) {
+                Some(aabb) if aabb.overlaps(&bounds) => (),
+                _ => continue
+            }
+
+            for particle in self.particles.iter_mut() {
+                let (distance, normal) = match point_distance(body, particle.position) {
+                    Some(result) => result,
+                    None => continue
+                };
+
+                let depth = settings.particle_radius - distance;
+                if depth <= 0.0 {
+                    continue;
+                }
+
+                particle.position += normal * depth;
+
+                let offset = particle.position - body.position;
+                let relative = particle.velocity - body.velocity_at(offset);
+                let vn = relative.dot(normal);
+                if vn >= 0.0 {
+                    continue;
+                }
+
+                let tangential = relative - normal * vn;
+                let change = -normal * (vn * (1.0 + settings.restitution)) - tangential * settings.friction.min(1.0);
+                particle.velocity += change;
+
+                if body.is_dynamic() {
+                    body.apply_particle_impulse(-change * settings.particle_mass, offset);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn step(
+        &mut self,
+        delta: f64,
+        acceleration: &dyn Fn(Vector3<f64>, Vector3<f64>) -> Vector3<f64>,
+        bodies: &mut [RigidBody],
+        backend: Option<&FluidBackendType>
+    ) {
+        if self.particles.is_empty() {
+            return;
+        }
+
+        if let Some(backend) = backend.filter(|_| self.use_backend) {
+            self.step_backend(backend, delta, acceleration, bodies);
+            return;
+        }
+
+        let substeps = self.settings.substeps.max(1);
+        let h = delta / substeps as f64;
+        let kernels = Kernels::new(self.settings.smoothing_radius);
+
+        for _ in 0..substeps {
+            let neighbours = self.neighbours();
+            self.compute_densities(&neighbours, &kernels);
+            self.compute_forces(&neighbours, &kernels);
+
+            // Symplectic Euler
+            for particle in self.particles.iter_mut() {
+                let external = acceleration(particle.position, particle.velocity);
+                particle.velocity += (particle.acceleration + external) * h;
+                particle.position += particle.velocity * h;
+            }
+
+            self.solve_collisions(bodies);
+        }
+    }
+
+    // All substeps go to the backend in one submission. The neighbour lists reach as far as
+    // pairs can close in during the step and collisions are resolved once at the end
+    fn step_backend(
+        &mut self,
+        backend: &FluidBackendType,
+        delta: f64,
+        acceleration: &dyn Fn(Vector3<f64>, Vector3<f64>) -> Vector3<f64>,
+        bodies: &mut [RigidBody]
+    ) {
+        let substeps = self.settings.substeps.max(1);
+        let accelerations: Vec<Vector3<f64>> = self.particles.iter()
+            .map(|p| acceleration(p.position, p.velocity))
+            .collect();
+
+        let speed = self.particles.iter().map(|p| p.velocity.magnitude()).fold(0.0, f64::max);
+        let neighbours = self.neighbours_within(self.settings.smoothing_radius + 2.0 * speed * delta);
+
+        backend.simulate(self, &neighbours, &accelerations, substeps, delta / substeps as f64);
+        self.solve_collisions(bodies);
+    }
+
+    pub fn set_accelerations(&mut self, densities: &[f64], accelerations: &[Vector3<f64>]) {
+        for ((particle, density), acceleration) in self.particles.iter_mut().zip(densities).zip(accelerations) {
+            particle.density = *density;
+            particle.pressure = (self.settings.stiffness * (density - self.settings.rest_density)).max(0.0);
+            particle.acceleration = *acceleration;
+        }
+    }
+
+    pub(crate) fn quantize(&mut self, bits: i32) {
+        for particle in self.particles.iter_mut() {
+            particle.position = quantize_vector(particle.position, bits);
+            particle.velocity = quantize_vector(particle.velocity, bits);
+            particle.density = quantize(particle.density, bits);
+        }
+    }
+
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        for particle in self.particles.iter() {
+            hasher.write_vector(particle.position);
+            hasher.write_vector(particle.velocity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+    use super::{Fluid, FluidSettings};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::body::RigidBody;
+    use crate::engine::physics::collider::Collider;
+
+    const TIMESTEP: f64 = 1.0 / 60.0;
+    const SPACING: f64 = 0.05;
+
+    fn run(physics: &mut PhysicsWorld, seconds: f64) {
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            physics.step(TIMESTEP);
+        }
+    }
+
+    // Open tank of 1 x 0.5 metres on the floor
+    fn tank() -> PhysicsWorld {
+        let mut physics = PhysicsWorld::new();
+        physics.add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+
+        for x in [-0.6, 0.6] {
+            physics.add_body(RigidBody::fixed(Vector3::new(x, 0.5, 0.0)).with_collider(Collider::cuboid(0.1, 0.5, 0.4), 1.0));
+        }
+        for z in [-0.35, 0.35] {
+            physics.add_body(RigidBody::fixed(Vector3::new(0.0, 0.5, z)).with_collider(Collider::cuboid(0.7, 0.5, 0.1), 1.0));
+        }
+        physics
+    }
+
+    #[test]
+    fn dam_break_stays_in_its_tank() {
+        let mut physics = tank();
+        let column = Fluid::block(Vector3::new(-0.45, 0.05, -0.2), Vector3::new(-0.1, 0.5, 0.2), SPACING, FluidSettings::water(SPACING));
+        let handle = physics.add_fluid(column);
+
+        run(&mut physics, 2.0);
+
+        let fluid = physics.get_fluid(handle).unwrap();
+        for particle in fluid.particles.iter() {
+            let p = particle.position;
+            assert!(p.x.abs() < 0.5 && p.z.abs() < 0.25 && p.y > 0.0 && p.y < 1.0, "particle left the tank at {:?}", p);
+        }
+
+        let front = fluid.particles.iter().map(|p| p.position.x).fold(f64::MIN, f64::max);
+        assert!(front > 0.3, "the column never collapsed, front at {}", front);
+    }
+
+    #[test]
+    fn settled_water_is_near_its_rest_density() {
+        let mut physics = tank();
+        let pool = Fluid::block(Vector3::new(-0.45, 0.05, -0.2), Vector3::new(0.45, 0.2, 0.2), SPACING, FluidSettings::water(SPACING));
+        let handle = physics.add_fluid(pool);
+
+        run(&mut physics, 3.0);
+
+        // Particles at the surface and the walls miss neighbours, the bulk has them all
+        let fluid = physics.get_fluid(handle).unwrap();
+        let bulk: Vec<f64> = fluid.particles.iter()
+            .filter(|p| p.position.x.abs() < 0.3 && p.position.z.abs() < 0.1 && p.position.y > 0.05 && p.position.y < 0.1)
+            .map(|p| p.density())
+            .collect();
+        assert!(!bulk.is_empty());
+
+        let mean = bulk.iter().sum::<f64>() / bulk.len() as f64;
+        assert!((mean - 1000.0).abs() < 100.0, "mean density {}", mean);
+    }
+}
@@ -0,0 +1,625 @@
+use cgmath::{InnerSpace, Rotation, Vector3, Zero};
+use super::body::{BodyHandle, RigidBody};
+use super::collider::{Aabb, Shape};
+use super::rng::Rng;
+
+pub type ForceType = Box<dyn ForceGenerator + Sync + Send>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ForceHandle(pub usize);
+
+pub trait ForceGenerator {
+    /// Accumulates forces on the bodies before velocities are integrated.
+    fn apply(&mut self, bodies: &mut [RigidBody], rng: &mut Rng, delta: f64);
+
+    /// Pairs of bodies coupled by this generator, they share a simulation island.
+    fn links(&self) -> Vec<(BodyHandle, BodyHandle)> {
+        Vec::new()
+    }
+
+    /// Acceleration of a free particle (soft bodies, fluids), zero by default.
+    fn particle_acceleration(&self, _position: Vector3<f64>, _velocity: Vector3<f64>) -> Vector3<f64> {
+        Vector3::zero()
+    }
+
+    /// Uniform acceleration this generator adds everywhere, zero by default.
+    fn gravity(&self) -> Vector3<f64> {
+        Vector3::zero()
+    }
+
+    /// Told the summed `gravity` of every generator before each step.
+    fn set_world_gravity(&mut self, _gravity: Vector3<f64>) { /* Empty */ }
+
+    // Internal state for snapshots
+    fn save_state(&self) -> Vec<f64> { Vec::new() }
+    fn load_state(&mut self, _state: &[f64]) { /* Empty */ }
+}
+
+fn is_active(body: &RigidBody) -> bool {
+    body.is_dynamic() && !body.is_sleeping()
+}
+
+pub struct Gravity {
+    pub acceleration: Vector3<f64>
+}
+
+impl Gravity {
+    pub fn new(acceleration: Vector3<f64>) -> Self {
+        Self {acceleration}
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+}
+
+impl ForceGenerator for Gravity {
+    fn apply(&mut self, bodies: &mut [RigidBody], _rng: &mut Rng, _delta: f64) {
+        for body in bodies.iter_mut().filter(|b| is_active(b)) {
+            let weight = self.acceleration * body.mass();
+            body.apply_force(weight);
+        }
+    }
+
+    fn particle_acceleration(&self, _position: Vector3<f64>, _velocity: Vector3<f64>) -> Vector3<f64> {
+        self.acceleration
+    }
+
+    fn gravity(&self) -> Vector3<f64> {
+        self.acceleration
+    }
+}
+
+// Linear and quadratic drag, angular drag is linear only
+pub struct Drag {
+    pub linear: f64,
+    pub quadratic: f64,
+    pub angular: f64
+}
+
+impl Drag {
+    pub fn new(linear: f64, quadratic: f64, angular: f64) -> Self {
+        Self {linear, quadratic, angular}
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+}
+
+impl ForceGenerator for Drag {
+    fn apply(&mut self, bodies: &mut [RigidBody], _rng: &mut Rng, _delta: f64) {
+        for body in bodies.iter_mut().filter(|b| is_active(b)) {
+            let velocity = body.velocity;
+            let force = -velocity * (self.linear + self.quadratic * velocity.magnitude());
+            let torque = -body.angular_velocity * self.angular;
+
+            body.apply_force(force);
+            body.apply_torque(torque);
+        }
+    }
+
+    fn particle_acceleration(&self, _position: Vector3<f64>, velocity: Vector3<f64>) -> Vector3<f64> {
+        -velocity * (self.linear + self.quadratic * velocity.magnitude())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SpringTarget {
+    Body(BodyHandle, Vector3<f64>),
+    Anchor(Vector3<f64>)
+}
+
+// Damped spring between a body point and another body or a world anchor
+pub struct Spring {
+    pub body: BodyHandle,
+    pub local_anchor: Vector3<f64>,
+    pub target: SpringTarget,
+    pub rest_length: f64,
+    pub stiffness: f64,
+    pub damping: f64
+}
+
+impl Spring {
+    pub fn new(body: BodyHandle, local_anchor: Vector3<f64>, target: SpringTarget, rest_length: f64, stiffness: f64, damping: f64) -> Self {
+        Self {body, local_anchor, target, rest_length, stiffness, damping}
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+}
+
+impl ForceGenerator for Spring {
+    fn apply(&mut self, bodies: &mut [RigidBody], _rng: &mut Rng, _delta: f64) {
+        // Handles of bodies that were never added leave the spring slack
+        let a = match bodies.get(self.body.0) {
+            Some(a) => a,
+            None => return
+        };
+        let pa = a.local_to_world(self.local_anchor);
+        let va = a.velocity_at(pa - a.position);
+
+        let (pb, vb, other) = match self.target {
+            SpringTarget::Body(handle, local) => {
+                let b = match bodies.get(handle.0) {
+                    Some(b) => b,
+                    None => return
+                };
+                let pb = b.local_to_world(local);
+                (pb, b.velocity_at(pb - b.position), Some(handle))
+            },
+            SpringTarget::Anchor(point) => (point, Vector3::zero(), None)
+        };
+
+        let awake = is_active(&bodies[self.body.0]) || other.map(|h| is_active(&bodies[h.0])).unwrap_or(false);
+        if !awake {
+            return;
+        }
+
+        let d = pb - pa;
+        let length = d.magnitude();
+        if length < 1.0e-9 {
+            return;
+        }
+
+        let direction = d / length;
+        let stretch = length - self.rest_length;
+        let speed = (vb - va).dot(direction);
+        let force = direction * (self.stiffness * stretch + self.damping * speed);
+
+        bodies[self.body.0].apply_force_at_point(force, pa);
+        if let Some(handle) = other {
+            bodies[handle.0].apply_force_at_point(-force, pb);
+        }
+    }
+
+    fn links(&self) -> Vec<(BodyHandle, BodyHandle)> {
+        match self.target {
+            SpringTarget::Body(handle, _) => vec![(self.body, handle)],
+            SpringTarget::Anchor(_) => Vec::new()
+        }
+    }
+}
+
+// Air drag towards the wind velocity, optionally limited to a region
+pub struct Wind {
+    pub velocity: Vector3<f64>,
+    pub coefficient: f64,
+    pub region: Option<Aabb>,
+    // Strength of random gusts, relative to the wind speed
+    pub turbulence: f64,
+    // How fast gusts change, in 1/s
+    pub gust_rate: f64,
+
+    gust: Vector3<f64>
+}
+
+impl Wind {
+    pub fn new(velocity: Vector3<f64>, coefficient: f64) -> Self {
+        Self {velocity, coefficient, region: None, turbulence: 0.0, gust_rate: 1.0, gust: Vector3::zero()}
+    }
+
+    pub fn with_region(mut self, region: Aabb) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_turbulence(mut self, turbulence: f64, gust_rate: f64) -> Self {
+        self.turbulence = turbulence;
+        self.gust_rate = gust_rate;
+        self
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+
+    fn contains(&self, point: Vector3<f64>) -> bool {
+        match &self.region {
+            Some(aabb) => aabb.overlaps(&Aabb::new(point, point)),
+            None => true
+        }
+    }
+}
+
+impl ForceGenerator for Wind {
+    fn apply(&mut self, bodies: &mut [RigidBody], rng: &mut Rng, delta: f64) {
+        if self.turbulence > 0.0 {
+            // Low-pass filtered random walk
+            let target = rng.unit_vector() * (self.turbulence * self.velocity.magnitude() * rng.next_f64());
+            let blend = (self.gust_rate * delta).min(1.0);
+            self.gust += (target - self.gust) * blend;
+        }
+
+        let wind = self.velocity + self.gust;
+        for body in bodies.iter_mut().filter(|b| is_active(b)) {
+            if !self.contains(body.position) {
+                continue;
+            }
+
+            let force = (wind - body.velocity) * self.coefficient;
+            body.apply_force(force);
+        }
+    }
+
+    fn particle_acceleration(&self, position: Vector3<f64>, velocity: Vector3<f64>) -> Vector3<f64> {
+        if !self.contains(position) {
+            return Vector3::zero();
+        }
+
+        (self.velocity + self.gust - velocity) * self.coefficient
+    }
+
+    fn save_state(&self) -> Vec<f64> {
+        vec![self.gust.x, self.gust.y, self.gust.z]
+    }
+
+    fn load_state(&mut self, state: &[f64]) {
+        if let [x, y, z] = state {
+            self.gust = Vector3::new(*x, *y, *z);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    InverseSquare
+}
+
+// Pulls bodies towards a point, negative strength pushes them away
+pub struct Attractor {
+    pub center: Vector3<f64>,
+    pub strength: f64,
+    pub radius: f64,
+    pub falloff: Falloff
+}
+
+impl Attractor {
+    pub fn new(center: Vector3<f64>, strength: f64, radius: f64, falloff: Falloff) -> Self {
+        Self {center, strength, radius, falloff}
+    }
+
+    pub fn repulsor(center: Vector3<f64>, strength: f64, radius: f64, falloff: Falloff) -> Self {
+        Self {center, strength: -strength, radius, falloff}
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+
+    fn acceleration(&self, position: Vector3<f64>) -> Vector3<f64> {
+        let d = self.center - position;
+        let distance = d.magnitude();
+        if distance < 1.0e-6 || distance > self.radius {
+            return Vector3::zero();
+        }
+
+        let scale = match self.falloff {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - distance / self.radius,
+            Falloff::InverseSquare => 1.0 / (distance * distance).max(1.0e-2)
+        };
+
+        d / distance * (self.strength * scale)
+    }
+}
+
+impl ForceGenerator for Attractor {
+    fn apply(&mut self, bodies: &mut [RigidBody], _rng: &mut Rng, _delta: f64) {
+        for body in bodies.iter_mut().filter(|b| is_active(b)) {
+            // Acceleration based, heavy and light bodies fall in alike
+            let force = self.acceleration(body.position) * body.mass();
+            body.apply_force(force);
+        }
+    }
+
+    fn particle_acceleration(&self, position: Vector3<f64>, _velocity: Vector3<f64>) -> Vector3<f64> {
+        self.acceleration(position)
+    }
+}
+
+// Sample points per axis of the grid that approximates a collider volume
+const VOLUME_SAMPLES: usize = 6;
+
+type InsideTest = Box<dyn Fn(Vector3<f64>) -> bool>;
+
+// Points evenly filling the collider in its local space and the size of their cells
+fn volume_samples(shape: &Shape) -> (Vec<Vector3<f64>>, f64) {
+    let (half, inside): (Vector3<f64>, InsideTest) = match shape {
+        Shape::Sphere {radius} => {
+            let r = *radius;
+            (Vector3::new(r, r, r), Box::new(move |p: Vector3<f64>| p.magnitude2() <= r * r))
+        },
+        Shape::Cuboid {half_extents} => (*half_extents, Box::new(|_| true)),
+        Shape::Capsule {radius, half_height} => {
+            let (r, h) = (*radius, *half_height);
+            let inside = move |p: Vector3<f64>| (p - Vector3::new(0.0, p.y.clamp(-h, h), 0.0)).magnitude2() <= r * r;
+            (Vector3::new(r, h + r, r), Box::new(inside))
+        },
+        _ => return (Vec::new(), 0.0)
+    };
+
+    let n = VOLUME_SAMPLES;
+    let cell = |i: usize, extent: f64| -extent + (2.0 * i as f64 + 1.0) * extent / n as f64;
+
+    let mut samples = Vec::new();
+    for i in 0..n {
+        for j in 0..n {
+            for k in 0..n {
+                let p = Vector3::new(cell(i, half.x), cell(j, half.y), cell(k, half.z));
+                if inside(p) {
+                    samples.push(p);
+                }
+            }
+        }
+    }
+
+    (samples, 2.0 * half.x.max(half.y).max(half.z) / n as f64)
+}
+
+// Body of water or another fluid, the top of the region is the surface. Bodies get buoyancy
+// from their submerged volume and drag towards the flow velocity
+pub struct FluidVolume {
+    pub region: Aabb,
+    pub density: f64,
+    pub flow: Vector3<f64>,
+    // Linear drag per second scaled by the displaced mass, angular drag per second
+    pub linear_drag: f64,
+    pub angular_drag: f64,
+
+    // Private
+    // Follows the gravity generators of the world, buoyancy pushes against it
+    gravity: Vector3<f64>
+}
+
+impl FluidVolume {
+    pub fn new(region: Aabb, density: f64) -> Self {
+        Self {region, density, flow: Vector3::zero(), linear_drag: 1.0, angular_drag: 0.5, gravity: Vector3::new(0.0, -9.81, 0.0)}
+    }
+
+    pub fn water(region: Aabb) -> Self {
+        Self::new(region, 1000.0)
+    }
+
+    pub fn with_flow(mut self, flow: Vector3<f64>) -> Self {
+        self.flow = flow;
+        self
+    }
+
+    pub fn with_drag(mut self, linear: f64, angular: f64) -> Self {
+        self.linear_drag = linear;
+        self.angular_drag = angular;
+        self
+    }
+
+    pub fn build(self) -> ForceType {
+        Box::new(self)
+    }
+
+    // Submerged part of a sample cell, blended across the surface so the forces stay smooth
+    fn immersion(&self, point: Vector3<f64>, cell: f64) -> f64 {
+        let (min, max) = (self.region.min, self.region.max);
+        if point.x < min.x || point.x > max.x || point.z < min.z || point.z > max.z || point.y < min.y {
+            return 0.0;
+        }
+
+        ((max.y - point.y) / cell + 0.5).clamp(0.0, 1.0)
+    }
+
+    /// Submerged volume of the body and the center of buoyancy.
+    pub fn submerged(&self, body: &RigidBody) -> Option<(f64, Vector3<f64>)> {
+        let collider = body.collider()?;
+        let (samples, cell) = volume_samples(&collider.shape);
+        if samples.is_empty() {
+            return None;
+        }
+
+        let frame = body.collider_frame();
+        let (count, sum) = samples.iter()
+            .map(|p| frame.local_to_world(*p))
+            .map(|p| (self.immersion(p, cell), p))
+            .fold((0.0, Vector3::zero()), |(count, sum), (weight, p)| (count + weight, sum + p * weight));
+
+        if count <= 0.0 {
+            return None;
+        }
+
+        Some((collider.volume() * count / samples.len() as f64, sum / count))
+    }
+}
+
+impl ForceGenerator for FluidVolume {
+    fn apply(&mut self, bodies: &mut [RigidBody], _rng: &mut Rng, _delta: f64) {
+        for body in bodies.iter_mut().filter(|b| is_active(b)) {
+            let (volume, center) = match self.submerged(body) {
+                Some(submerged) => submerged,
+                None => continue
+            };
+
+            let displaced = self.density * volume;
+            let relative = self.flow - body.velocity_at(center - body.position);
+            let force = -self.gravity * displaced + relative * (self.linear_drag * displaced);
+
+            // Angular drag is relative to the inertia, light small bodies stay stable
+            let fraction = body.collider().map(|c| volume / c.volume()).unwrap_or(0.0);
+            let spin = body.rotation.conjugate().rotate_vector(body.angular_velocity);
+            let momentum = Vector3::new(spin.x * body.inertia().x, spin.y * body.inertia().y, spin.z * body.inertia().z);
+            let torque = -body.rotation.rotate_vector(momentum) * (self.angular_drag * fraction);
+
+            body.apply_force_at_point(force, center);
+            body.apply_torque(torque);
+        }
+    }
+
+    fn set_world_gravity(&mut self, gravity: Vector3<f64>) {
+        self.gravity = gravity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use cgmath::{InnerSpace, Vector3, Zero};
+    use super::{Attractor, Drag, Falloff, FluidVolume, Gravity, Spring, SpringTarget, Wind};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::body::{BodyHandle, RigidBody};
+    use crate::engine::physics::collider::{Aabb, Collider};
+
+    const TIMESTEP: f64 = 1.0 / 240.0;
+
+    fn run(physics: &mut PhysicsWorld, seconds: f64) {
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            physics.step(TIMESTEP);
+        }
+    }
+
+    // No forces and no sleeping, only the generators under test move the bodies
+    fn empty() -> PhysicsWorld {
+        let mut physics = PhysicsWorld::new();
+        physics.clear_forces();
+        physics.settings.sleep_enabled = false;
+        physics
+    }
+
+    // Point mass without a collider, so bodies never touch
+    fn point(physics: &mut PhysicsWorld, position: Vector3<f64>, mass: f64) -> BodyHandle {
+        let mut body = RigidBody::dynamic(position);
+        body.set_mass_properties(mass, Vector3::new(mass, mass, mass));
+        physics.add_body(body)
+    }
+
+    #[test]
+    fn gravity_accelerates_every_mass_alike() {
+        let mut physics = empty();
+        physics.add_force(Gravity::new(Vector3::new(0.0, -2.0, 0.0)).build());
+        let light = point(&mut physics, Vector3::new(-5.0, 0.0, 0.0), 0.1);
+        let heavy = point(&mut physics, Vector3::new(5.0, 0.0, 0.0), 50.0);
+
+        run(&mut physics, 1.0);
+
+        for handle in [light, heavy] {
+            let velocity = physics.bodies()[handle.0].velocity;
+            assert!((velocity - Vector3::new(0.0, -2.0, 0.0)).magnitude() < 1.0e-9, "velocity {:?}", velocity);
+        }
+    }
+
+    #[test]
+    fn drag_limits_falling_to_terminal_velocity() {
+        let mut physics = empty();
+        physics.add_force(Gravity::new(Vector3::new(0.0, -9.81, 0.0)).build());
+        physics.add_force(Drag::new(2.0, 0.0, 0.5).build());
+        let handle = point(&mut physics, Vector3::zero(), 1.0);
+        physics.get_body_mut(handle).unwrap().angular_velocity = Vector3::new(0.0, 3.0, 0.0);
+
+        run(&mut physics, 10.0);
+
+        // m * g = k * v
+        let body = &physics.bodies()[handle.0];
+        assert!((body.velocity.y + 9.81 / 2.0).abs() < 1.0e-3, "velocity {:?}", body.velocity);
+        assert!(body.angular_velocity.magnitude() < 0.1, "still spinning at {:?}", body.angular_velocity);
+    }
+
+    #[test]
+    fn spring_oscillates_at_its_natural_frequency() {
+        let mut physics = empty();
+        let (mass, stiffness, rest_length) = (2.0, 50.0, 1.0);
+        let start = Vector3::new(1.2, 0.0, 0.0);
+        let handle = point(&mut physics, start, mass);
+        physics.add_force(Spring::new(handle, Vector3::zero(), SpringTarget::Anchor(Vector3::zero()), rest_length, stiffness, 0.0).build());
+
+        // Back at the start after one period, through the rest length half way
+        let period = 2.0 * PI * (mass / stiffness).sqrt();
+        run(&mut physics, period / 2.0);
+        let half = physics.bodies()[handle.0].position.x;
+        assert!((half - 0.8).abs() < 1.0e-2, "half a period at {}", half);
+
+        run(&mut physics, period / 2.0);
+        let full = physics.bodies()[handle.0].position.x;
+        assert!((full - start.x).abs() < 1.0e-2, "one period at {}", full);
+    }
+
+    #[test]
+    fn spring_between_bodies_keeps_momentum() {
+        let mut physics = empty();
+        let a = point(&mut physics, Vector3::new(-1.0, 0.0, 0.0), 1.0);
+        let b = point(&mut physics, Vector3::new(1.0, 0.0, 0.0), 3.0);
+        physics.add_force(Spring::new(a, Vector3::zero(), SpringTarget::Body(b, Vector3::zero()), 1.0, 20.0, 0.5).build());
+
+        run(&mut physics, 2.0);
+
+        let bodies = physics.bodies();
+        let momentum = bodies[a.0].velocity * bodies[a.0].mass() + bodies[b.0].velocity * bodies[b.0].mass();
+        assert!(momentum.magnitude() < 1.0e-9, "momentum {:?}", momentum);
+        assert!(bodies[a.0].velocity.magnitude() > 0.0, "the spring never pulled");
+    }
+
+    #[test]
+    fn spring_on_a_missing_body_does_nothing() {
+        let mut physics = empty();
+        let handle = point(&mut physics, Vector3::zero(), 1.0);
+        physics.add_force(Spring::new(BodyHandle(7), Vector3::zero(), SpringTarget::Body(handle, Vector3::zero()), 1.0, 20.0, 0.0).build());
+        physics.add_force(Spring::new(handle, Vector3::zero(), SpringTarget::Body(BodyHandle(7), Vector3::zero()), 1.0, 20.0, 0.0).build());
+
+        run(&mut physics, 0.5);
+        assert_eq!(physics.bodies()[handle.0].position, Vector3::zero());
+    }
+
+    #[test]
+    fn wind_only_blows_inside_its_region() {
+        let mut physics = empty();
+        let region = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        physics.add_force(Wind::new(Vector3::new(0.0, 0.0, 3.0), 4.0).with_region(region).build());
+        let inside = point(&mut physics, Vector3::new(0.0, -0.5, -0.9), 1.0);
+        let outside = point(&mut physics, Vector3::new(5.0, 0.0, 0.0), 1.0);
+
+        // Pushed up to the wind speed before leaving the region
+        run(&mut physics, 0.5);
+
+        let velocity = physics.bodies()[inside.0].velocity;
+        assert!(velocity.z > 2.5 && velocity.z <= 3.0, "velocity {:?}", velocity);
+        assert_eq!(physics.bodies()[outside.0].velocity, Vector3::zero());
+    }
+
+    #[test]
+    fn attractor_pulls_within_its_radius_and_repulsor_pushes() {
+        let mut physics = empty();
+        physics.add_force(Attractor::new(Vector3::zero(), 4.0, 5.0, Falloff::Constant).build());
+        physics.add_force(Attractor::repulsor(Vector3::new(20.0, 0.0, 0.0), 4.0, 5.0, Falloff::Linear).build());
+        let pulled = point(&mut physics, Vector3::new(0.0, 3.0, 0.0), 1.0);
+        let heavy = point(&mut physics, Vector3::new(0.0, -3.0, 0.0), 10.0);
+        let pushed = point(&mut physics, Vector3::new(22.0, 0.0, 0.0), 1.0);
+        let far = point(&mut physics, Vector3::new(10.0, 0.0, 0.0), 1.0);
+
+        physics.step(TIMESTEP);
+
+        // Constant falloff accelerates every mass by the strength
+        let bodies = physics.bodies();
+        let expected = 4.0 * TIMESTEP;
+        assert!((bodies[pulled.0].velocity.y + expected).abs() < 1.0e-9, "pulled {:?}", bodies[pulled.0].velocity);
+        assert!((bodies[heavy.0].velocity.y - expected).abs() < 1.0e-9, "heavy {:?}", bodies[heavy.0].velocity);
+
+        // Linear falloff at two fifths of the radius keeps three fifths of the strength
+        assert!((bodies[pushed.0].velocity.x - 0.6 * expected).abs() < 1.0e-9, "pushed {:?}", bodies[pushed.0].velocity);
+        assert_eq!(bodies[far.0].velocity, Vector3::zero());
+    }
+
+    #[test]
+    fn buoyancy_follows_world_gravity() {
+        let mut physics = PhysicsWorld::new();
+        physics.clear_forces();
+        physics.add_force(Gravity::new(Vector3::new(0.0, -3.71, 0.0)).build());
+        physics.add_force(FluidVolume::water(Aabb::new(Vector3::new(-10.0, -10.0, -10.0), Vector3::new(10.0, 0.0, 10.0))).build());
+
+        // As dense as the water, it neither sinks nor rises
+        let start = Vector3::new(0.0, -5.0, 0.0);
+        let crate_body = physics.add_body(RigidBody::dynamic(start).with_collider(Collider::cuboid(0.5, 0.5, 0.5), 1000.0));
+        assert!((physics.gravity() - Vector3::new(0.0, -3.71, 0.0)).magnitude() < 1.0e-12);
+
+        run(&mut physics, 1.0);
+        let drift = (physics.bodies()[crate_body.0].position - start).magnitude();
+        assert!(drift < 1.0e-6, "drifted {}", drift);
+    }
+}
@@ -0,0 +1,359 @@
+use cgmath::{InnerSpace, Matrix3, Quaternion, Rotation, SquareMatrix, Vector3, Zero};
+use super::body::{principal_axes, BodyHandle, RigidBody};
+use super::collider::{Aabb, Collider, Shape};
+use super::rng::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DestructibleHandle(pub usize);
+
+const EPSILON: f64 = 1.0e-9;
+
+type SupportFn = Box<dyn Fn(Vector3<f64>) -> f64>;
+
+/// Convex polyhedron, faces are counter-clockwise seen from the outside.
+#[derive(Clone, Debug)]
+pub struct ConvexCell {
+    pub faces: Vec<Vec<Vector3<f64>>>
+}
+
+impl ConvexCell {
+    pub fn cuboid(half: Vector3<f64>) -> Self {
+        let corner = |x: f64, y: f64, z: f64| Vector3::new(x * half.x, y * half.y, z * half.z);
+        let faces = vec![
+            vec![corner(1.0, -1.0, -1.0), corner(1.0, 1.0, -1.0), corner(1.0, 1.0, 1.0), corner(1.0, -1.0, 1.0)],
+            vec![corner(-1.0, -1.0, -1.0), corner(-1.0, -1.0, 1.0), corner(-1.0, 1.0, 1.0), corner(-1.0, 1.0, -1.0)],
+            vec![corner(-1.0, 1.0, -1.0), corner(-1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), corner(1.0, 1.0, -1.0)],
+            vec![corner(-1.0, -1.0, -1.0), corner(1.0, -1.0, -1.0), corner(1.0, -1.0, 1.0), corner(-1.0, -1.0, 1.0)],
+            vec![corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)],
+            vec![corner(-1.0, -1.0, -1.0), corner(-1.0, 1.0, -1.0), corner(1.0, 1.0, -1.0), corner(1.0, -1.0, -1.0)]
+        ];
+
+        Self {faces}
+    }
+
+    /// Cell around a collider shape, round shapes are approximated by their tangent planes.
+    pub fn from_shape(shape: &Shape) -> Option<Self> {
+        let (half, support): (Vector3<f64>, SupportFn) = match shape {
+            Shape::Cuboid {half_extents} => return Some(Self::cuboid(*half_extents)),
+            Shape::Sphere {radius} => {
+                let r = *radius;
+                (Vector3::new(r, r, r), Box::new(move |_| r))
+            },
+            Shape::Capsule {radius, half_height} => {
+                let (r, h) = (*radius, *half_height);
+                (Vector3::new(r, h + r, r), Box::new(move |n: Vector3<f64>| r + n.y.abs() * h))
+            },
+            _ => return None
+        };
+
+        let mut cell = Self::cuboid(half);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if (x, y, z) == (0, 0, 0) {
+                        continue;
+                    }
+
+                    let normal = Vector3::new(x as f64, y as f64, z as f64).normalize();
+                    cell = cell.clip(normal, support(normal))?;
+                }
+            }
+        }
+
+        Some(cell)
+    }
+
+    pub fn vertices(&self) -> Vec<Vector3<f64>> {
+        let mut vertices: Vec<Vector3<f64>> = Vec::new();
+        for p in self.faces.iter().flatten() {
+            if !vertices.iter().any(|v| (v - p).magnitude2() < EPSILON) {
+                vertices.push(*p);
+            }
+        }
+
+        vertices
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let vertices = self.vertices();
+        vertices.iter().fold(Aabb::new(vertices[0], vertices[0]), |aabb, p| aabb.merge(&Aabb::new(*p, *p)))
+    }
+
+    pub fn translated(&self, offset: Vector3<f64>) -> Self {
+        Self {faces: self.faces.iter().map(|f| f.iter().map(|p| p + offset).collect()).collect()}
+    }
+
+    /// Keeps the part with `normal . p <= offset`, None when nothing is left.
+    pub fn clip(&self, normal: Vector3<f64>, offset: f64) -> Option<Self> {
+        let distance = |p: &Vector3<f64>| p.dot(normal) - offset;
+
+        let mut faces = Vec::new();
+        let mut cap: Vec<Vector3<f64>> = Vec::new();
+
+        for face in self.faces.iter() {
+            // Sutherland-Hodgman against the plane
+            let mut clipped = Vec::new();
+            for (i, a) in face.iter().enumerate() {
+                let b = &face[(i + 1) % face.len()];
+                let (da, db) = (distance(a), distance(b));
+
+                if da <= 0.0 {
+                    clipped.push(*a);
+                }
+                if (da < 0.0 && db > 0.0) || (da > 0.0 && db < 0.0) {
+                    let p = a + (b - a) * (da / (da - db));
+                    clipped.push(p);
+                    cap.push(p);
+                } else if da.abs() <= EPSILON {
+                    cap.push(*a);
+                }
+            }
+
+            if clipped.len() >= 3 {
+                faces.push(clipped);
+            }
+        }
+
+        if faces.is_empty() {
+            return None;
+        }
+
+        // New face on the plane, sorted counter-clockwise around the normal
+        let mut points: Vec<Vector3<f64>> = Vec::new();
+        for p in cap {
+            if !points.iter().any(|q| (q - p).magnitude2() < EPSILON) {
+                points.push(p);
+            }
+        }
+
+        if points.len() >= 3 {
+            let center = points.iter().fold(Vector3::zero(), |sum, p| sum + p) / points.len() as f64;
+            let helper = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            let u = normal.cross(helper).normalize();
+            let v = normal.cross(u);
+
+            let angle = |p: &Vector3<f64>| (p - center).dot(v).atan2((p - center).dot(u));
+            points.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+
+            // Only when the plane really cut something off
+            if !self.faces.iter().flatten().all(|p| distance(p) <= EPSILON) {
+                faces.push(points);
+            }
+        }
+
+        Some(Self {faces})
+    }
+
+    /// Mass, center of mass and the inertia tensor around it, in the cell axes.
+    pub fn mass_properties(&self, density: f64) -> (f64, Vector3<f64>, Matrix3<f64>) {
+        let vertices = self.vertices();
+        let apex = vertices.iter().fold(Vector3::zero(), |sum, p| sum + p) / vertices.len() as f64;
+
+        let mut volume = 0.0;
+        let mut moment = Vector3::zero();
+        // Second moments around the apex
+        let mut second = Matrix3::zero();
+
+        for face in self.faces.iter() {
+            for i in 1..face.len().saturating_sub(1) {
+                let (a, b, c) = (face[0] - apex, face[i] - apex, face[i + 1] - apex);
+                let v = a.dot(b.cross(c)) / 6.0;
+                let sum = a + b + c;
+
+                volume += v;
+                moment += sum * (v / 4.0);
+
+                // Tetrahedron covariance with one vertex at the origin
+                let outer = |x: Vector3<f64>| Matrix3::from_cols(x * x.x, x * x.y, x * x.z);
+                second += (outer(a) + outer(b) + outer(c) + outer(sum)) * (v / 20.0);
+            }
+        }
+
+        if volume <= EPSILON {
+            return (0.0, apex, Matrix3::zero());
+        }
+
+        let offset = moment / volume;
+        let centered = second - Matrix3::from_cols(offset * offset.x, offset * offset.y, offset * offset.z) * volume;
+        let trace = centered.x.x + centered.y.y + centered.z.z;
+        let inertia = (Matrix3::from_value(trace) - centered) * density;
+
+        (volume * density, apex + offset, inertia)
+    }
+
+    pub fn rotated(&self, rotation: Quaternion<f64>) -> Self {
+        Self {faces: self.faces.iter().map(|f| f.iter().map(|p| rotation.rotate_vector(*p)).collect()).collect()}
+    }
+}
+
+/// Voronoi cells of the sites clipped to `bounds`, empty cells are dropped.
+pub fn voronoi_cells(bounds: &ConvexCell, sites: &[Vector3<f64>]) -> Vec<ConvexCell> {
+    let mut cells = Vec::new();
+
+    'sites: for (i, site) in sites.iter().enumerate() {
+        let mut cell = bounds.clone();
+
+        for (j, other) in sites.iter().enumerate() {
+            let d = other - site;
+            if i == j || d.magnitude2() < EPSILON {
+                continue;
+            }
+
+            let normal = d.normalize();
+            cell = match cell.clip(normal, normal.dot((site + other) * 0.5)) {
+                Some(cell) => cell,
+                None => continue 'sites
+            };
+        }
+
+        cells.push(cell);
+    }
+
+    cells
+}
+
+/// Random sites in the cell bounds, denser towards `focus`.
+pub fn random_sites(bounds: &ConvexCell, count: usize, focus: Vector3<f64>, rng: &mut Rng) -> Vec<Vector3<f64>> {
+    let aabb = bounds.aabb();
+
+    (0..count).map(|_| {
+        let uniform = Vector3::new(
+            rng.range(aabb.min.x, aabb.max.x),
+            rng.range(aabb.min.y, aabb.max.y),
+            rng.range(aabb.min.z, aabb.max.z)
+        );
+        focus + (uniform - focus) * rng.next_f64().sqrt()
+    }).collect()
+}
+
+#[derive(Clone, Debug)]
+pub enum FracturePattern {
+    // Cells in the local space of the body, e.g. from `voronoi_cells`
+    Precomputed(Vec<ConvexCell>),
+    // Voronoi cells of random sites around the impact point
+    Runtime { pieces: usize }
+}
+
+/// Breaks its body into pieces once the contact impulse of a step exceeds the threshold.
+#[derive(Clone, Debug)]
+pub struct Destructible {
+    pub body: BodyHandle,
+    // Summed normal impulse of one step, in Ns
+    pub threshold: f64,
+    pub pattern: FracturePattern,
+
+    // Private
+    broken: bool
+}
+
+impl Destructible {
+    pub fn new(body: BodyHandle, threshold: f64, pattern: FracturePattern) -> Self {
+        Self {body, threshold, pattern, broken: false}
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Pieces of the body with their cells around their center of mass, in the principal axes of
+    /// the cell. The colliders are boxes of the cell volume along those axes, the mass properties
+    /// come from the cells.
+    pub(crate) fn fracture(&mut self, body: &RigidBody, point: Vector3<f64>, rng: &mut Rng) -> Vec<(RigidBody, ConvexCell)> {
+        let collider = match body.collider() {
+            Some(collider) => collider,
+            None => return Vec::new()
+        };
+
+        let cells = match &self.pattern {
+            FracturePattern::Precomputed(cells) => cells.clone(),
+            FracturePattern::Runtime {pieces} => match ConvexCell::from_shape(&collider.shape) {
+                Some(bounds) => {
+                    let sites = random_sites(&bounds, *pieces, body.world_to_local(point), rng);
+                    voronoi_cells(&bounds, &sites)
+                },
+                None => return Vec::new()
+            }
+        };
+
+        // The pieces share the mass of the body, also when the cells only approximate its shape
+        let volume: f64 = cells.iter().map(|c| c.mass_properties(1.0).0).sum();
+        if volume <= 0.0 {
+            return Vec::new();
+        }
+        let density = body.mass() / volume;
+
+        let mut pieces = Vec::new();
+        for cell in cells {
+            let (mass, center, inertia) = cell.mass_properties(density);
+            if mass <= 0.0 {
+                continue;
+            }
+
+            let (moments, axes) = principal_axes(inertia);
+            let cell = cell.translated(-center).rotated(axes.conjugate());
+            let aabb = cell.aabb();
+            let half = (aabb.max - aabb.min) * 0.5;
+            let scale = (mass / density / (8.0 * half.x * half.y * half.z)).cbrt();
+
+            let offset = body.rotation.rotate_vector(center);
+            let mut piece = RigidBody::dynamic(body.position + offset)
+                .with_collider(Collider::cuboid(half.x * scale, half.y * scale, half.z * scale), density);
+
+            piece.set_mass_properties(mass, moments);
+            piece.rotation = body.rotation * axes;
+            piece.velocity = body.velocity_at(offset);
+            piece.angular_velocity = body.angular_velocity;
+            piece.restitution = body.restitution;
+            piece.friction = body.friction;
+
+            pieces.push((piece, cell));
+        }
+
+        self.broken = !pieces.is_empty();
+        pieces
+    }
+}
+
+/// A body that broke in the last step, the first piece keeps the handle of the body.
+#[derive(Clone, Debug)]
+pub struct FractureEvent {
+    pub destructible: DestructibleHandle,
+    pub point: Vector3<f64>,
+    pub pieces: Vec<(BodyHandle, ConvexCell)>
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector3};
+    use super::ConvexCell;
+    use crate::engine::physics::body::principal_axes;
+
+    #[test]
+    fn rotated_cell_inertia_matches_box() {
+        let half = Vector3::new(1.0, 2.0, 3.0);
+        let rotation = Quaternion::from_axis_angle(Vector3::new(1.0, 2.0, 0.5).normalize(), Rad(0.7));
+        let cell = ConvexCell::cuboid(half).rotated(rotation).translated(Vector3::new(5.0, -1.0, 2.0));
+
+        let (mass, center, inertia) = cell.mass_properties(1.0);
+        let (moments, axes) = principal_axes(inertia);
+        let expected = Vector3::new(4.0 + 9.0, 1.0 + 9.0, 1.0 + 4.0) * (mass / 3.0);
+
+        assert!((mass - 48.0).abs() < 1.0e-9, "mass {}", mass);
+        assert!((center - Vector3::new(5.0, -1.0, 2.0)).magnitude() < 1.0e-9, "center {:?}", center);
+
+        // The moments come out in any order, the box in the principal axes has to match them
+        let aabb = cell.translated(-center).rotated(axes.conjugate()).aabb();
+        let fitted = (aabb.max - aabb.min) * 0.5;
+        let box_moments = Vector3::new(
+            fitted.y * fitted.y + fitted.z * fitted.z,
+            fitted.x * fitted.x + fitted.z * fitted.z,
+            fitted.x * fitted.x + fitted.y * fitted.y
+        ) * (mass / 3.0);
+
+        assert!((moments - box_moments).magnitude() < 1.0e-9, "moments {:?} box {:?}", moments, box_moments);
+        let (mut sorted, mut wanted) = ([moments.x, moments.y, moments.z], [expected.x, expected.y, expected.z]);
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        wanted.sort_by(|a, b| a.total_cmp(b));
+        assert!(sorted.iter().zip(wanted.iter()).all(|(a, b)| (a - b).abs() < 1.0e-9), "moments {:?} expected {:?}", moments, expected);
+    }
+}
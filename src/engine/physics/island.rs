@@ -0,0 +1,122 @@
+use super::body::{BodyHandle, RigidBody};
+use super::contact::Contact;
+use super::joint::Joint;
+
+// Disjoint set over body indices
+struct UnionFind {
+    parent: Vec<usize>
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {parent: (0..size).collect()}
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+
+        // Path compression
+        let mut current = x;
+        while self.parent[current] != root {
+            let next = self.parent[current];
+            self.parent[current] = root;
+            current = next;
+        }
+
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+
+        // Smaller index wins, keeps the result independent of the link order
+        if ra < rb {
+            self.parent[rb] = ra;
+        } else if rb < ra {
+            self.parent[ra] = rb;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Island {
+    pub bodies: Vec<BodyHandle>,
+    pub sleeping: bool
+}
+
+/// Groups dynamic bodies connected by contacts, joints or extra links. Static and
+/// kinematic bodies never link islands together.
+pub fn build_islands(bodies: &[RigidBody], contacts: &[Contact], joints: &[Joint], links: &[(BodyHandle, BodyHandle)]) -> Vec<Island> {
+    let mut sets = UnionFind::new(bodies.len());
+
+    let mut link = |a: usize, b: usize| {
+        if bodies[a].is_dynamic() && bodies[b].is_dynamic() {
+            sets.union(a, b);
+        }
+    };
+
+    for contact in contacts {
+        link(contact.body_a.0, contact.body_b.0);
+    }
+
+    for joint in joints.iter().filter(|j| j.enabled) {
+        if let Some(b) = joint.body_b {
+            link(joint.body_a.0, b.0);
+        }
+    }
+
+    for (a, b) in links.iter().filter(|(a, b)| a.0 < bodies.len() && b.0 < bodies.len()) {
+        link(a.0, b.0);
+    }
+
+    // Islands are ordered by their smallest body index
+    let mut islands: Vec<Island> = Vec::new();
+    let mut island_of_root = vec![usize::MAX; bodies.len()];
+
+    for (index, body) in bodies.iter().enumerate() {
+        if !body.is_dynamic() {
+            continue;
+        }
+
+        let root = sets.find(index);
+        if island_of_root[root] == usize::MAX {
+            island_of_root[root] = islands.len();
+            islands.push(Island {bodies: Vec::new(), sleeping: true});
+        }
+
+        let island = &mut islands[island_of_root[root]];
+        island.bodies.push(BodyHandle(index));
+        island.sleeping &= body.is_sleeping();
+    }
+
+    islands
+}
+
+/// Wakes every body of an island touched by an awake body.
+pub fn wake_islands(islands: &mut [Island], bodies: &mut [RigidBody]) {
+    for island in islands.iter_mut() {
+        if !island.sleeping && island.bodies.iter().any(|h| bodies[h.0].is_sleeping()) {
+            for handle in island.bodies.iter() {
+                bodies[handle.0].wake_up();
+            }
+        }
+    }
+}
+
+/// Puts islands to sleep once every body has been at rest for `time_to_sleep`.
+pub fn sleep_islands(islands: &mut [Island], bodies: &mut [RigidBody], time_to_sleep: f64) {
+    for island in islands.iter_mut().filter(|i| !i.sleeping) {
+        let rested = island.bodies.iter().all(|h| bodies[h.0].sleep_timer() >= time_to_sleep);
+        if !rested {
+            continue;
+        }
+
+        for handle in island.bodies.iter() {
+            bodies[handle.0].sleep();
+        }
+        island.sleeping = true;
+    }
+}
@@ -0,0 +1,585 @@
+use std::f64::consts::PI;
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+use super::body::{BodyHandle, RigidBody};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JointHandle(pub usize);
+
+// Position and speed error a PD motor leaves a sleeping island asleep with
+const MOTOR_SLEEP_TOLERANCE: f64 = 1.0e-2;
+
+#[derive(Clone, Debug)]
+pub enum JointKind {
+    // Anchors are kept at the same point
+    Ball,
+    // Anchors are kept at a fixed distance
+    Distance { length: f64 },
+    // Anchors and relative rotation are kept
+    Fixed,
+    // Rotation around `axis`, local to `body_a`. Limits are angles in radians
+    Hinge { axis: Vector3<f64>, limits: Option<(f64, f64)> },
+    // Sliding along `axis`, local to `body_a`, without rotation. Limits are distances
+    Prismatic { axis: Vector3<f64>, limits: Option<(f64, f64)> }
+}
+
+/// Drive of a hinge or prismatic joint, forces are torques for hinges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JointMotor {
+    // Holds the joint speed in rad/s or m/s
+    Velocity { speed: f64, max_force: f64 },
+    // Moves the joint to an angle or offset, no faster than `max_speed`
+    Position { target: f64, max_speed: f64, max_force: f64 },
+    // Force of kp * (target - position) + kd * (target_speed - speed), applied before
+    // the solver like any other force. Stiff gains need small timesteps
+    Pd { target: f64, target_speed: f64, kp: f64, kd: f64, max_force: f64 }
+}
+
+impl JointMotor {
+    pub fn velocity(speed: f64, max_force: f64) -> Self {
+        Self::Velocity {speed, max_force}
+    }
+
+    pub fn position(target: f64, max_speed: f64, max_force: f64) -> Self {
+        Self::Position {target, max_speed, max_force}
+    }
+
+    pub fn pd(target: f64, kp: f64, kd: f64, max_force: f64) -> Self {
+        Self::Pd {target, target_speed: 0.0, kp, kd, max_force}
+    }
+
+    /// Changes the target angle or offset of position and PD motors.
+    pub fn set_target(&mut self, position: f64) {
+        match self {
+            Self::Position {target, ..} | Self::Pd {target, ..} => *target = position,
+            Self::Velocity {..} => ()
+        }
+    }
+
+    pub fn max_force(&self) -> f64 {
+        match *self {
+            Self::Velocity {max_force, ..} | Self::Position {max_force, ..} | Self::Pd {max_force, ..} => max_force
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JointState {
+    pub position: f64,
+    pub speed: f64,
+    // Torque or force of the motor in the last step
+    pub motor_force: f64
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub kind: JointKind,
+    pub body_a: BodyHandle,
+    // None - attached to a world anchor
+    pub body_b: Option<BodyHandle>,
+    // Local to the body, or world space when there is no body
+    pub anchor_a: Vector3<f64>,
+    pub anchor_b: Vector3<f64>,
+    // Rotation of `body_b` in the frame of `body_a` at a joint angle of zero,
+    // None - taken from the bodies when the joint is added
+    pub rest_rotation: Option<Quaternion<f64>>,
+    // Contacts between the two bodies
+    pub collide_connected: bool,
+    pub motor: Option<JointMotor>,
+    // Caps every motor on the joint, e.g. the effort and velocity limits of an actuator
+    pub max_motor_force: f64,
+    pub max_motor_speed: f64,
+    pub enabled: bool,
+
+    // Private
+    motor_impulse: f64,
+    motor_force: f64
+}
+
+impl Joint {
+    pub fn new(kind: JointKind, body_a: BodyHandle, anchor_a: Vector3<f64>, body_b: Option<BodyHandle>, anchor_b: Vector3<f64>) -> Self {
+        Self {
+            kind, body_a, body_b, anchor_a, anchor_b,
+            rest_rotation: None, collide_connected: true, motor: None,
+            max_motor_force: f64::INFINITY, max_motor_speed: f64::INFINITY, enabled: true,
+            motor_impulse: 0.0, motor_force: 0.0
+        }
+    }
+
+    pub fn fixed(body_a: BodyHandle, body_b: BodyHandle, anchor_a: Vector3<f64>, anchor_b: Vector3<f64>) -> Self {
+        Self::new(JointKind::Fixed, body_a, anchor_a, Some(body_b), anchor_b)
+    }
+
+    pub fn hinge(body_a: BodyHandle, body_b: BodyHandle, anchor_a: Vector3<f64>, anchor_b: Vector3<f64>, axis: Vector3<f64>) -> Self {
+        Self::new(JointKind::Hinge {axis: axis.normalize(), limits: None}, body_a, anchor_a, Some(body_b), anchor_b)
+    }
+
+    pub fn prismatic(body_a: BodyHandle, body_b: BodyHandle, anchor_a: Vector3<f64>, anchor_b: Vector3<f64>, axis: Vector3<f64>) -> Self {
+        Self::new(JointKind::Prismatic {axis: axis.normalize(), limits: None}, body_a, anchor_a, Some(body_b), anchor_b)
+    }
+
+    /// Limits of hinges and prismatic joints, ignored by the other kinds.
+    pub fn with_limits(mut self, lower: f64, upper: f64) -> Self {
+        match &mut self.kind {
+            JointKind::Hinge {limits, ..} | JointKind::Prismatic {limits, ..} => *limits = Some((lower, upper)),
+            _ => ()
+        }
+        self
+    }
+
+    pub fn with_rest_rotation(mut self, rotation: Quaternion<f64>) -> Self {
+        self.rest_rotation = Some(rotation);
+        self
+    }
+
+    pub fn with_collide_connected(mut self, collide: bool) -> Self {
+        self.collide_connected = collide;
+        self
+    }
+
+    /// Motor of hinges and prismatic joints, ignored by the other kinds.
+    pub fn with_motor(mut self, motor: JointMotor) -> Self {
+        self.motor = Some(motor);
+        self
+    }
+
+    /// Force or torque and speed no motor on this joint goes past, whatever it is set to.
+    pub fn with_motor_limits(mut self, max_force: f64, max_speed: f64) -> Self {
+        self.max_motor_force = max_force;
+        self.max_motor_speed = max_speed;
+        self
+    }
+
+    /// Torque or force the motor applied along the axis in the last step.
+    pub fn motor_force(&self) -> f64 {
+        self.motor_force
+    }
+
+    fn rotations(&self, bodies: &[RigidBody]) -> (Quaternion<f64>, Quaternion<f64>) {
+        let identity = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        let qa = bodies[self.body_a.0].rotation;
+        let qb = self.body_b.map(|h| bodies[h.0].rotation).unwrap_or(identity);
+        (qa, qb)
+    }
+
+    /// Current rotation of `body_b` in the frame of `body_a`.
+    pub fn relative_rotation(&self, bodies: &[RigidBody]) -> Quaternion<f64> {
+        let (qa, qb) = self.rotations(bodies);
+        qa.conjugate() * qb
+    }
+
+    // Rotation that brings `body_b` from its rest rotation to the current one, in world space
+    fn rotation_error(&self, bodies: &[RigidBody]) -> Quaternion<f64> {
+        let (qa, qb) = self.rotations(bodies);
+        let rest = self.rest_rotation.unwrap_or(Quaternion::new(1.0, 0.0, 0.0, 0.0));
+        let error = qb * rest.conjugate() * qa.conjugate();
+
+        // Shortest way around
+        if error.s < 0.0 { -error } else { error }
+    }
+
+    /// Axis of hinges and prismatic joints in world space.
+    pub fn world_axis(&self, bodies: &[RigidBody]) -> Option<Vector3<f64>> {
+        match self.kind {
+            JointKind::Hinge {axis, ..} | JointKind::Prismatic {axis, ..} => Some(bodies[self.body_a.0].rotation.rotate_vector(axis)),
+            _ => None
+        }
+    }
+
+    /// Angle of a hinge or offset of a prismatic joint from its rest position.
+    pub fn position(&self, bodies: &[RigidBody]) -> Option<f64> {
+        let axis = self.world_axis(bodies)?;
+
+        match self.kind {
+            JointKind::Hinge {..} => {
+                let error = self.rotation_error(bodies);
+                let angle = 2.0 * error.v.dot(axis).atan2(error.s);
+                Some(if angle > PI { angle - 2.0 * PI } else { angle })
+            },
+            _ => {
+                let (pa, _, pb, _) = self.anchors(bodies);
+                Some((pb - pa).dot(axis))
+            }
+        }
+    }
+
+    /// Relative angular or linear speed along the axis of a hinge or prismatic joint.
+    pub fn speed(&self, bodies: &[RigidBody]) -> Option<f64> {
+        let axis = self.world_axis(bodies)?;
+        let a = &bodies[self.body_a.0];
+        let b = self.body_b.map(|h| &bodies[h.0]);
+
+        match self.kind {
+            JointKind::Hinge {..} => {
+                let wb = b.map(|b| b.angular_velocity).unwrap_or(Vector3::zero());
+                Some((wb - a.angular_velocity).dot(axis))
+            },
+            _ => {
+                let (_, ra, _, rb) = self.anchors(bodies);
+                let vb = b.map(|b| b.velocity_at(rb)).unwrap_or(Vector3::zero());
+                Some((vb - a.velocity_at(ra)).dot(axis))
+            }
+        }
+    }
+
+    pub(crate) fn capture_rest_rotation(&mut self, bodies: &[RigidBody]) {
+        if self.rest_rotation.is_none() {
+            self.rest_rotation = Some(self.relative_rotation(bodies));
+        }
+    }
+
+    pub fn ball(body_a: BodyHandle, body_b: BodyHandle, anchor_a: Vector3<f64>, anchor_b: Vector3<f64>) -> Self {
+        Self::new(JointKind::Ball, body_a, anchor_a, Some(body_b), anchor_b)
+    }
+
+    pub fn distance(body_a: BodyHandle, body_b: BodyHandle, length: f64) -> Self {
+        Self::new(JointKind::Distance {length}, body_a, Vector3::zero(), Some(body_b), Vector3::zero())
+    }
+
+    pub fn to_world(body_a: BodyHandle, anchor_a: Vector3<f64>, world_anchor: Vector3<f64>, kind: JointKind) -> Self {
+        Self::new(kind, body_a, anchor_a, None, world_anchor)
+    }
+
+    // World-space anchors and offsets from the centers of mass
+    pub(crate) fn anchors(&self, bodies: &[RigidBody]) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+        let a = &bodies[self.body_a.0];
+        let pa = a.local_to_world(self.anchor_a);
+
+        let (pb, rb) = match self.body_b {
+            Some(handle) => {
+                let b = &bodies[handle.0];
+                let pb = b.local_to_world(self.anchor_b);
+                (pb, pb - b.position)
+            },
+            None => (self.anchor_b, Vector3::zero())
+        };
+
+        (pa, pa - a.position, pb, rb)
+    }
+
+    /// Applies the force of a PD motor and resets the impulse of the others, once per step.
+    pub(crate) fn apply_motor(&mut self, bodies: &mut [RigidBody]) {
+        self.motor_impulse = 0.0;
+        self.motor_force = 0.0;
+
+        let (target, target_speed, kp, kd, max_force) = match self.motor {
+            Some(JointMotor::Pd {target, target_speed, kp, kd, max_force}) if self.enabled => {
+                (target, target_speed.clamp(-self.max_motor_speed, self.max_motor_speed), kp, kd, max_force.min(self.max_motor_force))
+            },
+            _ => return
+        };
+        let (axis, position, speed) = match (self.world_axis(bodies), self.position(bodies), self.speed(bodies)) {
+            (Some(axis), Some(position), Some(speed)) => (axis, position, speed),
+            _ => return
+        };
+
+        let force = (kp * (target - position) + kd * (target_speed - speed)).clamp(-max_force, max_force);
+        self.motor_force = force;
+
+        // Holding a settled joint would wake its island every step, new targets wake it on their own
+        let resting = |h: BodyHandle| !bodies[h.0].is_dynamic() || bodies[h.0].is_sleeping();
+        let asleep = resting(self.body_a) && self.body_b.is_none_or(resting);
+        if asleep && (target - position).abs() <= MOTOR_SLEEP_TOLERANCE && (target_speed - speed).abs() <= MOTOR_SLEEP_TOLERANCE {
+            return;
+        }
+
+        let (pa, _, pb, _) = self.anchors(bodies);
+        let hinge = matches!(self.kind, JointKind::Hinge {..});
+
+        if hinge {
+            bodies[self.body_a.0].apply_torque(-axis * force);
+        } else {
+            bodies[self.body_a.0].apply_force_at_point(-axis * force, pa);
+        }
+
+        if let Some(b) = self.body_b {
+            if hinge {
+                bodies[b.0].apply_torque(axis * force);
+            } else {
+                bodies[b.0].apply_force_at_point(axis * force, pb);
+            }
+        }
+    }
+
+    // Velocity and position motors, the total impulse of a step stays within the maximum force
+    fn solve_motor(&mut self, bodies: &mut [RigidBody], ra: Vector3<f64>, rb: Vector3<f64>, axis: Vector3<f64>, bias_factor: f64, delta: f64) {
+        let (target, max_force) = match self.motor {
+            Some(JointMotor::Velocity {speed, max_force}) => (speed, max_force),
+            Some(JointMotor::Position {target, max_speed, max_force}) => {
+                let error = target - self.position(bodies).unwrap();
+                ((bias_factor * error / delta).clamp(-max_speed, max_speed), max_force)
+            },
+            _ => return
+        };
+        let target = target.clamp(-self.max_motor_speed, self.max_motor_speed);
+        let max_force = max_force.min(self.max_motor_force);
+
+        let a = self.body_a.0;
+        let b = self.body_b.map(|h| h.0);
+        let max = max_force * delta;
+        let limit = Some((-max - self.motor_impulse, max - self.motor_impulse));
+
+        let impulse = match self.kind {
+            JointKind::Hinge {..} => solve_angular(bodies, a, b, axis, target, limit),
+            _ => solve_axis(bodies, a, b, ra, rb, axis, target, limit)
+        };
+
+        self.motor_impulse += impulse;
+        self.motor_force = self.motor_impulse / delta;
+    }
+
+    pub(crate) fn solve(&mut self, bodies: &mut [RigidBody], bias_factor: f64, delta: f64) {
+        if !self.enabled {
+            return;
+        }
+
+        let (pa, ra, pb, rb) = self.anchors(bodies);
+        let a = self.body_a.0;
+        let b = self.body_b.map(|h| h.0);
+
+        match self.kind {
+            JointKind::Ball => {
+                let error = pb - pa;
+                for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                    let bias = -bias_factor * error.dot(axis) / delta;
+                    solve_axis(bodies, a, b, ra, rb, axis, bias, None);
+                }
+            },
+            JointKind::Distance {length} => {
+                let d = pb - pa;
+                let current = d.magnitude();
+                if current < 1.0e-9 {
+                    return;
+                }
+
+                let axis = d / current;
+                let bias = -bias_factor * (current - length) / delta;
+                solve_axis(bodies, a, b, ra, rb, axis, bias, None);
+            },
+            JointKind::Fixed => {
+                let error = pb - pa;
+                let rotation = self.rotation_error(bodies).v * 2.0;
+
+                for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                    solve_angular(bodies, a, b, axis, -bias_factor * rotation.dot(axis) / delta, None);
+                    solve_axis(bodies, a, b, ra, rb, axis, -bias_factor * error.dot(axis) / delta, None);
+                }
+            },
+            JointKind::Hinge {limits, ..} => {
+                let axis = self.world_axis(bodies).unwrap();
+                let (u, v) = perpendicular(axis);
+                let rotation = self.rotation_error(bodies).v * 2.0;
+
+                // Only the rotation around the axis is free
+                for perp in [u, v] {
+                    solve_angular(bodies, a, b, perp, -bias_factor * rotation.dot(perp) / delta, None);
+                }
+
+                let error = pb - pa;
+                for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                    solve_axis(bodies, a, b, ra, rb, axis, -bias_factor * error.dot(axis) / delta, None);
+                }
+
+                // Limits come after the motor so they win
+                self.solve_motor(bodies, ra, rb, axis, bias_factor, delta);
+
+                if let Some((lower, upper)) = limits {
+                    let angle = self.position(bodies).unwrap();
+                    if angle < lower {
+                        solve_angular(bodies, a, b, axis, bias_factor * (lower - angle) / delta, Some((0.0, f64::MAX)));
+                    } else if angle > upper {
+                        solve_angular(bodies, a, b, axis, bias_factor * (upper - angle) / delta, Some((f64::MIN, 0.0)));
+                    }
+                }
+            },
+            JointKind::Prismatic {limits, ..} => {
+                let axis = self.world_axis(bodies).unwrap();
+                let (u, v) = perpendicular(axis);
+                let rotation = self.rotation_error(bodies).v * 2.0;
+
+                for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                    solve_angular(bodies, a, b, axis, -bias_factor * rotation.dot(axis) / delta, None);
+                }
+
+                // Only the translation along the axis is free
+                let error = pb - pa;
+                for perp in [u, v] {
+                    solve_axis(bodies, a, b, ra, rb, perp, -bias_factor * error.dot(perp) / delta, None);
+                }
+
+                self.solve_motor(bodies, ra, rb, axis, bias_factor, delta);
+
+                if let Some((lower, upper)) = limits {
+                    let offset = error.dot(axis);
+                    if offset < lower {
+                        solve_axis(bodies, a, b, ra, rb, axis, bias_factor * (lower - offset) / delta, Some((0.0, f64::MAX)));
+                    } else if offset > upper {
+                        solve_axis(bodies, a, b, ra, rb, axis, bias_factor * (upper - offset) / delta, Some((f64::MIN, 0.0)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Two unit vectors perpendicular to `axis` and to each other
+fn perpendicular(axis: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let helper = if axis.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = axis.cross(helper).normalize();
+    (u, axis.cross(u))
+}
+
+/// Drives the relative angular velocity of the bodies around `axis` towards `target`.
+/// Returns the applied angular impulse, optionally clamped to `limit`.
+pub(crate) fn solve_angular(
+    bodies: &mut [RigidBody],
+    a: usize,
+    b: Option<usize>,
+    axis: Vector3<f64>,
+    target: f64,
+    limit: Option<(f64, f64)>
+) -> f64 {
+    let wa = bodies[a].angular_velocity;
+    let wb = b.map(|b| bodies[b].angular_velocity).unwrap_or(Vector3::zero());
+
+    let k = bodies[a].inv_inertia_world(axis).dot(axis) + b.map(|b| bodies[b].inv_inertia_world(axis).dot(axis)).unwrap_or(0.0);
+    if k <= 1.0e-12 {
+        return 0.0;
+    }
+
+    let mut impulse = (target - (wb - wa).dot(axis)) / k;
+    if let Some((min, max)) = limit {
+        impulse = impulse.clamp(min, max);
+    }
+
+    bodies[a].apply_angular_impulse(-axis * impulse);
+    if let Some(b) = b {
+        bodies[b].apply_angular_impulse(axis * impulse);
+    }
+
+    impulse
+}
+
+/// Drives the relative velocity of two anchor points along `axis` towards `target`.
+/// Returns the applied impulse, optionally clamped to `limit`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn solve_axis(
+    bodies: &mut [RigidBody],
+    a: usize,
+    b: Option<usize>,
+    ra: Vector3<f64>,
+    rb: Vector3<f64>,
+    axis: Vector3<f64>,
+    target: f64,
+    limit: Option<(f64, f64)>
+) -> f64 {
+    let va = bodies[a].velocity_at(ra);
+    let vb = b.map(|b| bodies[b].velocity_at(rb)).unwrap_or(Vector3::zero());
+
+    let k = bodies[a].effective_inv_mass(ra, axis) + b.map(|b| bodies[b].effective_inv_mass(rb, axis)).unwrap_or(0.0);
+    if k <= 1.0e-12 {
+        return 0.0;
+    }
+
+    let mut impulse = (target - (vb - va).dot(axis)) / k;
+    if let Some((min, max)) = limit {
+        impulse = impulse.clamp(min, max);
+    }
+
+    bodies[a].apply_impulse_at(-axis * impulse, ra);
+    if let Some(b) = b {
+        bodies[b].apply_impulse_at(axis * impulse, rb);
+    }
+
+    impulse
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+    use super::{Joint, JointHandle, JointMotor};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::body::{BodyHandle, RigidBody};
+    use crate::engine::physics::collider::Collider;
+
+    const TIMESTEP: f64 = 1.0 / 240.0;
+
+    // A one metre arm hinged around z at the origin, without gravity
+    fn arm(motor: JointMotor) -> (PhysicsWorld, BodyHandle, JointHandle) {
+        let mut physics = PhysicsWorld::new();
+        physics.clear_forces();
+
+        let base = physics.add_body(RigidBody::fixed(Vector3::zero()));
+        let arm = physics.add_body(RigidBody::dynamic(Vector3::new(1.0, 0.0, 0.0)).with_collider(Collider::cuboid(0.5, 0.1, 0.1), 100.0));
+        let joint = Joint::hinge(base, arm, Vector3::zero(), Vector3::new(-1.0, 0.0, 0.0), Vector3::unit_z()).with_motor(motor);
+        let handle = physics.add_joint(joint).unwrap();
+
+        (physics, arm, handle)
+    }
+
+    #[test]
+    fn pd_hinge_settles_on_its_target_and_sleeps() {
+        let max_force = 20.0;
+        let (mut physics, arm, joint) = arm(JointMotor::pd(1.0, 60.0, 30.0, max_force));
+
+        for _ in 0..(4.0 / TIMESTEP) as usize {
+            physics.step(TIMESTEP);
+            let force = physics.get_joint(joint).unwrap().motor_force();
+            assert!(force.abs() <= max_force + 1.0e-9, "motor pushed with {}", force);
+        }
+
+        let state = physics.joint_state(joint).unwrap();
+        assert!((state.position - 1.0).abs() < 0.01, "settled at {}", state.position);
+
+        // A motor holding its target leaves the island asleep
+        for _ in 0..(2.0 / TIMESTEP) as usize {
+            physics.step(TIMESTEP);
+        }
+        assert!(physics.bodies()[arm.0].is_sleeping(), "the held arm never fell asleep");
+
+        // A new target wakes it again
+        physics.set_joint_target(joint, -0.5);
+        for _ in 0..(4.0 / TIMESTEP) as usize {
+            physics.step(TIMESTEP);
+        }
+        let state = physics.joint_state(joint).unwrap();
+        assert!((state.position + 0.5).abs() < 0.01, "settled at {}", state.position);
+    }
+
+    #[test]
+    fn velocity_motor_reaches_its_speed() {
+        let (mut physics, _, joint) = arm(JointMotor::velocity(2.0, 50.0));
+        physics.settings.sleep_enabled = false;
+
+        for _ in 0..(1.0 / TIMESTEP) as usize {
+            physics.step(TIMESTEP);
+        }
+
+        let state = physics.joint_state(joint).unwrap();
+        assert!((state.speed - 2.0).abs() < 1.0e-3, "spinning at {}", state.speed);
+    }
+
+    #[test]
+    fn motor_force_reports_the_clamped_force() {
+        // Saturated while speeding up, nothing left to push once at speed without any load
+        let (mut physics, _, joint) = arm(JointMotor::velocity(2.0, 5.0));
+        physics.settings.sleep_enabled = false;
+
+        physics.step(TIMESTEP);
+        let force = physics.joint_state(joint).unwrap().motor_force;
+        assert!((force - 5.0).abs() < 1.0e-9, "first step pushed with {}", force);
+
+        for _ in 0..(5.0 / TIMESTEP) as usize {
+            physics.step(TIMESTEP);
+        }
+        let state = physics.joint_state(joint).unwrap();
+        assert!((state.speed - 2.0).abs() < 1.0e-3, "spinning at {}", state.speed);
+        assert!(state.motor_force.abs() < 0.05, "still pushing with {}", state.motor_force);
+
+        // PD force is kp * error clamped to the joint limit below the motor's own
+        let (mut physics, _, joint) = arm(JointMotor::pd(1.0, 60.0, 12.0, 20.0));
+        physics.get_joint_mut(joint).unwrap().max_motor_force = 8.0;
+        physics.step(TIMESTEP);
+        let force = physics.joint_state(joint).unwrap().motor_force;
+        assert!((force - 8.0).abs() < 1.0e-9, "first step pushed with {}", force);
+    }
+}
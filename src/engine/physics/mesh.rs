@@ -0,0 +1,636 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::collider::Aabb;
+use super::query::ray_aabb;
+
+// Static level geometry, everything here is in the local space of the body
+
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    pub a: Vector3<f64>,
+    pub b: Vector3<f64>,
+    pub c: Vector3<f64>
+}
+
+impl Triangle {
+    pub fn new(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>) -> Self {
+        Self {a, b, c}
+    }
+
+    /// Counter-clockwise winding faces the normal.
+    pub fn normal(&self) -> Vector3<f64> {
+        let n = (self.b - self.a).cross(self.c - self.a);
+        let length = n.magnitude();
+        if length > 1.0e-12 { n / length } else { Vector3::unit_y() }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let (a, b, c) = (self.a, self.b, self.c);
+        Aabb::new(
+            Vector3::new(a.x.min(b.x).min(c.x), a.y.min(b.y).min(c.y), a.z.min(b.z).min(c.z)),
+            Vector3::new(a.x.max(b.x).max(c.x), a.y.max(b.y).max(c.y), a.z.max(b.z).max(c.z))
+        )
+    }
+
+    pub fn map(&self, f: impl Fn(Vector3<f64>) -> Vector3<f64>) -> Self {
+        Self::new(f(self.a), f(self.b), f(self.c))
+    }
+
+    // Ericson, Real-Time Collision Detection 5.1.5
+    pub fn closest_point(&self, p: Vector3<f64>) -> Vector3<f64> {
+        let (a, b, c) = (self.a, self.b, self.c);
+        let ab = b - a;
+        let ac = c - a;
+
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return a;
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denominator = 1.0 / (va + vb + vc);
+        a + ab * (vb * denominator) + ac * (vc * denominator)
+    }
+
+    /// Möller-Trumbore, both sides are hit and the normal faces the ray.
+    pub fn raycast(&self, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<(f64, Vector3<f64>)> {
+        let ab = self.b - self.a;
+        let ac = self.c - self.a;
+        let p = direction.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < 1.0e-12 {
+            return None;
+        }
+
+        let inv = 1.0 / det;
+        let s = origin - self.a;
+        let u = s.dot(p) * inv;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(ab);
+        let v = direction.dot(q) * inv;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(q) * inv;
+        if t < 0.0 || t > max_distance {
+            return None;
+        }
+
+        let normal = self.normal();
+        Some((t, if normal.dot(direction) > 0.0 { -normal } else { normal }))
+    }
+}
+
+fn point_aabb_distance2(aabb: &Aabb, p: Vector3<f64>) -> f64 {
+    let d = Vector3::new(
+        (aabb.min.x - p.x).max(0.0).max(p.x - aabb.max.x),
+        (aabb.min.y - p.y).max(0.0).max(p.y - aabb.max.y),
+        (aabb.min.z - p.z).max(0.0).max(p.z - aabb.max.z)
+    );
+    d.magnitude2()
+}
+
+/// Regular grid of heights, centered on the body in X and Z.
+#[derive(Clone, Debug)]
+pub struct Heightfield {
+    // Row major, rows run along Z
+    heights: Vec<f64>,
+    columns: usize,
+    rows: usize,
+    // Cell width, height multiplier, cell depth
+    scale: Vector3<f64>
+}
+
+impl Heightfield {
+    pub fn new(heights: Vec<Vec<f64>>, scale: Vector3<f64>) -> Self {
+        let rows = heights.len();
+        let columns = heights.first().map(|r| r.len()).unwrap_or(0);
+        assert!(rows >= 2 && columns >= 2, "a heightfield needs at least 2x2 samples");
+
+        let heights = heights.into_iter().flat_map(|r| r.into_iter().chain(std::iter::repeat(0.0)).take(columns)).collect();
+        Self {heights, columns, rows, scale}
+    }
+
+    /// 8 bit grayscale pixels, black is 0 and white is `scale.y`.
+    pub fn from_grayscale(pixels: &[u8], width: usize, height: usize, scale: Vector3<f64>) -> Self {
+        let heights = (0..height)
+            .map(|row| (0..width).map(|column| pixels[row * width + column] as f64 / 255.0).collect())
+            .collect();
+
+        Self::new(heights, scale)
+    }
+
+    /// Loads a binary (P5) or plain (P2) PGM image.
+    pub fn load_pgm(path: &Path, scale: Vector3<f64>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // Header: magic, width, height, max value, comments start with #
+        let mut fields = Vec::new();
+        let mut i = 0;
+        while fields.len() < 4 && i < data.len() {
+            match data[i] {
+                b'#' => while i < data.len() && data[i] != b'\n' { i += 1; },
+                c if c.is_ascii_whitespace() => i += 1,
+                _ => {
+                    let start = i;
+                    while i < data.len() && !data[i].is_ascii_whitespace() { i += 1; }
+                    fields.push(String::from_utf8_lossy(&data[start..i]).to_string());
+                }
+            }
+        }
+
+        if fields.len() < 4 {
+            return Err(invalid("truncated PGM header"));
+        }
+
+        let number = |s: &String| s.parse::<usize>().map_err(|_| invalid("bad PGM header"));
+        let (width, height, max) = (number(&fields[1])?, number(&fields[2])?, number(&fields[3])?.max(1));
+
+        let samples: Vec<usize> = match fields[0].as_str() {
+            "P5" => {
+                let body = &data[(i + 1).min(data.len())..];
+                if max < 256 {
+                    body.iter().map(|b| *b as usize).collect()
+                } else {
+                    body.chunks(2).map(|c| ((c[0] as usize) << 8) | *c.get(1).unwrap_or(&0) as usize).collect()
+                }
+            },
+            "P2" => String::from_utf8_lossy(&data[i..])
+                .split_whitespace()
+                .map(|s| s.parse::<usize>().map_err(|_| invalid("bad PGM sample")))
+                .collect::<io::Result<Vec<usize>>>()?,
+            _ => return Err(invalid("not a grayscale PGM"))
+        };
+
+        if samples.len() < width * height {
+            return Err(invalid("truncated PGM data"));
+        }
+
+        let heights = (0..height)
+            .map(|row| (0..width).map(|column| samples[row * width + column] as f64 / max as f64).collect())
+            .collect();
+
+        Ok(Self::new(heights, scale))
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn scale(&self) -> Vector3<f64> {
+        self.scale
+    }
+
+    /// Unscaled heights, one row per Z step.
+    pub fn heights(&self) -> Vec<Vec<f64>> {
+        self.heights.chunks(self.columns).map(|r| r.to_vec()).collect()
+    }
+
+    fn origin(&self) -> Vector3<f64> {
+        Vector3::new(-0.5 * (self.columns - 1) as f64 * self.scale.x, 0.0, -0.5 * (self.rows - 1) as f64 * self.scale.z)
+    }
+
+    pub fn vertex(&self, column: usize, row: usize) -> Vector3<f64> {
+        let origin = self.origin();
+        Vector3::new(
+            origin.x + column as f64 * self.scale.x,
+            self.heights[row * self.columns + column] * self.scale.y,
+            origin.z + row as f64 * self.scale.z
+        )
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let (low, high) = self.heights.iter().fold((f64::MAX, f64::MIN), |(l, h), y| (l.min(*y), h.max(*y)));
+        let origin = self.origin();
+        Aabb::new(
+            Vector3::new(origin.x, low * self.scale.y, origin.z),
+            Vector3::new(-origin.x, high * self.scale.y, -origin.z)
+        )
+    }
+
+    // Two triangles per cell, split along the same diagonal everywhere
+    fn cell_triangles(&self, column: usize, row: usize) -> [Triangle; 2] {
+        let p00 = self.vertex(column, row);
+        let p10 = self.vertex(column + 1, row);
+        let p01 = self.vertex(column, row + 1);
+        let p11 = self.vertex(column + 1, row + 1);
+
+        [Triangle::new(p00, p01, p10), Triangle::new(p10, p01, p11)]
+    }
+
+    fn cell_range(&self, min: f64, max: f64, origin: f64, size: f64, count: usize) -> Option<(usize, usize)> {
+        let first = ((min - origin) / size).floor();
+        let last = ((max - origin) / size).floor();
+        if last < 0.0 || first > (count - 2) as f64 {
+            return None;
+        }
+
+        Some((first.max(0.0) as usize, (last as usize).min(count - 2)))
+    }
+
+    pub fn triangles_in(&self, aabb: &Aabb) -> Vec<Triangle> {
+        let origin = self.origin();
+        let columns = self.cell_range(aabb.min.x, aabb.max.x, origin.x, self.scale.x, self.columns);
+        let rows = self.cell_range(aabb.min.z, aabb.max.z, origin.z, self.scale.z, self.rows);
+
+        let mut triangles = Vec::new();
+        if let (Some((c0, c1)), Some((r0, r1))) = (columns, rows) {
+            for row in r0..=r1 {
+                for column in c0..=c1 {
+                    triangles.extend(self.cell_triangles(column, row).into_iter().filter(|t| t.aabb().overlaps(aabb)));
+                }
+            }
+        }
+
+        triangles
+    }
+
+    /// Surface height and normal under the point, None outside of the grid.
+    pub fn height_at(&self, x: f64, z: f64) -> Option<(f64, Vector3<f64>)> {
+        let origin = self.origin();
+        let u = (x - origin.x) / self.scale.x;
+        let v = (z - origin.z) / self.scale.z;
+        if u < 0.0 || v < 0.0 || u > (self.columns - 1) as f64 || v > (self.rows - 1) as f64 {
+            return None;
+        }
+
+        let column = (u.floor() as usize).min(self.columns - 2);
+        let row = (v.floor() as usize).min(self.rows - 2);
+        let (fu, fv) = (u - column as f64, v - row as f64);
+
+        let triangle = self.cell_triangles(column, row)[if fu + fv <= 1.0 { 0 } else { 1 }];
+        let normal = triangle.normal();
+        let height = triangle.a.y - ((x - triangle.a.x) * normal.x + (z - triangle.a.z) * normal.z) / normal.y;
+
+        Some((height, normal))
+    }
+
+    /// Walks the cells under the ray, the first hit is the closest.
+    pub fn raycast(&self, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<(f64, Vector3<f64>)> {
+        let (near, far) = ray_aabb(&self.aabb(), origin, direction, max_distance)?;
+        let grid = self.origin();
+        let last = (self.columns as i64 - 2, self.rows as i64 - 2);
+
+        let entry = origin + direction * near;
+        let mut cell = (
+            (((entry.x - grid.x) / self.scale.x).floor() as i64).clamp(0, last.0),
+            (((entry.z - grid.z) / self.scale.z).floor() as i64).clamp(0, last.1)
+        );
+
+        let axis = |d: f64, p: f64, start: f64, size: f64, index: i64| -> (i64, f64, f64) {
+            if d > 1.0e-12 {
+                (1, (start + (index + 1) as f64 * size - p) / d, size / d)
+            } else if d < -1.0e-12 {
+                (-1, (start + index as f64 * size - p) / d, -size / d)
+            } else {
+                (0, f64::MAX, f64::MAX)
+            }
+        };
+
+        let (step_x, mut next_x, delta_x) = axis(direction.x, origin.x, grid.x, self.scale.x, cell.0);
+        let (step_z, mut next_z, delta_z) = axis(direction.z, origin.z, grid.z, self.scale.z, cell.1);
+
+        loop {
+            let hit = self.cell_triangles(cell.0 as usize, cell.1 as usize).iter()
+                .filter_map(|t| t.raycast(origin, direction, far))
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if hit.is_some() {
+                return hit;
+            }
+
+            if next_x < next_z {
+                cell.0 += step_x;
+                if next_x > far { return None; }
+                next_x += delta_x;
+            } else {
+                cell.1 += step_z;
+                if next_z > far { return None; }
+                next_z += delta_z;
+            }
+
+            if cell.0 < 0 || cell.1 < 0 || cell.0 > last.0 || cell.1 > last.1 {
+                return None;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct BvhNode {
+    aabb: Aabb,
+    // Children for inner nodes, a range of `order` for leaves
+    left: usize,
+    right: usize,
+    leaf: bool
+}
+
+const BVH_LEAF_SIZE: usize = 4;
+
+/// Triangle soup with a bounding volume hierarchy.
+#[derive(Clone, Debug)]
+pub struct TriMesh {
+    vertices: Vec<Vector3<f64>>,
+    indices: Vec<[usize; 3]>,
+    nodes: Vec<BvhNode>,
+    order: Vec<usize>
+}
+
+impl TriMesh {
+    pub fn new(vertices: Vec<Vector3<f64>>, indices: Vec<[usize; 3]>) -> Self {
+        assert!(!indices.is_empty(), "a triangle mesh needs at least one triangle");
+
+        let mut mesh = Self {vertices, indices, nodes: Vec::new(), order: Vec::new()};
+        mesh.order = (0..mesh.indices.len()).collect();
+
+        let count = mesh.order.len();
+        mesh.build(0, count);
+        mesh
+    }
+
+    pub fn triangle(&self, i: usize) -> Triangle {
+        let [a, b, c] = self.indices[i];
+        Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn vertices(&self) -> &Vec<Vector3<f64>> {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &Vec<[usize; 3]> {
+        &self.indices
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        self.nodes[0].aabb
+    }
+
+    // Median split along the longest axis of the centroids
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let aabb = self.order[start..end].iter()
+            .map(|i| self.triangle(*i).aabb())
+            .reduce(|a, b| a.merge(&b))
+            .unwrap();
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {aabb, left: start, right: end, leaf: true});
+        if end - start <= BVH_LEAF_SIZE {
+            return index;
+        }
+
+        let centroid = |i: usize| {
+            let t = self.triangle(i);
+            (t.a + t.b + t.c) / 3.0
+        };
+        let bounds = self.order[start..end].iter()
+            .map(|i| Aabb::new(centroid(*i), centroid(*i)))
+            .reduce(|a, b| a.merge(&b))
+            .unwrap();
+        let extent = bounds.max - bounds.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+        let mut order = self.order[start..end].to_vec();
+        order.sort_by(|a, b| centroid(*a)[axis].total_cmp(&centroid(*b)[axis]).then(a.cmp(b)));
+        self.order[start..end].copy_from_slice(&order);
+
+        let middle = (start + end) / 2;
+        let left = self.build(start, middle);
+        let right = self.build(middle, end);
+        self.nodes[index] = BvhNode {aabb, left, right, leaf: false};
+
+        index
+    }
+
+    pub fn triangles_in(&self, aabb: &Aabb) -> Vec<Triangle> {
+        let mut triangles = Vec::new();
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node.aabb.overlaps(aabb) {
+                continue;
+            }
+
+            if node.leaf {
+                triangles.extend(self.order[node.left..node.right].iter()
+                    .map(|i| self.triangle(*i))
+                    .filter(|t| t.aabb().overlaps(aabb)));
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+
+        triangles
+    }
+
+    pub fn raycast(&self, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<(f64, Vector3<f64>)> {
+        let mut best: Option<(f64, Vector3<f64>)> = None;
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let limit = best.map(|b| b.0).unwrap_or(max_distance);
+            if ray_aabb(&node.aabb, origin, direction, limit).is_none() {
+                continue;
+            }
+
+            if node.leaf {
+                for i in self.order[node.left..node.right].iter() {
+                    let limit = best.map(|b| b.0).unwrap_or(max_distance);
+                    if let Some(hit) = self.triangle(*i).raycast(origin, direction, limit) {
+                        best = Some(hit);
+                    }
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+
+        best
+    }
+
+    /// Closest point on the surface and the normal of its triangle.
+    pub fn closest_point(&self, p: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        let mut best = (f64::MAX, Vector3::zero(), Vector3::unit_y());
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if point_aabb_distance2(&node.aabb, p) >= best.0 {
+                continue;
+            }
+
+            if node.leaf {
+                for i in self.order[node.left..node.right].iter() {
+                    let triangle = self.triangle(*i);
+                    let q = triangle.closest_point(p);
+                    let distance = (p - q).magnitude2();
+                    if distance < best.0 {
+                        best = (distance, q, triangle.normal());
+                    }
+                }
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+
+        (best.1, best.2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+    use super::{Heightfield, TriMesh, Triangle};
+    use crate::engine::physics::collider::Aabb;
+
+    // Closest hit over every triangle, what the BVH and the grid walk must agree with
+    fn brute_force_raycast(triangles: &[Triangle], origin: Vector3<f64>, direction: Vector3<f64>) -> Option<f64> {
+        triangles.iter()
+            .filter_map(|t| t.raycast(origin, direction, 100.0).map(|hit| hit.0))
+            .min_by(|a, b| a.total_cmp(b))
+    }
+
+    #[test]
+    fn trimesh_bvh_matches_brute_force() {
+        let size = 12;
+        let mut vertices = Vec::new();
+        for z in 0..=size {
+            for x in 0..=size {
+                vertices.push(Vector3::new(x as f64, (x as f64 * 0.7).sin() + (z as f64 * 0.4).cos(), z as f64));
+            }
+        }
+        let mut indices = Vec::new();
+        for z in 0..size {
+            for x in 0..size {
+                let i = z * (size + 1) + x;
+                indices.push([i, i + size + 1, i + 1]);
+                indices.push([i + 1, i + size + 1, i + size + 2]);
+            }
+        }
+
+        let mesh = TriMesh::new(vertices, indices);
+        let triangles: Vec<_> = (0..mesh.triangle_count()).map(|i| mesh.triangle(i)).collect();
+
+        for i in 0..50 {
+            let origin = Vector3::new((i * 7 % 13) as f64 - 0.3, 5.0, (i * 5 % 11) as f64 + 0.6);
+            let direction = Vector3::new(((i % 5) as f64 - 2.0) * 0.3, -1.0, ((i % 3) as f64 - 1.0) * 0.4).normalize();
+
+            let hit = mesh.raycast(origin, direction, 100.0).map(|hit| hit.0);
+            let expected = brute_force_raycast(&triangles, origin, direction);
+            match (hit, expected) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1.0e-9, "ray {} hit {} expected {}", i, a, b),
+                (a, b) => assert_eq!(a.is_some(), b.is_some(), "ray {} hit {:?} expected {:?}", i, a, b)
+            }
+        }
+
+        let query = Aabb::new(Vector3::new(2.5, -3.0, 4.5), Vector3::new(6.2, 3.0, 7.1));
+        let expected = triangles.iter().filter(|t| t.aabb().overlaps(&query)).count();
+        assert_eq!(mesh.triangles_in(&query).len(), expected);
+    }
+
+    #[test]
+    fn heightfield_raycast_hits_the_surface() {
+        let heights = (0..8).map(|row| (0..10).map(|column| 0.1 * column as f64 + 0.05 * (row % 3) as f64).collect()).collect();
+        let field = Heightfield::new(heights, Vector3::new(1.0, 2.0, 1.5));
+        let all = field.triangles_in(&field.aabb());
+
+        // Straight down lands on the interpolated height
+        for (x, z) in [(0.0, 0.0), (-3.7, 2.2), (4.1, -4.9), (2.5, 1.25)] {
+            let (height, _) = field.height_at(x, z).unwrap();
+            let (distance, _) = field.raycast(Vector3::new(x, 10.0, z), -Vector3::unit_y(), 100.0).unwrap();
+            assert!((distance - (10.0 - height)).abs() < 1.0e-9, "at ({}, {}) distance {} height {}", x, z, distance, height);
+        }
+
+        // Grazing rays cross several cells before hitting
+        for i in 0..20 {
+            let origin = Vector3::new(-6.0 + 0.3 * i as f64, 3.0, -5.0 + 0.2 * i as f64);
+            let direction = Vector3::new(1.0, -0.25, 0.6 - 0.05 * i as f64).normalize();
+
+            let hit = field.raycast(origin, direction, 100.0).map(|hit| hit.0);
+            let expected = brute_force_raycast(&all, origin, direction);
+            match (hit, expected) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < 1.0e-9, "ray {} hit {} expected {}", i, a, b),
+                (a, b) => assert_eq!(a.is_some(), b.is_some(), "ray {} hit {:?} expected {:?}", i, a, b)
+            }
+        }
+
+        assert!(field.raycast(Vector3::new(20.0, 10.0, 0.0), -Vector3::unit_y(), 100.0).is_none());
+        assert!(field.raycast(Vector3::new(0.0, 10.0, 0.0), Vector3::unit_y(), 100.0).is_none());
+    }
+
+    #[test]
+    fn pgm_loads_plain_and_binary_images() {
+        let directory = std::env::temp_dir();
+        let plain = directory.join(format!("dengine_plain_{}.pgm", std::process::id()));
+        let binary = directory.join(format!("dengine_binary_{}.pgm", std::process::id()));
+
+        std::fs::write(&plain, "P2\n# a comment\n3 2\n4\n0 1 2\n3 4 2\n").unwrap();
+        let mut data = b"P5 3 2 255\n".to_vec();
+        data.extend_from_slice(&[0, 51, 102, 153, 204, 255]);
+        std::fs::write(&binary, data).unwrap();
+
+        let scale = Vector3::new(1.0, 2.0, 1.0);
+        let field = Heightfield::load_pgm(&plain, scale).unwrap();
+        assert_eq!((field.columns(), field.rows()), (3, 2));
+        assert_eq!(field.heights, vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.5]);
+        assert!((field.vertex(1, 1).y - 2.0).abs() < 1.0e-12);
+
+        let field = Heightfield::load_pgm(&binary, scale).unwrap();
+        assert_eq!((field.columns(), field.rows()), (3, 2));
+        assert!(field.heights.iter().zip([0.0, 0.2, 0.4, 0.6, 0.8, 1.0]).all(|(a, b)| (a - b).abs() < 1.0e-12));
+
+        std::fs::write(&plain, "P2\n3 2\n4\n0 1 2\n").unwrap();
+        assert_eq!(Heightfield::load_pgm(&plain, scale).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let _ = std::fs::remove_file(plain);
+        let _ = std::fs::remove_file(binary);
+    }
+}
@@ -0,0 +1,406 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::body::RigidBody;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // Second order, one force evaluation per step
+    Leapfrog,
+    // Fourth order, three leapfrog steps of Yoshida's weights
+    Yoshida4
+}
+
+impl Integrator {
+    // Drift and kick weights of the drift-kick-drift form
+    fn coefficients(&self) -> (Vec<f64>, Vec<f64>) {
+        match self {
+            Integrator::Leapfrog => (vec![0.5, 0.5], vec![1.0]),
+            Integrator::Yoshida4 => {
+                let cbrt2 = 2f64.cbrt();
+                let w1 = 1.0 / (2.0 - cbrt2);
+                let w0 = -cbrt2 / (2.0 - cbrt2);
+
+                (vec![w1 / 2.0, (w0 + w1) / 2.0, (w0 + w1) / 2.0, w1 / 2.0], vec![w1, w0, w1])
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NBodySettings {
+    pub gravitational_constant: f64,
+    // Plummer softening length, keeps close encounters finite
+    pub softening: f64,
+    // Barnes-Hut opening angle, 0 is exact
+    pub theta: f64,
+    // Below this many bodies the forces are summed directly
+    pub tree_threshold: usize,
+    pub integrator: Integrator
+}
+
+impl NBodySettings {
+    pub fn new() -> Self {
+        Self {
+            gravitational_constant: 6.674e-11,
+            softening: 0.0,
+            theta: 0.5,
+            tree_threshold: 1024,
+            integrator: Integrator::Yoshida4
+        }
+    }
+}
+
+impl Default for NBodySettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ForceModel {
+    // Force generators only, uniform gravity by default
+    Generators,
+    // Pairwise gravitation between dynamic bodies, integrated symplectically.
+    // Generators still apply, remove the uniform gravity for orbital scenes
+    NBody(NBodySettings)
+}
+
+/// Conserved quantities of the n-body system and their drift since the reference.
+#[derive(Clone, Debug)]
+pub struct NBodyStats {
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub momentum: Vector3<f64>,
+    pub angular_momentum: Vector3<f64>,
+
+    // Relative to the reference energy, the momenta drift in absolute terms
+    pub energy_drift: f64,
+    pub momentum_drift: f64,
+    pub angular_momentum_drift: f64,
+
+    // Private
+    reference: Option<(f64, Vector3<f64>, Vector3<f64>)>
+}
+
+impl NBodyStats {
+    pub fn new() -> Self {
+        Self {
+            kinetic_energy: 0.0,
+            potential_energy: 0.0,
+            momentum: Vector3::zero(),
+            angular_momentum: Vector3::zero(),
+            energy_drift: 0.0,
+            momentum_drift: 0.0,
+            angular_momentum_drift: 0.0,
+            reference: None
+        }
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// The next measurement becomes the reference for the drifts.
+    pub fn reset_reference(&mut self) {
+        self.reference = None;
+    }
+}
+
+impl Default for NBodyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Octree node, leaves hold their bodies and inner nodes their eight children
+struct Node {
+    center: Vector3<f64>,
+    half: f64,
+    mass: f64,
+    center_of_mass: Vector3<f64>,
+    children: Option<[usize; 8]>,
+    bodies: Vec<usize>
+}
+
+impl Node {
+    fn new(center: Vector3<f64>, half: f64) -> Self {
+        Self {center, half, mass: 0.0, center_of_mass: Vector3::zero(), children: None, bodies: Vec::new()}
+    }
+
+    fn contains(&self, point: Vector3<f64>) -> bool {
+        let d = point - self.center;
+        d.x.abs() <= self.half && d.y.abs() <= self.half && d.z.abs() <= self.half
+    }
+
+    fn octant(&self, point: Vector3<f64>) -> usize {
+        (point.x > self.center.x) as usize | ((point.y > self.center.y) as usize) << 1 | ((point.z > self.center.z) as usize) << 2
+    }
+}
+
+const LEAF_SIZE: usize = 8;
+const MAX_DEPTH: usize = 32;
+
+struct Octree {
+    nodes: Vec<Node>
+}
+
+impl Octree {
+    fn build(positions: &[Vector3<f64>], masses: &[f64]) -> Self {
+        let mut min = positions[0];
+        let mut max = positions[0];
+        for p in positions.iter() {
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+
+        let extent = max - min;
+        let half = extent.x.max(extent.y).max(extent.z) * 0.5 + 1.0e-9;
+
+        let mut tree = Self {nodes: vec![Node::new((min + max) * 0.5, half)]};
+        for i in 0..positions.len() {
+            tree.insert(0, i, positions, 0);
+        }
+        tree.summarize(0, positions, masses);
+
+        tree
+    }
+
+    fn insert(&mut self, node: usize, body: usize, positions: &[Vector3<f64>], depth: usize) {
+        if let Some(children) = self.nodes[node].children {
+            let octant = self.nodes[node].octant(positions[body]);
+            self.insert(children[octant], body, positions, depth + 1);
+            return;
+        }
+
+        self.nodes[node].bodies.push(body);
+
+        // Coincident bodies stay together in a leaf at the maximum depth
+        if self.nodes[node].bodies.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let (center, half) = (self.nodes[node].center, self.nodes[node].half * 0.5);
+        let mut children = [0; 8];
+        for (octant, child) in children.iter_mut().enumerate() {
+            let sign = |bit: usize| if octant & bit != 0 { half } else { -half };
+            *child = self.nodes.len();
+            self.nodes.push(Node::new(center + Vector3::new(sign(1), sign(2), sign(4)), half));
+        }
+        self.nodes[node].children = Some(children);
+
+        for body in std::mem::take(&mut self.nodes[node].bodies) {
+            let octant = self.nodes[node].octant(positions[body]);
+            self.insert(children[octant], body, positions, depth + 1);
+        }
+    }
+
+    fn summarize(&mut self, node: usize, positions: &[Vector3<f64>], masses: &[f64]) {
+        let (mass, moment) = match self.nodes[node].children {
+            Some(children) => children.iter().fold((0.0, Vector3::zero()), |(mass, moment), child| {
+                self.summarize(*child, positions, masses);
+                let child = &self.nodes[*child];
+                (mass + child.mass, moment + child.center_of_mass * child.mass)
+            }),
+            None => self.nodes[node].bodies.iter()
+                .fold((0.0, Vector3::zero()), |(mass, moment), i| (mass + masses[*i], moment + positions[*i] * masses[*i]))
+        };
+
+        let node = &mut self.nodes[node];
+        node.mass = mass;
+        node.center_of_mass = if mass > 0.0 { moment / mass } else { node.center };
+    }
+
+    // Acceleration and potential per unit mass at body `i`
+    fn field(&self, i: usize, positions: &[Vector3<f64>], masses: &[f64], settings: &NBodySettings) -> (Vector3<f64>, f64) {
+        let point = positions[i];
+        let mut acceleration = Vector3::zero();
+        let mut potential = 0.0;
+
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.mass <= 0.0 {
+                continue;
+            }
+
+            match node.children {
+                Some(children) => {
+                    let distance = (node.center_of_mass - point).magnitude();
+                    if !node.contains(point) && 2.0 * node.half < settings.theta * distance {
+                        let (a, phi) = pair(point, node.center_of_mass, node.mass, settings);
+                        acceleration += a;
+                        potential += phi;
+                    } else {
+                        stack.extend(children.iter());
+                    }
+                },
+                None => {
+                    for j in node.bodies.iter().filter(|j| **j != i) {
+                        let (a, phi) = pair(point, positions[*j], masses[*j], settings);
+                        acceleration += a;
+                        potential += phi;
+                    }
+                }
+            }
+        }
+
+        (acceleration, potential)
+    }
+}
+
+// Softened attraction of `point` towards a mass
+fn pair(point: Vector3<f64>, source: Vector3<f64>, mass: f64, settings: &NBodySettings) -> (Vector3<f64>, f64) {
+    let d = source - point;
+    let r2 = d.magnitude2() + settings.softening * settings.softening;
+    if r2 <= 0.0 {
+        return (Vector3::zero(), 0.0);
+    }
+
+    let r = r2.sqrt();
+    let g = settings.gravitational_constant * mass;
+    (d * (g / (r2 * r)), -g / r)
+}
+
+/// Gravitational acceleration and potential per unit mass of every body.
+pub fn gravity_field(positions: &[Vector3<f64>], masses: &[f64], settings: &NBodySettings) -> Vec<(Vector3<f64>, f64)> {
+    let n = positions.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    if n <= settings.tree_threshold || settings.theta <= 0.0 {
+        let mut field = vec![(Vector3::zero(), 0.0); n];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let (a, phi) = pair(positions[i], positions[j], 1.0, settings);
+                field[i].0 += a * masses[j];
+                field[i].1 += phi * masses[j];
+                field[j].0 -= a * masses[i];
+                field[j].1 += phi * masses[i];
+            }
+        }
+
+        return field;
+    }
+
+    let tree = Octree::build(positions, masses);
+    (0..n).map(|i| tree.field(i, positions, masses, settings)).collect()
+}
+
+/// Integrates the dynamic bodies under their mutual gravitation, replacing the position integration of the step.
+pub(crate) fn integrate(bodies: &mut [RigidBody], settings: &NBodySettings, delta: f64) {
+    let indices: Vec<usize> = (0..bodies.len())
+        .filter(|i| bodies[*i].is_dynamic() && !bodies[*i].is_sleeping())
+        .collect();
+    let masses: Vec<f64> = indices.iter().map(|i| bodies[*i].mass()).collect();
+
+    let (drifts, kicks) = settings.integrator.coefficients();
+    for (k, drift) in drifts.iter().enumerate() {
+        for i in indices.iter() {
+            let body = &mut bodies[*i];
+            body.position += body.velocity * (drift * delta);
+        }
+
+        if let Some(kick) = kicks.get(k) {
+            let positions: Vec<Vector3<f64>> = indices.iter().map(|i| bodies[*i].position).collect();
+            let field = gravity_field(&positions, &masses, settings);
+
+            for (i, (acceleration, _)) in indices.iter().zip(field) {
+                bodies[*i].velocity += acceleration * (kick * delta);
+            }
+        }
+    }
+
+    for i in indices.iter() {
+        bodies[*i].integrate_rotation(delta);
+    }
+}
+
+pub(crate) fn measure(bodies: &[RigidBody], settings: &NBodySettings, stats: &mut NBodyStats) {
+    let dynamic: Vec<&RigidBody> = bodies.iter().filter(|b| b.is_dynamic()).collect();
+    let positions: Vec<Vector3<f64>> = dynamic.iter().map(|b| b.position).collect();
+    let masses: Vec<f64> = dynamic.iter().map(|b| b.mass()).collect();
+    let field = gravity_field(&positions, &masses, settings);
+
+    stats.kinetic_energy = dynamic.iter().map(|b| 0.5 * b.mass() * b.velocity.magnitude2()).sum();
+    // Every pair is counted from both sides
+    stats.potential_energy = field.iter().zip(masses.iter()).map(|((_, phi), m)| 0.5 * phi * m).sum();
+    stats.momentum = dynamic.iter().fold(Vector3::zero(), |p, b| p + b.velocity * b.mass());
+    stats.angular_momentum = dynamic.iter().fold(Vector3::zero(), |l, b| l + b.position.cross(b.velocity * b.mass()));
+
+    let (energy, momentum, angular_momentum) = *stats.reference
+        .get_or_insert((stats.energy(), stats.momentum, stats.angular_momentum));
+
+    stats.energy_drift = if energy != 0.0 { (stats.energy() - energy) / energy.abs() } else { stats.energy() - energy };
+    stats.momentum_drift = (stats.momentum - momentum).magnitude();
+    stats.angular_momentum_drift = (stats.angular_momentum - angular_momentum).magnitude();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use cgmath::{InnerSpace, Vector3};
+    use crate::engine::physics::body::RigidBody;
+    use crate::engine::physics::rng::Rng;
+    use super::{gravity_field, integrate, measure, Integrator, NBodySettings, NBodyStats};
+
+    fn settings() -> NBodySettings {
+        let mut settings = NBodySettings::new();
+        settings.gravitational_constant = 1.0;
+        settings
+    }
+
+    #[test]
+    fn barnes_hut_matches_direct_sum_at_small_theta() {
+        let mut rng = Rng::new(3);
+        let positions: Vec<Vector3<f64>> = (0..300).map(|_| rng.unit_vector() * rng.range(0.1, 10.0)).collect();
+        let masses: Vec<f64> = (0..300).map(|_| rng.range(0.5, 2.0)).collect();
+
+        let mut tree = settings();
+        tree.tree_threshold = 0;
+        tree.theta = 0.2;
+        let mut direct = tree.clone();
+        direct.theta = 0.0;
+
+        let approximate = gravity_field(&positions, &masses, &tree);
+        let exact = gravity_field(&positions, &masses, &direct);
+
+        for (i, ((a, phi), (b, psi))) in approximate.iter().zip(exact.iter()).enumerate() {
+            let error = (a - b).magnitude() / b.magnitude();
+            assert!(error < 1.0e-2, "body {} acceleration off by {}", i, error);
+            assert!((phi - psi).abs() < 1.0e-3 * psi.abs(), "body {} potential {} expected {}", i, phi, psi);
+        }
+    }
+
+    #[test]
+    fn yoshida_orbit_conserves_energy_and_momentum() {
+        // Two equal masses two apart on a circular orbit around their centre
+        let speed = 0.5;
+        let mut bodies: Vec<RigidBody> = [-1.0, 1.0].iter().map(|side| {
+            let mut body = RigidBody::dynamic(Vector3::new(*side, 0.0, 0.0));
+            body.set_mass_properties(1.0, Vector3::new(1.0, 1.0, 1.0));
+            body.velocity = Vector3::new(0.0, 0.0, -side * speed);
+            body
+        }).collect();
+
+        let settings = settings();
+        assert_eq!(settings.integrator, Integrator::Yoshida4);
+
+        let mut stats = NBodyStats::new();
+        measure(&bodies, &settings, &mut stats);
+
+        let delta = 0.01;
+        let period = 2.0 * PI / speed;
+        for _ in 0..(10.0 * period / delta).round() as usize {
+            integrate(&mut bodies, &settings, delta);
+        }
+        measure(&bodies, &settings, &mut stats);
+
+        assert!(stats.energy_drift.abs() < 1.0e-6, "energy drifted by {}", stats.energy_drift);
+        assert!(stats.momentum_drift < 1.0e-9, "momentum drifted by {}", stats.momentum_drift);
+        assert!(stats.angular_momentum_drift < 1.0e-6, "angular momentum drifted by {}", stats.angular_momentum_drift);
+
+        // Still on the circle after ten orbits
+        let separation = (bodies[0].position - bodies[1].position).magnitude();
+        assert!((separation - 2.0).abs() < 1.0e-3, "separation {}", separation);
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use super::PhysicsStats;
+
+/// Wall time of the stages of one step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepTimings {
+    // Force generators, vehicles and velocity integration
+    pub forces: Duration,
+    pub broadphase: Duration,
+    pub narrowphase: Duration,
+    pub islands: Duration,
+    pub solver: Duration,
+    // Continuous collision and position integration
+    pub integration: Duration,
+    // Soft bodies, fluids and fracture
+    pub deformables: Duration,
+    pub sleeping: Duration,
+    pub total: Duration
+}
+
+impl StepTimings {
+    fn stages(&self) -> [Duration; 9] {
+        [
+            self.forces, self.broadphase, self.narrowphase, self.islands, self.solver,
+            self.integration, self.deformables, self.sleeping, self.total
+        ]
+    }
+
+    fn from_stages(stages: [Duration; 9]) -> Self {
+        let [forces, broadphase, narrowphase, islands, solver, integration, deformables, sleeping, total] = stages;
+        Self {forces, broadphase, narrowphase, islands, solver, integration, deformables, sleeping, total}
+    }
+}
+
+const CSV_HEADER: &str = "tick,bodies,awake_bodies,sleeping_bodies,islands,sleeping_islands,pairs,contacts,\
+forces_us,broadphase_us,narrowphase_us,islands_us,solver_us,integration_us,deformables_us,sleeping_us,total_us";
+
+/// Keeps the statistics of the last steps to find where step time goes.
+pub struct PhysicsProfiler {
+    pub enabled: bool,
+
+    // Private
+    capacity: usize,
+    history: VecDeque<PhysicsStats>,
+    last_tick: Option<u64>
+}
+
+impl PhysicsProfiler {
+    pub fn new(capacity: usize) -> Self {
+        Self {enabled: true, capacity, history: VecDeque::new(), last_tick: None}
+    }
+
+    /// Adds the steps of the last update. A world restored to an earlier tick simulates its ticks
+    /// again, the steps recorded from there on are replaced by the new ones.
+    pub fn record(&mut self, steps: &[PhysicsStats]) {
+        if !self.enabled || self.capacity == 0 {
+            return;
+        }
+
+        for stats in steps {
+            if self.last_tick.is_some_and(|tick| stats.tick <= tick) {
+                while self.history.back().is_some_and(|s| s.tick >= stats.tick) {
+                    self.history.pop_back();
+                }
+            }
+
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(stats.clone());
+            self.last_tick = Some(stats.tick);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.last_tick = None;
+    }
+
+    pub fn history(&self) -> &VecDeque<PhysicsStats> {
+        &self.history
+    }
+
+    pub fn last(&self) -> Option<&PhysicsStats> {
+        self.history.back()
+    }
+
+    pub fn average(&self) -> StepTimings {
+        let mut sum = [Duration::ZERO; 9];
+        for stats in self.history.iter() {
+            for (total, stage) in sum.iter_mut().zip(stats.timings.stages()) {
+                *total += stage;
+            }
+        }
+
+        let count = self.history.len().max(1) as u32;
+        StepTimings::from_stages(sum.map(|d| d / count))
+    }
+
+    /// Slowest step in the history.
+    pub fn peak(&self) -> Option<&PhysicsStats> {
+        self.history.iter().max_by_key(|s| s.timings.total)
+    }
+
+    /// One line per step, timings in microseconds.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+
+        for s in self.history.iter() {
+            write!(writer, "{},{},{},{},{},{},{},{}", s.tick, s.bodies, s.awake_bodies, s.sleeping_bodies, s.islands, s.sleeping_islands, s.pairs, s.contacts)?;
+            for stage in s.timings.stages() {
+                write!(writer, ",{:.1}", stage.as_secs_f64() * 1.0e6)?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{PhysicsProfiler, StepTimings, CSV_HEADER};
+    use crate::engine::physics::PhysicsStats;
+
+    fn step(tick: u64, total_us: u64) -> PhysicsStats {
+        let timings = StepTimings {
+            solver: Duration::from_micros(total_us / 2),
+            total: Duration::from_micros(total_us),
+            ..Default::default()
+        };
+        PhysicsStats {tick, timings, ..Default::default()}
+    }
+
+    fn ticks(profiler: &PhysicsProfiler) -> Vec<u64> {
+        profiler.history().iter().map(|s| s.tick).collect()
+    }
+
+    #[test]
+    fn average_and_peak_cover_the_history() {
+        let mut profiler = PhysicsProfiler::new(3);
+        profiler.record(&[step(1, 900), step(2, 100)]);
+        profiler.record(&[step(3, 200)]);
+        profiler.record(&[step(4, 600)]);
+
+        // The first step fell out of the history
+        assert_eq!(ticks(&profiler), vec![2, 3, 4]);
+
+        let average = profiler.average();
+        assert_eq!(average.total, Duration::from_micros(300));
+        assert_eq!(average.solver, Duration::from_micros(150));
+        assert_eq!(profiler.peak().unwrap().tick, 4);
+    }
+
+    #[test]
+    fn restored_ticks_replace_the_recorded_ones() {
+        let mut profiler = PhysicsProfiler::new(10);
+        let steps: Vec<PhysicsStats> = (1..=5).map(|t| step(t, 100)).collect();
+        profiler.record(&steps);
+
+        // Same update seen twice while paused
+        profiler.record(&steps[4..]);
+        assert_eq!(ticks(&profiler), vec![1, 2, 3, 4, 5]);
+
+        // Back to tick 2 and simulated again
+        profiler.record(&[step(3, 400)]);
+        assert_eq!(ticks(&profiler), vec![1, 2, 3]);
+        assert_eq!(profiler.last().unwrap().timings.total, Duration::from_micros(400));
+    }
+
+    #[test]
+    fn csv_has_one_row_per_step() {
+        let mut profiler = PhysicsProfiler::new(10);
+        profiler.record(&[step(7, 250), step(8, 125)]);
+
+        let mut buffer = Vec::new();
+        profiler.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines.len(), 3);
+
+        let columns = CSV_HEADER.split(',').count();
+        let row: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!(row.len(), columns);
+        assert_eq!(row[0], "7");
+        assert_eq!(row[columns - 1], "250.0");
+        assert_eq!(lines[2].rsplit(',').next(), Some("125.0"));
+    }
+}
@@ -0,0 +1,143 @@
+use cgmath::{InnerSpace, Rotation, Vector3};
+use super::body::{BodyHandle, RigidBody};
+use super::collider::{collide, compute_aabb, point_distance, Aabb, ContactPoint, Shape};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub body: BodyHandle,
+    pub point: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    pub distance: f64
+}
+
+/// Entry and exit distances of the ray through the box, None when it misses.
+pub fn ray_aabb(aabb: &Aabb, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<(f64, f64)> {
+    let mut near: f64 = 0.0;
+    let mut far = max_distance;
+
+    for axis in 0..3 {
+        if direction[axis].abs() < 1.0e-12 {
+            if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let inv = 1.0 / direction[axis];
+        let (mut t0, mut t1) = ((aabb.min[axis] - origin[axis]) * inv, (aabb.max[axis] - origin[axis]) * inv);
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+
+        near = near.max(t0);
+        far = far.min(t1);
+        if near > far {
+            return None;
+        }
+    }
+
+    Some((near, far))
+}
+
+const MARCH_STEPS: usize = 96;
+const MARCH_EPSILON: f64 = 1.0e-6;
+
+/// Distance along the normalized `direction` to the collider surface and the normal there.
+pub fn raycast_body(body: &RigidBody, origin: Vector3<f64>, direction: Vector3<f64>, max_distance: f64) -> Option<(f64, Vector3<f64>)> {
+    let frame = body.collider_frame();
+    let body = frame.as_ref();
+
+    match body.shape()? {
+        Shape::Plane {normal, offset} => {
+            let denominator = direction.dot(*normal);
+            let height = origin.dot(*normal) - offset;
+            if height < 0.0 || denominator >= 0.0 {
+                return None;
+            }
+
+            let t = -height / denominator;
+            return if t <= max_distance { Some((t, *normal)) } else { None };
+        },
+        // Meshes are traced in their local space, rotations keep the distances
+        Shape::Heightfield {field} => {
+            let local = body.rotation.conjugate().rotate_vector(direction);
+            let (t, normal) = field.raycast(body.world_to_local(origin), local, max_distance)?;
+            return Some((t, body.rotation.rotate_vector(normal)));
+        },
+        Shape::TriMesh {mesh} => {
+            let local = body.rotation.conjugate().rotate_vector(direction);
+            let (t, normal) = mesh.raycast(body.world_to_local(origin), local, max_distance)?;
+            return Some((t, body.rotation.rotate_vector(normal)));
+        },
+        _ => ()
+    }
+
+    let (near, far) = ray_aabb(&compute_aabb(body)?, origin, direction, max_distance)?;
+
+    // Sphere tracing, the signed distances of the convex shapes are exact outside of them
+    let mut t = near;
+    for _ in 0..MARCH_STEPS {
+        let (distance, normal) = point_distance(body, origin + direction * t)?;
+        if distance < MARCH_EPSILON {
+            // Rays starting inside report no hit
+            return if t > 0.0 || distance > -MARCH_EPSILON { Some((t, normal)) } else { None };
+        }
+
+        t += distance;
+        if t > far {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Closest hit among the bodies accepted by `filter`.
+pub fn raycast(
+    bodies: &[RigidBody],
+    origin: Vector3<f64>,
+    direction: Vector3<f64>,
+    max_distance: f64,
+    filter: impl Fn(BodyHandle) -> bool
+) -> Option<RayHit> {
+    let direction = direction.normalize();
+    let mut best: Option<RayHit> = None;
+
+    for (i, body) in bodies.iter().enumerate() {
+        if !filter(BodyHandle(i)) {
+            continue;
+        }
+
+        let limit = best.map(|b| b.distance).unwrap_or(max_distance);
+        if let Some((distance, normal)) = raycast_body(body, origin, direction, limit) {
+            best = Some(RayHit {body: BodyHandle(i), point: origin + direction * distance, normal, distance});
+        }
+    }
+
+    best
+}
+
+/// Contacts of a probe body, not part of the world, against the bodies accepted by `filter`.
+/// Normals point from the probe into the other body.
+pub fn overlap(bodies: &[RigidBody], probe: &RigidBody, filter: impl Fn(BodyHandle) -> bool) -> Vec<(BodyHandle, ContactPoint)> {
+    let bounds = match compute_aabb(probe) {
+        Some(aabb) => aabb,
+        None => return Vec::new()
+    };
+
+    let mut contacts = Vec::new();
+    for (i, body) in bodies.iter().enumerate() {
+        if !filter(BodyHandle(i)) {
+            continue;
+        }
+
+        match compute_aabb(body) {
+            Some(aabb) if aabb.overlaps(&bounds) => (),
+            _ => continue
+        }
+
+        contacts.extend(collide(probe, body).into_iter().map(|c| (BodyHandle(i), c)));
+    }
+
+    contacts
+}
@@ -0,0 +1,44 @@
+use std::f64::consts::PI;
+use cgmath::Vector3;
+
+// SplitMix64, small and reproducible on every platform
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {state: seed}
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    pub fn unit_vector(&mut self) -> Vector3<f64> {
+        let z = self.range(-1.0, 1.0);
+        let angle = self.range(0.0, 2.0 * PI);
+        let r = (1.0 - z * z).sqrt();
+
+        Vector3::new(r * angle.cos(), r * angle.sin(), z)
+    }
+}
@@ -0,0 +1,826 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use cgmath::{InnerSpace, Quaternion, Vector3, Zero};
+use super::body::{BodyHandle, RigidBody};
+use super::collider::{compute_aabb, point_distance, Aabb};
+use super::determinism::{quantize_vector, StateHasher};
+
+// Body index with its start and end pose in a step
+type PoseSpan = (usize, Vector3<f64>, Quaternion<f64>, Vector3<f64>, Quaternion<f64>);
+
+fn is_moving(body: &RigidBody) -> bool {
+    body.is_dynamic() && !body.is_sleeping()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SoftBodyHandle(pub usize);
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    pub mass: f64,
+
+    // Private
+    previous: Vector3<f64>,
+    pinned: bool
+}
+
+impl Particle {
+    pub fn new(position: Vector3<f64>, mass: f64) -> Self {
+        Self {position, velocity: Vector3::zero(), mass, previous: position, pinned: false}
+    }
+
+    pub fn inv_mass(&self) -> f64 {
+        if self.pinned || self.mass <= 0.0 { 0.0 } else { 1.0 / self.mass }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConstraintKind {
+    Stretch,
+    // Distance between the far vertices of two triangles sharing an edge
+    Bending
+}
+
+#[derive(Clone, Debug)]
+pub struct DistanceConstraint {
+    pub a: usize,
+    pub b: usize,
+    pub rest: f64,
+    pub compliance: f64,
+    pub kind: ConstraintKind,
+
+    lambda: f64,
+    broken: bool
+}
+
+impl DistanceConstraint {
+    pub fn new(a: usize, b: usize, rest: f64, compliance: f64, kind: ConstraintKind) -> Self {
+        Self {a, b, rest, compliance, kind, lambda: 0.0, broken: false}
+    }
+}
+
+// Keeps the volume of a closed triangle mesh
+#[derive(Clone, Debug)]
+pub struct VolumeConstraint {
+    pub rest_volume: f64,
+    // Scales the rest volume, above 1.0 inflates the body
+    pub pressure: f64,
+    pub compliance: f64,
+
+    lambda: f64
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AttachTarget {
+    Anchor(Vector3<f64>),
+    // Point local to the body
+    Body(BodyHandle, Vector3<f64>)
+}
+
+// Ties a particle to a world point or a rigid body, dynamic bodies are pulled back
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    pub particle: usize,
+    pub target: AttachTarget,
+    pub compliance: f64,
+
+    lambda: f64,
+    broken: bool
+}
+
+/// Runs the prediction and distance solve of all substeps of a step, the compute path in
+/// `EnginePipeline` plugs in here. `accelerations` are sampled once at the start of the step
+/// and `delta` is the length of a substep.
+pub trait SoftBodyBackend {
+    fn solve_substeps(&self, body: &mut SoftBody, accelerations: &[Vector3<f64>], substeps: usize, delta: f64);
+}
+
+pub type SoftBackendType = Box<dyn SoftBodyBackend + Sync + Send>;
+
+#[derive(Clone, Debug)]
+pub struct SoftBody {
+    pub particles: Vec<Particle>,
+    pub triangles: Vec<[usize; 3]>,
+    pub distances: Vec<DistanceConstraint>,
+    pub volume: Option<VolumeConstraint>,
+    pub attachments: Vec<Attachment>,
+
+    // Collision radius around every particle
+    pub thickness: f64,
+    pub friction: f64,
+    // Fraction of velocity lost per second
+    pub damping: f64,
+    pub substeps: usize,
+    // Constraints pulled harder than this break, only on the CPU solver
+    pub tear_force: Option<f64>,
+    pub use_backend: bool
+}
+
+impl SoftBody {
+    pub fn new(particles: Vec<Particle>, triangles: Vec<[usize; 3]>) -> Self {
+        Self {
+            particles,
+            triangles,
+            distances: Vec::new(),
+            volume: None,
+            attachments: Vec::new(),
+            thickness: 0.02,
+            friction: 0.3,
+            damping: 0.05,
+            substeps: 10,
+            tear_force: None,
+            use_backend: false
+        }
+    }
+
+    /// Rectangular cloth spanned by `u` and `v` from `origin`.
+    pub fn cloth(
+        origin: Vector3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+        resolution: (usize, usize),
+        mass: f64,
+        stretch_compliance: f64,
+        bend_compliance: f64
+    ) -> Self {
+        let (nx, ny) = (resolution.0.max(1), resolution.1.max(1));
+        let particle_mass = mass / ((nx + 1) * (ny + 1)) as f64;
+
+        let mut particles = Vec::new();
+        for j in 0..=ny {
+            for i in 0..=nx {
+                let position = origin + u * (i as f64 / nx as f64) + v * (j as f64 / ny as f64);
+                particles.push(Particle::new(position, particle_mass));
+            }
+        }
+
+        let index = |i: usize, j: usize| j * (nx + 1) + i;
+        let mut triangles = Vec::new();
+        for j in 0..ny {
+            for i in 0..nx {
+                triangles.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                triangles.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+
+        let mut body = Self::new(particles, triangles);
+        body.add_edge_constraints(stretch_compliance, bend_compliance);
+        body
+    }
+
+    /// Closed sphere that keeps its volume.
+    pub fn ball(center: Vector3<f64>, radius: f64, segments: usize, mass: f64, stretch_compliance: f64, volume_compliance: f64) -> Self {
+        let rings = segments.max(3);
+        let sectors = 2 * rings;
+
+        let mut positions = vec![center + Vector3::new(0.0, radius, 0.0)];
+        for ring in 1..rings {
+            let theta = PI * ring as f64 / rings as f64;
+            for sector in 0..sectors {
+                let phi = 2.0 * PI * sector as f64 / sectors as f64;
+                positions.push(center + Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) * radius);
+            }
+        }
+        positions.push(center - Vector3::new(0.0, radius, 0.0));
+
+        let bottom = positions.len() - 1;
+        let ring_start = |ring: usize| 1 + (ring - 1) * sectors;
+
+        let mut triangles = Vec::new();
+        for sector in 0..sectors {
+            let next = (sector + 1) % sectors;
+
+            // Caps
+            triangles.push([0, ring_start(1) + next, ring_start(1) + sector]);
+            triangles.push([bottom, ring_start(rings - 1) + sector, ring_start(rings - 1) + next]);
+
+            for ring in 1..rings - 1 {
+                let (a, b) = (ring_start(ring) + sector, ring_start(ring) + next);
+                let (c, d) = (ring_start(ring + 1) + sector, ring_start(ring + 1) + next);
+                triangles.push([a, b, d]);
+                triangles.push([a, d, c]);
+            }
+        }
+
+        let particle_mass = mass / positions.len() as f64;
+        let particles = positions.into_iter().map(|p| Particle::new(p, particle_mass)).collect();
+
+        let mut body = Self::new(particles, triangles);
+        body.add_edge_constraints(stretch_compliance, stretch_compliance);
+
+        let rest_volume = body.current_volume();
+        body.volume = Some(VolumeConstraint {rest_volume, pressure: 1.0, compliance: volume_compliance, lambda: 0.0});
+        body
+    }
+
+    /// Rope of `segments` links between `start` and `end`, slack hangs down in a parabola.
+    pub fn rope(
+        start: Vector3<f64>,
+        end: Vector3<f64>,
+        length: f64,
+        segments: usize,
+        mass: f64,
+        stretch_compliance: f64,
+        bend_compliance: f64
+    ) -> Self {
+        let segments = segments.max(1);
+        let particle_mass = mass / (segments + 1) as f64;
+        let rest = length / segments as f64;
+
+        let curve = |sag: f64, t: f64| start + (end - start) * t - Vector3::new(0.0, 4.0 * sag * t * (1.0 - t), 0.0);
+        let samples = 16 * segments;
+        let polyline = |sag: f64| -> Vec<Vector3<f64>> {
+            (0..=samples).map(|i| curve(sag, i as f64 / samples as f64)).collect()
+        };
+        let arc_length = |points: &Vec<Vector3<f64>>| points.windows(2).map(|w| (w[1] - w[0]).magnitude()).sum::<f64>();
+
+        // Bisection for the sag that matches the length
+        let (mut low, mut high) = (0.0, length);
+        for _ in 0..50 {
+            let sag = (low + high) / 2.0;
+            if arc_length(&polyline(sag)) < length { low = sag } else { high = sag }
+        }
+
+        // Particles evenly spaced along the curve
+        let points = polyline(low);
+        let total = arc_length(&points);
+        let mut particles = vec![Particle::new(start, particle_mass)];
+        let mut travelled = 0.0;
+        for w in points.windows(2) {
+            let step = (w[1] - w[0]).magnitude();
+            while particles.len() <= segments && travelled + step >= total * particles.len() as f64 / segments as f64 {
+                let t = (total * particles.len() as f64 / segments as f64 - travelled) / step.max(1.0e-12);
+                particles.push(Particle::new(w[0] + (w[1] - w[0]) * t, particle_mass));
+            }
+            travelled += step;
+        }
+        while particles.len() <= segments {
+            particles.push(Particle::new(end, particle_mass));
+        }
+
+        let mut body = Self::new(particles, Vec::new());
+        for i in 0..segments {
+            body.distances.push(DistanceConstraint::new(i, i + 1, rest, stretch_compliance, ConstraintKind::Stretch));
+        }
+
+        // Skips one particle, a straight rope has twice the segment length
+        for i in 0..segments.saturating_sub(1) {
+            body.distances.push(DistanceConstraint::new(i, i + 2, 2.0 * rest, bend_compliance, ConstraintKind::Bending));
+        }
+
+        body.thickness = 0.05;
+        body
+    }
+
+    /// Inextensible links that bend freely.
+    pub fn chain(start: Vector3<f64>, end: Vector3<f64>, length: f64, links: usize, mass: f64) -> Self {
+        let mut body = Self::rope(start, end, length, links, mass, 0.0, 1.0);
+        body.friction = 0.5;
+        body
+    }
+
+    /// Stretch constraints along triangle edges and bending constraints across shared edges.
+    pub fn add_edge_constraints(&mut self, stretch_compliance: f64, bend_compliance: f64) {
+        // Ordered map, constraint order must not depend on hashing
+        let mut edges: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+
+        for triangle in self.triangles.iter() {
+            for k in 0..3 {
+                let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                let opposite = triangle[(k + 2) % 3];
+                edges.entry((a.min(b), a.max(b))).or_default().push(opposite);
+            }
+        }
+
+        for ((a, b), opposite) in edges.iter() {
+            let rest = (self.particles[*a].position - self.particles[*b].position).magnitude();
+            self.distances.push(DistanceConstraint::new(*a, *b, rest, stretch_compliance, ConstraintKind::Stretch));
+
+            if let [c, d] = opposite[..] {
+                let rest = (self.particles[c].position - self.particles[d].position).magnitude();
+                self.distances.push(DistanceConstraint::new(c, d, rest, bend_compliance, ConstraintKind::Bending));
+            }
+        }
+    }
+
+    // Pinning
+    pub fn pin(&mut self, index: usize) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.pinned = true;
+            particle.velocity = Vector3::zero();
+        }
+    }
+
+    pub fn pin_to(&mut self, index: usize, position: Vector3<f64>) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.position = position;
+            particle.previous = position;
+        }
+        self.pin(index);
+    }
+
+    pub fn unpin(&mut self, index: usize) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.pinned = false;
+        }
+    }
+
+    pub fn attach(&mut self, index: usize, target: AttachTarget, compliance: f64) {
+        if index < self.particles.len() {
+            self.attachments.push(Attachment {particle: index, target, compliance, lambda: 0.0, broken: false});
+        }
+    }
+
+    pub fn detach(&mut self, index: usize) {
+        self.attachments.retain(|a| a.particle != index);
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let first = self.particles.first().map(|p| p.position).unwrap_or(Vector3::zero());
+        let mut aabb = Aabb::new(first, first);
+
+        for particle in self.particles.iter() {
+            aabb = aabb.merge(&Aabb::new(particle.position, particle.position));
+        }
+
+        let t = Vector3::new(self.thickness, self.thickness, self.thickness);
+        Aabb::new(aabb.min - t, aabb.max + t)
+    }
+
+    pub fn current_volume(&self) -> f64 {
+        self.triangles.iter()
+            .map(|t| {
+                let (p0, p1, p2) = (self.particles[t[0]].position, self.particles[t[1]].position, self.particles[t[2]].position);
+                p0.cross(p1).dot(p2) / 6.0
+            })
+            .sum()
+    }
+
+    pub fn center_of_mass(&self) -> Vector3<f64> {
+        let total: f64 = self.particles.iter().map(|p| p.mass).sum();
+        if total <= 0.0 {
+            return Vector3::zero();
+        }
+
+        self.particles.iter().fold(Vector3::zero(), |acc, p| acc + p.position * p.mass) / total
+    }
+
+    // Solver phases, one substep
+    fn predict(&mut self, delta: f64, acceleration: &dyn Fn(Vector3<f64>, Vector3<f64>) -> Vector3<f64>) {
+        let keep = (1.0 - self.damping * delta).max(0.0);
+
+        for particle in self.particles.iter_mut() {
+            particle.previous = particle.position;
+            if particle.pinned {
+                continue;
+            }
+
+            particle.velocity += acceleration(particle.position, particle.velocity) * delta;
+            particle.velocity *= keep;
+            particle.position += particle.velocity * delta;
+        }
+    }
+
+    pub(crate) fn solve_distances(&mut self, delta: f64) {
+        let h2 = delta * delta;
+        let limit = self.tear_limit(delta);
+
+        // Symmetric sweep, a single forward pass feeds rounding errors back
+        // through the velocities and makes closed meshes blow up
+        let count = self.distances.len();
+        for k in 0..2 * count {
+            let i = if k < count { k } else { 2 * count - 1 - k };
+            let (a, b) = (self.distances[i].a, self.distances[i].b);
+            let (wa, wb) = (self.particles[a].inv_mass(), self.particles[b].inv_mass());
+            let w = wa + wb;
+            if w == 0.0 {
+                continue;
+            }
+
+            let d = self.particles[a].position - self.particles[b].position;
+            let length = d.magnitude();
+            if length < 1.0e-9 {
+                continue;
+            }
+
+            let constraint = &mut self.distances[i];
+            if constraint.broken {
+                continue;
+            }
+
+            let alpha = constraint.compliance / h2;
+            let c = length - constraint.rest;
+            let dlambda = (-c - alpha * constraint.lambda) / (w + alpha);
+
+            // Torn before the correction, the pieces keep their momentum
+            if constraint.kind == ConstraintKind::Stretch && (constraint.lambda + dlambda).abs() > limit {
+                constraint.broken = true;
+                continue;
+            }
+            constraint.lambda += dlambda;
+
+            let n = d / length;
+            self.particles[a].position += n * (wa * dlambda);
+            self.particles[b].position -= n * (wb * dlambda);
+        }
+    }
+
+    fn solve_volume(&mut self, delta: f64) {
+        let volume = match &self.volume {
+            Some(v) => v.clone(),
+            None => return
+        };
+
+        let mut gradients = vec![Vector3::zero(); self.particles.len()];
+        for t in self.triangles.iter() {
+            let (p0, p1, p2) = (self.particles[t[0]].position, self.particles[t[1]].position, self.particles[t[2]].position);
+            gradients[t[0]] += p1.cross(p2) / 6.0;
+            gradients[t[1]] += p2.cross(p0) / 6.0;
+            gradients[t[2]] += p0.cross(p1) / 6.0;
+        }
+
+        let w: f64 = self.particles.iter()
+            .zip(gradients.iter())
+            .map(|(p, g)| p.inv_mass() * g.magnitude2())
+            .sum();
+
+        let alpha = volume.compliance / (delta * delta);
+        if w + alpha <= 1.0e-12 {
+            return;
+        }
+
+        let c = self.current_volume() - volume.rest_volume * volume.pressure;
+        let dlambda = (-c - alpha * volume.lambda) / (w + alpha);
+
+        for (particle, gradient) in self.particles.iter_mut().zip(gradients.iter()) {
+            particle.position += gradient * (particle.inv_mass() * dlambda);
+        }
+
+        if let Some(v) = self.volume.as_mut() {
+            v.lambda += dlambda;
+        }
+    }
+
+    fn solve_attachments(&mut self, bodies: &mut [RigidBody], delta: f64) {
+        let h2 = delta * delta;
+        let limit = self.tear_limit(delta);
+
+        for attachment in self.attachments.iter_mut().filter(|a| !a.broken) {
+            let particle = &mut self.particles[attachment.particle];
+
+            let (target, body) = match attachment.target {
+                AttachTarget::Anchor(point) => (point, None),
+                AttachTarget::Body(handle, local) => match bodies.get_mut(handle.0) {
+                    Some(body) => (body.local_to_world(local), Some(body).filter(|b| is_moving(b))),
+                    None => continue
+                }
+            };
+
+            let d = particle.position - target;
+            let length = d.magnitude();
+            if length < 1.0e-9 {
+                continue;
+            }
+
+            let n = d / length;
+            let offset = body.as_ref().map(|b| target - b.position).unwrap_or(Vector3::zero());
+            let wb = body.as_ref().map(|b| b.effective_inv_mass(offset, n)).unwrap_or(0.0);
+
+            let w = particle.inv_mass() + wb;
+            let alpha = attachment.compliance / h2;
+            if w + alpha <= 1.0e-12 {
+                continue;
+            }
+
+            let dlambda = (-length - alpha * attachment.lambda) / (w + alpha);
+            if (attachment.lambda + dlambda).abs() > limit {
+                attachment.broken = true;
+                continue;
+            }
+            attachment.lambda += dlambda;
+            particle.position += n * (particle.inv_mass() * dlambda);
+
+            if let Some(body) = body {
+                body.apply_correction(-n * dlambda, offset, delta);
+            }
+        }
+    }
+
+    // XPBD multipliers are force * h^2
+    fn tear_limit(&self, delta: f64) -> f64 {
+        self.tear_force.map(|force| force * delta * delta).unwrap_or(f64::INFINITY)
+    }
+
+    fn remove_broken(&mut self) {
+        let torn: Vec<(usize, usize)> = self.distances.iter()
+            .filter(|c| c.broken)
+            .map(|c| (c.a, c.b))
+            .collect();
+
+        // Bending constraints around the tear would keep the pieces together
+        for (a, b) in torn.iter() {
+            self.distances.retain(|c| match c.kind {
+                ConstraintKind::Stretch => (c.a, c.b) != (*a, *b),
+                ConstraintKind::Bending => ![c.a, c.b].iter().any(|i| i == a || i == b)
+            });
+        }
+
+        self.attachments.retain(|a| !a.broken);
+    }
+
+    // Pushes particles out of rigid colliders, dynamic bodies receive the reaction
+    fn solve_collisions(&mut self, bodies: &mut [RigidBody], delta: f64) {
+        let bounds = self.aabb();
+
+        // Particles attached to a body do not collide with it
+        let attached: Vec<(usize, usize)> = self.attachments.iter()
+            .filter_map(|a| match a.target {
+                AttachTarget::Body(handle, _) => Some((handle.0, a.particle)),
+                AttachTarget::Anchor(_) => None
+            })
+            .collect();
+
+        for (index, body) in bodies.iter_mut().enumerate() {
+            match compute_aabb(body) {
+                Some(aabb) if aabb.overlaps(&bounds) => (),
+                _ => continue
+            }
+
+            for (i, particle) in self.particles.iter_mut().enumerate().filter(|(_, p)| !p.pinned) {
+                if attached.contains(&(index, i)) {
+                    continue;
+                }
+
+                let (distance, normal) = match point_distance(body, particle.position) {
+                    Some(result) => result,
+                    None => continue
+                };
+
+                let depth = self.thickness - distance;
+                if depth <= 0.0 {
+                    continue;
+                }
+
+                // Overlap the particle did not move into this substep is resolved without a kick
+                let incoming = (particle.previous - particle.position).dot(normal).max(0.0);
+                if depth > incoming {
+                    particle.previous += normal * (depth - incoming);
+                }
+                particle.position += normal * depth;
+
+                // Friction against the surface motion
+                let offset = particle.position - body.position;
+                let surface = body.velocity_at(offset) * delta;
+                let moved = particle.position - particle.previous - surface;
+                let tangential = moved - normal * moved.dot(normal);
+                let slide = tangential.magnitude();
+
+                // Coulomb limit, the correction is bounded by the normal push
+                if slide > 1.0e-12 {
+                    particle.position -= tangential * ((self.friction * depth) / slide).min(1.0);
+                }
+
+                if body.is_dynamic() {
+                    let impulse = -normal * (depth * particle.mass / delta);
+                    body.apply_particle_impulse(impulse, offset);
+                }
+            }
+        }
+    }
+
+    fn update_velocities(&mut self, delta: f64) {
+        for particle in self.particles.iter_mut() {
+            if particle.pinned {
+                particle.velocity = Vector3::zero();
+                continue;
+            }
+
+            particle.velocity = (particle.position - particle.previous) / delta;
+        }
+    }
+
+    fn reset_multipliers(&mut self) {
+        for constraint in self.distances.iter_mut() {
+            constraint.lambda = 0.0;
+        }
+        if let Some(volume) = self.volume.as_mut() {
+            volume.lambda = 0.0;
+        }
+        for attachment in self.attachments.iter_mut() {
+            attachment.lambda = 0.0;
+        }
+    }
+
+    // All substeps go to the backend in one submission, the constraints it does not know
+    // about are solved once over the whole step afterwards
+    fn step_backend(
+        &mut self,
+        backend: &SoftBackendType,
+        delta: f64,
+        acceleration: &dyn Fn(Vector3<f64>, Vector3<f64>) -> Vector3<f64>,
+        bodies: &mut [RigidBody]
+    ) {
+        let substeps = self.substeps.max(1);
+        let start: Vec<Vector3<f64>> = self.particles.iter().map(|p| p.position).collect();
+        let accelerations: Vec<Vector3<f64>> = self.particles.iter()
+            .map(|p| if p.pinned { Vector3::zero() } else { acceleration(p.position, p.velocity) })
+            .collect();
+
+        backend.solve_substeps(self, &accelerations, substeps, delta / substeps as f64);
+
+        for (particle, previous) in self.particles.iter_mut().zip(start) {
+            particle.previous = previous;
+        }
+
+        self.reset_multipliers();
+        self.solve_volume(delta);
+        self.solve_attachments(bodies, delta);
+        self.solve_collisions(bodies, delta);
+        self.update_velocities(delta);
+        self.remove_broken();
+    }
+
+    pub(crate) fn step(
+        &mut self,
+        delta: f64,
+        acceleration: &dyn Fn(Vector3<f64>, Vector3<f64>) -> Vector3<f64>,
+        bodies: &mut [RigidBody],
+        start_poses: &[(Vector3<f64>, Quaternion<f64>)],
+        backend: Option<&SoftBackendType>
+    ) {
+        if let Some(backend) = backend.filter(|_| self.use_backend) {
+            self.step_backend(backend, delta, acceleration, bodies);
+            return;
+        }
+
+        let substeps = self.substeps.max(1);
+        let h = delta / substeps as f64;
+
+        // Attached bodies were already moved for the whole step, the substeps see them part way
+        // between their start and end pose
+        let mut attached: Vec<usize> = self.attachments.iter()
+            .filter_map(|a| match a.target {
+                AttachTarget::Body(handle, _) if handle.0 < bodies.len() && handle.0 < start_poses.len() => Some(handle.0),
+                _ => None
+            })
+            .collect();
+        attached.sort();
+        attached.dedup();
+
+        // Start and end pose of every attached body, corrections from the rope shift both
+        let mut poses: Vec<PoseSpan> = attached.iter()
+            .map(|i| (*i, start_poses[*i].0, start_poses[*i].1, bodies[*i].position, bodies[*i].rotation))
+            .collect();
+
+        for substep in 1..=substeps {
+            self.predict(h, acceleration);
+
+            let t = substep as f64 / substeps as f64;
+            let interpolated: Vec<(Vector3<f64>, Quaternion<f64>)> = poses.iter()
+                .map(|(_, start_position, start_rotation, end_position, end_rotation)| match substep == substeps {
+                    true => (*end_position, *end_rotation),
+                    false => (start_position + (end_position - start_position) * t, start_rotation.slerp(*end_rotation, t))
+                })
+                .collect();
+
+            for ((index, ..), (position, rotation)) in poses.iter().zip(interpolated.iter()) {
+                bodies[*index].position = *position;
+                bodies[*index].rotation = *rotation;
+            }
+
+            self.reset_multipliers();
+            self.solve_distances(h);
+            self.solve_volume(h);
+            self.solve_attachments(bodies, h);
+            self.solve_collisions(bodies, h);
+            self.update_velocities(h);
+            self.remove_broken();
+
+            for ((index, start_position, start_rotation, end_position, end_rotation), (position, rotation)) in poses.iter_mut().zip(interpolated.iter()) {
+                let body = &bodies[*index];
+                let shift = body.position - position;
+                let turn = body.rotation * rotation.conjugate();
+
+                *start_position += shift;
+                *end_position += shift;
+                *start_rotation = (turn * *start_rotation).normalize();
+                *end_rotation = (turn * *end_rotation).normalize();
+            }
+        }
+    }
+
+    pub(crate) fn quantize(&mut self, bits: i32) {
+        for particle in self.particles.iter_mut() {
+            particle.position = quantize_vector(particle.position, bits);
+            particle.velocity = quantize_vector(particle.velocity, bits);
+        }
+    }
+
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        for particle in self.particles.iter() {
+            hasher.write_vector(particle.position);
+            hasher.write_vector(particle.velocity);
+            hasher.write_bool(particle.pinned);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3, Zero};
+    use super::{ConstraintKind, SoftBody};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::body::RigidBody;
+    use crate::engine::physics::collider::Collider;
+
+    const TIMESTEP: f64 = 1.0 / 60.0;
+
+    fn run(physics: &mut PhysicsWorld, seconds: f64) {
+        for _ in 0..(seconds / TIMESTEP).round() as usize {
+            physics.step(TIMESTEP);
+        }
+    }
+
+    // Hangs from its two top corners, 10 x 10 cells of 10 cm
+    fn curtain(tear_force: Option<f64>) -> SoftBody {
+        let mut cloth = SoftBody::cloth(Vector3::new(0.0, 2.0, 0.0), Vector3::unit_x(), -Vector3::unit_y(), (10, 10), 0.5, 0.0, 0.01);
+        cloth.pin(0);
+        cloth.pin(10);
+        cloth.tear_force = tear_force;
+        cloth
+    }
+
+    fn stretch(body: &SoftBody) -> f64 {
+        body.distances.iter()
+            .filter(|c| c.kind == ConstraintKind::Stretch)
+            .map(|c| (body.particles[c.a].position - body.particles[c.b].position).magnitude() / c.rest - 1.0)
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn pinned_cloth_hangs_without_stretching() {
+        let mut physics = PhysicsWorld::new();
+        let handle = physics.add_soft_body(curtain(None));
+        run(&mut physics, 3.0);
+
+        let cloth = physics.get_soft_body(handle).unwrap();
+        assert!(stretch(cloth) < 0.02, "stretched by {}", stretch(cloth));
+
+        let speed = cloth.particles.iter().map(|p| p.velocity.magnitude()).fold(0.0, f64::max);
+        assert!(speed < 0.05, "still swinging at {}", speed);
+    }
+
+    #[test]
+    fn torn_cloth_loses_its_constraints() {
+        let mut physics = PhysicsWorld::new();
+        let whole = physics.add_soft_body(curtain(None));
+        let torn = physics.add_soft_body(curtain(Some(0.5)));
+        let constraints = physics.get_soft_body(whole).unwrap().distances.len();
+
+        run(&mut physics, 2.0);
+
+        assert_eq!(physics.get_soft_body(whole).unwrap().distances.len(), constraints);
+        assert!(physics.get_soft_body(torn).unwrap().distances.len() < constraints, "the cloth never tore");
+    }
+
+    #[test]
+    fn ball_keeps_its_volume_on_the_ground() {
+        let mut physics = PhysicsWorld::new();
+        physics.add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+        let handle = physics.add_soft_body(SoftBody::ball(Vector3::new(0.0, 1.0, 0.0), 0.5, 6, 1.0, 0.001, 0.0));
+        let rest = physics.get_soft_body(handle).unwrap().volume.as_ref().unwrap().rest_volume;
+
+        run(&mut physics, 3.0);
+
+        let ball = physics.get_soft_body(handle).unwrap();
+        let volume = ball.current_volume();
+        assert!((volume - rest).abs() < 0.02 * rest, "volume {} rest {}", volume, rest);
+        assert!(ball.particles.iter().all(|p| p.position.y > -0.05), "fell through the ground");
+    }
+
+    #[test]
+    fn cloth_resting_on_a_sleeping_crate_leaves_it_asleep() {
+        let mut physics = PhysicsWorld::new();
+        physics.add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+        let crate_body = physics.add_body(RigidBody::dynamic(Vector3::new(0.0, 0.5, 0.0)).with_collider(Collider::cuboid(0.5, 0.5, 0.5), 1.0));
+        run(&mut physics, 2.0);
+        assert!(physics.bodies()[crate_body.0].is_sleeping());
+
+        let cloth = SoftBody::cloth(Vector3::new(-0.4, 1.05, -0.4), Vector3::new(0.8, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.8), (8, 8), 0.2, 0.0, 0.01);
+        physics.add_soft_body(cloth);
+        run(&mut physics, 2.0);
+
+        for _ in 0..60 {
+            physics.step(TIMESTEP);
+            let body = &physics.bodies()[crate_body.0];
+            assert!(body.is_sleeping(), "the crate woke up");
+            assert_eq!(body.velocity, Vector3::zero());
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use cgmath::{InnerSpace, Rotation, Vector3, Zero};
+use super::body::{BodyHandle, RigidBody};
+use super::determinism::{quantize, StateHasher};
+use super::query::raycast;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VehicleHandle(pub usize);
+
+// Below this speed the slips are measured against it, avoids dividing by zero at rest
+const MIN_SLIP_SPEED: f64 = 1.0;
+
+/// Engine torque in Nm over rpm, linear between the points and flat past the ends.
+#[derive(Clone, Debug)]
+pub struct TorqueCurve {
+    points: Vec<(f64, f64)>
+}
+
+impl TorqueCurve {
+    pub fn new(mut points: Vec<(f64, f64)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self {points}
+    }
+
+    pub fn torque_at(&self, rpm: f64) -> f64 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0
+        };
+
+        if rpm <= first.0 {
+            return first.1;
+        }
+        if rpm >= last.0 {
+            return last.1;
+        }
+
+        let i = self.points.iter().position(|p| p.0 > rpm).unwrap();
+        let ((r0, t0), (r1, t1)) = (self.points[i - 1], self.points[i]);
+        t0 + (t1 - t0) * (rpm - r0) / (r1 - r0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VehicleEngine {
+    pub torque_curve: TorqueCurve,
+    pub idle_rpm: f64,
+    // No torque above it
+    pub redline_rpm: f64,
+    // Gearbox and differential together, reverse uses the same ratio
+    pub gear_ratio: f64
+}
+
+impl VehicleEngine {
+    pub fn new() -> Self {
+        Self {
+            torque_curve: TorqueCurve::new(vec![(800.0, 150.0), (3000.0, 250.0), (5000.0, 240.0), (6500.0, 180.0)]),
+            idle_rpm: 800.0,
+            redline_rpm: 6500.0,
+            gear_ratio: 6.0
+        }
+    }
+}
+
+impl Default for VehicleEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WheelSettings {
+    // Top of the suspension in chassis space, the wheel hangs along -y
+    pub attachment: Vector3<f64>,
+    pub radius: f64,
+    pub inertia: f64,
+    pub rest_length: f64,
+    pub stiffness: f64,
+    pub damping: f64,
+    // Peak friction coefficient of the tire
+    pub friction: f64,
+    pub rolling_resistance: f64,
+    pub brake_torque: f64,
+    pub steerable: bool,
+    pub driven: bool
+}
+
+impl WheelSettings {
+    pub fn new(attachment: Vector3<f64>, radius: f64) -> Self {
+        Self {
+            attachment,
+            radius,
+            inertia: 1.0,
+            rest_length: 0.3,
+            stiffness: 30000.0,
+            damping: 3000.0,
+            friction: 1.0,
+            rolling_resistance: 0.015,
+            brake_torque: 1000.0,
+            steerable: false,
+            driven: false
+        }
+    }
+}
+
+/// State of a wheel after the last step.
+#[derive(Clone, Debug)]
+pub struct WheelTelemetry {
+    pub grounded: bool,
+    pub ground: Option<BodyHandle>,
+    pub contact_point: Vector3<f64>,
+    pub contact_normal: Vector3<f64>,
+    // Wheel center in world space
+    pub center: Vector3<f64>,
+    pub suspension_length: f64,
+    // 0 at rest length, 1 fully compressed
+    pub compression: f64,
+    pub suspension_force: f64,
+    pub steer_angle: f64,
+    // Rolling speed in rad/s, positive forwards, and the accumulated angle
+    pub angular_velocity: f64,
+    pub rotation: f64,
+    pub slip_ratio: f64,
+    // Radians, positive when the contact slides to the right
+    pub slip_angle: f64,
+    pub longitudinal_force: f64,
+    pub lateral_force: f64,
+    pub drive_torque: f64,
+    pub brake_torque: f64
+}
+
+impl WheelTelemetry {
+    pub fn new() -> Self {
+        Self {
+            grounded: false,
+            ground: None,
+            contact_point: Vector3::zero(),
+            contact_normal: Vector3::unit_y(),
+            center: Vector3::zero(),
+            suspension_length: 0.0,
+            compression: 0.0,
+            suspension_force: 0.0,
+            steer_angle: 0.0,
+            angular_velocity: 0.0,
+            rotation: 0.0,
+            slip_ratio: 0.0,
+            slip_angle: 0.0,
+            longitudinal_force: 0.0,
+            lateral_force: 0.0,
+            drive_torque: 0.0,
+            brake_torque: 0.0
+        }
+    }
+}
+
+impl Default for WheelTelemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Wheel {
+    pub settings: WheelSettings,
+    telemetry: WheelTelemetry
+}
+
+impl Wheel {
+    pub fn telemetry(&self) -> &WheelTelemetry {
+        &self.telemetry
+    }
+}
+
+// Simplified magic formula, normalized force for a slip
+fn tire_curve(slip: f64) -> f64 {
+    let (b, c) = (10.0, 1.9);
+    (c * (b * slip).atan()).sin()
+}
+
+fn tire_slope(slip: f64) -> f64 {
+    let (b, c) = (10.0, 1.9);
+    (c * (b * slip).atan()).cos() * c * b / (1.0 + b * b * slip * slip)
+}
+
+/// Raycast vehicle: the chassis is a rigid body, wheels are rays along the suspension
+/// that push it with spring forces and tire friction. Chassis space is -z forward, +x right.
+#[derive(Clone, Debug)]
+pub struct Vehicle {
+    pub chassis: BodyHandle,
+    pub engine: VehicleEngine,
+    pub max_steer_angle: f64,
+
+    // Private
+    wheels: Vec<Wheel>,
+    throttle: f64,
+    brake: f64,
+    steering: f64,
+    rpm: f64
+}
+
+impl Vehicle {
+    pub fn new(chassis: BodyHandle, engine: VehicleEngine) -> Self {
+        let rpm = engine.idle_rpm;
+        Self {chassis, engine, max_steer_angle: 0.5, wheels: Vec::new(), throttle: 0.0, brake: 0.0, steering: 0.0, rpm}
+    }
+
+    pub fn add_wheel(&mut self, settings: WheelSettings) -> usize {
+        self.wheels.push(Wheel {settings, telemetry: WheelTelemetry::new()});
+        self.wheels.len() - 1
+    }
+
+    pub fn wheels(&self) -> &Vec<Wheel> {
+        &self.wheels
+    }
+
+    pub fn get_wheel_mut(&mut self, wheel: usize) -> Option<&mut Wheel> {
+        self.wheels.get_mut(wheel)
+    }
+
+    /// -1 is full reverse, 1 full throttle.
+    pub fn set_throttle(&mut self, throttle: f64) {
+        self.throttle = throttle.clamp(-1.0, 1.0);
+    }
+
+    pub fn set_brake(&mut self, brake: f64) {
+        self.brake = brake.clamp(0.0, 1.0);
+    }
+
+    /// -1 is full left, 1 full right.
+    pub fn set_steering(&mut self, steering: f64) {
+        self.steering = steering.clamp(-1.0, 1.0);
+    }
+
+    pub fn rpm(&self) -> f64 {
+        self.rpm
+    }
+
+    /// Forward speed of the chassis in m/s.
+    pub fn speed(&self, bodies: &[RigidBody]) -> f64 {
+        bodies.get(self.chassis.0)
+            .map(|body| body.velocity.dot(body.rotation.rotate_vector(-Vector3::unit_z())))
+            .unwrap_or(0.0)
+    }
+
+    fn update_engine(&mut self) -> f64 {
+        let driven: Vec<f64> = self.wheels.iter()
+            .filter(|w| w.settings.driven)
+            .map(|w| w.telemetry.angular_velocity.abs())
+            .collect();
+        if driven.is_empty() {
+            return 0.0;
+        }
+
+        let wheel_speed = driven.iter().sum::<f64>() / driven.len() as f64;
+        self.rpm = (wheel_speed * self.engine.gear_ratio * 60.0 / (2.0 * std::f64::consts::PI)).max(self.engine.idle_rpm);
+
+        if self.rpm >= self.engine.redline_rpm {
+            return 0.0;
+        }
+
+        self.throttle * self.engine.torque_curve.torque_at(self.rpm) * self.engine.gear_ratio / driven.len() as f64
+    }
+
+    /// Suspension and tire forces for this step, applied before the velocities are integrated.
+    pub(crate) fn update(&mut self, bodies: &mut [RigidBody], delta: f64) {
+        let chassis = self.chassis;
+        if delta <= 0.0 || bodies.get(chassis.0).map(|b| !b.is_dynamic()).unwrap_or(true) {
+            return;
+        }
+
+        // A parked vehicle sleeps like any other body until it is driven again
+        if bodies[chassis.0].is_sleeping() {
+            if self.throttle == 0.0 {
+                return;
+            }
+            bodies[chassis.0].wake_up();
+        }
+
+        let drive_torque = self.update_engine();
+        let steer_angle = self.steering * self.max_steer_angle;
+        let count = self.wheels.len() as f64;
+
+        for wheel in self.wheels.iter_mut() {
+            let settings = &wheel.settings;
+            let telemetry = &mut wheel.telemetry;
+            let body = &bodies[chassis.0];
+
+            let origin = body.local_to_world(settings.attachment);
+            let up = body.rotation.rotate_vector(Vector3::unit_y());
+            let steer = if settings.steerable { steer_angle } else { 0.0 };
+            let heading = body.rotation.rotate_vector(Vector3::new(steer.sin(), 0.0, -steer.cos()));
+
+            telemetry.steer_angle = steer;
+            telemetry.drive_torque = if settings.driven { drive_torque } else { 0.0 };
+            telemetry.brake_torque = settings.brake_torque * self.brake;
+
+            let hit = raycast(bodies, origin, -up, settings.rest_length + settings.radius, |h| h != chassis);
+            let hit = match hit {
+                Some(hit) => hit,
+                None => {
+                    // Spinning freely in the air
+                    let free = telemetry.angular_velocity + telemetry.drive_torque * delta / settings.inertia;
+                    let braking = telemetry.brake_torque * delta / settings.inertia;
+                    telemetry.angular_velocity = free.signum() * (free.abs() - braking).max(0.0);
+                    telemetry.rotation += telemetry.angular_velocity * delta;
+
+                    telemetry.grounded = false;
+                    telemetry.ground = None;
+                    telemetry.suspension_length = settings.rest_length;
+                    telemetry.center = origin - up * settings.rest_length;
+                    telemetry.compression = 0.0;
+                    telemetry.suspension_force = 0.0;
+                    telemetry.slip_ratio = 0.0;
+                    telemetry.slip_angle = 0.0;
+                    telemetry.longitudinal_force = 0.0;
+                    telemetry.lateral_force = 0.0;
+                    continue;
+                }
+            };
+
+            let normal = hit.normal;
+            let offset = hit.point - body.position;
+            let ground_velocity = bodies[hit.body.0].velocity_at(hit.point - bodies[hit.body.0].position);
+            let velocity = body.velocity_at(offset) - ground_velocity;
+
+            // Spring and damper, never pulling the chassis down
+            let length = (hit.distance - settings.radius).max(0.0);
+            let compression = settings.rest_length - length;
+            let suspension = (settings.stiffness * compression - settings.damping * velocity.dot(up)).max(0.0);
+            let load = suspension * up.dot(normal).max(0.0);
+
+            // Tire axes in the ground plane
+            let forward = heading - normal * heading.dot(normal);
+            let forward = if forward.magnitude2() > 1.0e-12 { forward.normalize() } else { heading };
+            let side = forward.cross(normal);
+
+            let v_long = velocity.dot(forward);
+            let v_lat = velocity.dot(side);
+            let reference = v_long.abs().max(MIN_SLIP_SPEED);
+            let limit = settings.friction * load;
+            let r = settings.radius;
+
+            // Wheel spin, implicit in the linearized tire force so stiff tires stay stable
+            let w0 = telemetry.angular_velocity;
+            let slip = (w0 * r - v_long) / reference;
+            let f0 = limit * tire_curve(slip);
+            let slope = (limit * tire_slope(slip)).max(0.0) * r / reference;
+
+            // Brakes and rolling resistance slow the wheel down but never reverse it
+            let scale = delta / settings.inertia / (1.0 + slope * r * delta / settings.inertia);
+            let free = w0 + (telemetry.drive_torque - f0 * r) * scale;
+            let braking = (telemetry.brake_torque + settings.rolling_resistance * load * r) * scale;
+
+            // Largest friction that only stops the sliding within the step, the chassis can roll
+            // and every wheel pushes at once
+            let stopping = |speed: f64, axis: Vector3<f64>| speed.abs() / (body.effective_inv_mass(offset, axis) * count * delta);
+
+            let locked = free.abs() <= braking;
+            let w1 = if locked { 0.0 } else { free - braking * free.signum() };
+            let mut longitudinal = if locked {
+                let stop = stopping(v_long, forward);
+                (limit * tire_curve(-v_long / reference)).clamp(-stop, stop)
+            } else {
+                f0 + slope * (w1 - w0)
+            };
+
+            let slip_angle = v_lat.atan2(reference);
+            let mut lateral = -limit * tire_curve(slip_angle);
+
+            // Friction circle
+            let total = (longitudinal * longitudinal + lateral * lateral).sqrt();
+            if total > limit && total > 0.0 {
+                longitudinal *= limit / total;
+                lateral *= limit / total;
+            }
+
+            let stop = stopping(v_lat, side);
+            lateral = lateral.clamp(-stop, stop);
+
+            telemetry.angular_velocity = w1;
+            telemetry.rotation += w1 * delta;
+            telemetry.grounded = true;
+            telemetry.ground = Some(hit.body);
+            telemetry.contact_point = hit.point;
+            telemetry.contact_normal = normal;
+            telemetry.center = origin - up * length;
+            telemetry.suspension_length = length;
+            telemetry.compression = compression / settings.rest_length;
+            telemetry.suspension_force = suspension;
+            telemetry.slip_ratio = slip;
+            telemetry.slip_angle = slip_angle;
+            telemetry.longitudinal_force = longitudinal;
+            telemetry.lateral_force = lateral;
+
+            let force = up * suspension + forward * longitudinal + side * lateral;
+            bodies[chassis.0].apply_force_at_point(force, hit.point);
+            bodies[hit.body.0].apply_force_at_point(-force, hit.point);
+        }
+    }
+
+    pub(crate) fn quantize(&mut self, bits: i32) {
+        for wheel in self.wheels.iter_mut() {
+            wheel.telemetry.angular_velocity = quantize(wheel.telemetry.angular_velocity, bits);
+            wheel.telemetry.rotation = quantize(wheel.telemetry.rotation, bits);
+        }
+    }
+
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_f64(self.rpm);
+        for wheel in self.wheels.iter() {
+            hasher.write_f64(wheel.telemetry.angular_velocity);
+            hasher.write_f64(wheel.telemetry.rotation);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use super::VERSION;
+use super::world::context::InputEvent;
+
+// Replay files are JSON Lines: a header followed by one event per line
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: String,
+    pub start_tick: usize,
+    pub seed: u64,
+    pub timestep: f64
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub tick: usize,
+    pub event: InputEvent
+}
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub struct InputRecorder {
+    file: File,
+    events: usize
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, start_tick: usize, seed: u64, timestep: f64) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+
+        let header = ReplayHeader {version: VERSION.to_string(), start_tick, seed, timestep};
+        writeln!(file, "{}", serde_json::to_string(&header).map_err(invalid_data)?)?;
+
+        Ok(Self {file, events: 0})
+    }
+
+    // Written right away, the application may exit without dropping the recorder
+    pub fn record(&mut self, tick: usize, event: InputEvent) -> io::Result<()> {
+        let line = serde_json::to_string(&RecordedInput {tick, event}).map_err(invalid_data)?;
+        writeln!(self.file, "{}", line)?;
+
+        self.events += 1;
+        Ok(())
+    }
+
+    pub fn events(&self) -> usize {
+        self.events
+    }
+}
+
+pub struct ReplayPlayer {
+    header: ReplayHeader,
+    events: Vec<RecordedInput>,
+    cursor: usize
+}
+
+impl ReplayPlayer {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header: ReplayHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(invalid_data)?,
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "empty replay file"))
+        };
+
+        if header.version != VERSION {
+            println!("Replay was recorded with v{}, current version is v{}", header.version, VERSION);
+        }
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            events.push(serde_json::from_str::<RecordedInput>(&line).map_err(invalid_data)?);
+        }
+
+        // Keeps the recorded order within a tick
+        events.sort_by_key(|e| e.tick);
+
+        Ok(Self {header, events, cursor: 0})
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        &self.header
+    }
+
+    pub fn start_tick(&self) -> usize {
+        self.header.start_tick
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.header.seed
+    }
+
+    pub fn timestep(&self) -> f64 {
+        self.header.timestep
+    }
+
+    /// Events recorded up to `tick` that were not played yet.
+    pub fn events_for(&mut self, tick: usize) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        while self.cursor < self.events.len() && self.events[self.cursor].tick <= tick {
+            events.push(self.events[self.cursor].event);
+            self.cursor += 1;
+        }
+
+        events
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3, Zero};
+    use winit::event::VirtualKeyCode;
+    use crate::engine::physics::body::RigidBody;
+    use crate::engine::physics::character::CharacterController;
+    use crate::engine::physics::collider::Collider;
+    use crate::engine::world::World;
+    use crate::engine::world::context::{InputContext, InputEvent};
+
+    const TIMESTEP: f64 = 1.0 / 60.0;
+    const TICKS: usize = 240;
+
+    fn world(name: &str) -> &'static mut World {
+        let world = World::new(name);
+        let physics = world.get_physics();
+        physics.add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+        physics.add_body(RigidBody::dynamic(Vector3::new(2.0, 3.0, 0.0)).with_collider(Collider::cuboid(0.5, 0.5, 0.5), 1.0));
+        world.set_character(Some(CharacterController::new(Vector3::new(0.0, 0.81, 0.0), 0.3, 0.5)));
+        world
+    }
+
+    // Press W, jump while walking, stop
+    fn live_events(tick: usize) -> Vec<InputEvent> {
+        match tick {
+            10 => vec![InputEvent::Pressed(VirtualKeyCode::W)],
+            60 => vec![InputEvent::Pressed(VirtualKeyCode::Space)],
+            62 => vec![InputEvent::Released(VirtualKeyCode::Space)],
+            120 => vec![InputEvent::Released(VirtualKeyCode::W)],
+            _ => Vec::new()
+        }
+    }
+
+    #[test]
+    fn recorded_input_replays_to_the_same_state() {
+        let path = std::env::temp_dir().join(format!("dengine_replay_{}.jsonl", std::process::id()));
+
+        let recorded = world("Recorded");
+        let mut input = InputContext::new();
+        recorded.start_input_recording(&mut input, &path, 3, TIMESTEP).unwrap();
+
+        for tick in 0..TICKS {
+            for event in live_events(tick) {
+                input.handle_input(event);
+            }
+            recorded.process_tick(&mut input, TIMESTEP);
+            input.update();
+        }
+
+        let start = Vector3::new(0.0, 0.81, 0.0);
+        let walked = recorded.get_character().unwrap().position - start;
+        assert!(walked.magnitude() > 1.0, "character only moved {:?}", walked);
+
+        // The replay starts from its recorded tick, not from where this context is
+        let replayed = world("Replayed");
+        let mut input = InputContext::new();
+        for _ in 0..25 {
+            input.update();
+        }
+        replayed.start_input_replay(&mut input, &path).unwrap();
+        assert_eq!(input.time.ticks(), 0);
+
+        for tick in 0..TICKS {
+            // Ignored while the replay plays
+            if tick == 30 {
+                input.handle_input(InputEvent::Pressed(VirtualKeyCode::D));
+            }
+            replayed.process_tick(&mut input, TIMESTEP);
+            input.update();
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded.state_hash(), replayed.state_hash());
+    }
+}
@@ -0,0 +1,786 @@
+use std::collections::HashMap;
+use std::f64::consts::FRAC_PI_2;
+use std::fs;
+use std::io;
+use std::path::Path;
+use cgmath::{InnerSpace, Matrix, Matrix3, Quaternion, Rad, Rotation, Rotation3, SquareMatrix, Vector3, Zero};
+use roxmltree::{Document, Node};
+
+use super::physics::PhysicsWorld;
+use super::physics::body::{principal_axes, BodyHandle, BodyType, RigidBody};
+use super::physics::collider::Collider;
+use super::physics::joint::{Joint, JointHandle, JointKind};
+
+// Robot descriptions from URDF and a subset of MJCF. Every link becomes one rigid body
+// at its center of mass, along the principal axes of its inertia, with the first
+// primitive collision shape at its offset from there
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pose {
+    pub position: Vector3<f64>,
+    pub rotation: Quaternion<f64>
+}
+
+impl Pose {
+    pub fn new(position: Vector3<f64>, rotation: Quaternion<f64>) -> Self {
+        Self {position, rotation}
+    }
+
+    pub fn identity() -> Self {
+        Self::new(Vector3::zero(), Quaternion::new(1.0, 0.0, 0.0, 0.0))
+    }
+
+    /// Fixed axis roll, pitch and yaw as in URDF.
+    pub fn from_xyz_rpy(xyz: Vector3<f64>, rpy: Vector3<f64>) -> Self {
+        let rotation = Quaternion::from_angle_z(Rad(rpy.z)) * Quaternion::from_angle_y(Rad(rpy.y)) * Quaternion::from_angle_x(Rad(rpy.x));
+        Self::new(xyz, rotation)
+    }
+
+    /// `other` given in this frame.
+    pub fn compose(&self, other: &Pose) -> Pose {
+        Pose::new(self.transform_point(other.position), (self.rotation * other.rotation).normalize())
+    }
+
+    pub fn transform_point(&self, point: Vector3<f64>) -> Vector3<f64> {
+        self.position + self.rotation.rotate_vector(point)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum GeometryShape {
+    Box { half_extents: Vector3<f64> },
+    Sphere { radius: f64 },
+    // Along the local Z axis
+    Cylinder { radius: f64, half_length: f64 },
+    Capsule { radius: f64, half_length: f64 },
+    Mesh { filename: String, scale: Vector3<f64> }
+}
+
+#[derive(Clone, Debug)]
+pub struct RobotGeometry {
+    // In the link frame
+    pub origin: Pose,
+    pub shape: GeometryShape
+}
+
+#[derive(Clone, Debug)]
+pub struct RobotLink {
+    pub name: String,
+    pub mass: f64,
+    // Center of mass frame in the link frame
+    pub inertial: Pose,
+    // Around the center of mass in the inertial frame
+    pub inertia: Matrix3<f64>,
+    pub collisions: Vec<RobotGeometry>,
+    pub visuals: Vec<RobotGeometry>
+}
+
+impl RobotLink {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            mass: 0.0,
+            inertial: Pose::identity(),
+            inertia: Matrix3::zero(),
+            collisions: Vec::new(),
+            visuals: Vec::new()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RobotJointType {
+    Fixed,
+    Revolute,
+    Continuous,
+    Prismatic,
+    Ball,
+    Floating,
+    Planar
+}
+
+#[derive(Clone, Debug)]
+pub struct RobotJoint {
+    pub name: String,
+    pub joint_type: RobotJointType,
+    pub parent: String,
+    pub child: String,
+    // Child link frame in the parent link frame at a joint position of zero
+    pub origin: Pose,
+    // Joint point and axis in the child link frame
+    pub anchor: Vector3<f64>,
+    pub axis: Vector3<f64>,
+    pub limits: Option<(f64, f64)>,
+    // Maximum force or torque and speed
+    pub effort: Option<f64>,
+    pub velocity: Option<f64>
+}
+
+impl RobotJoint {
+    fn new(name: &str, joint_type: RobotJointType, parent: &str, child: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            joint_type,
+            parent: parent.to_string(),
+            child: child.to_string(),
+            origin: Pose::identity(),
+            anchor: Vector3::zero(),
+            axis: Vector3::unit_x(),
+            limits: None,
+            effort: None,
+            velocity: None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RobotVisual {
+    pub body: BodyHandle,
+    // Relative to the body
+    pub origin: Pose,
+    pub shape: GeometryShape
+}
+
+/// Bodies and joints of a spawned robot, by link and joint name.
+#[derive(Clone, Debug)]
+pub struct Robot {
+    pub links: Vec<(String, BodyHandle)>,
+    pub joints: Vec<(String, JointHandle)>,
+    pub visuals: Vec<RobotVisual>,
+    // Parts of the model that could not be spawned as described
+    pub warnings: Vec<String>
+}
+
+impl Robot {
+    pub fn body(&self, link: &str) -> Option<BodyHandle> {
+        self.links.iter().find(|(n, _)| n == link).map(|(_, h)| *h)
+    }
+
+    pub fn joint(&self, name: &str) -> Option<JointHandle> {
+        self.joints.iter().find(|(n, _)| n == name).map(|(_, h)| *h)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RobotModel {
+    pub name: String,
+    pub links: Vec<RobotLink>,
+    pub joints: Vec<RobotJoint>,
+    // Parts of the description that were ignored or approximated
+    pub warnings: Vec<String>
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn numbers(text: &str) -> io::Result<Vec<f64>> {
+    text.split_whitespace()
+        .map(|n| n.parse::<f64>().map_err(|_| invalid_data(format!("invalid number {}", n))))
+        .collect()
+}
+
+fn number(node: Node, name: &str) -> io::Result<Option<f64>> {
+    match node.attribute(name) {
+        Some(text) => text.trim().parse().map(Some).map_err(|_| invalid_data(format!("invalid {} on <{}>", name, node.tag_name().name()))),
+        None => Ok(None)
+    }
+}
+
+fn vector_attribute(node: Node, name: &str, default: Vector3<f64>) -> io::Result<Vector3<f64>> {
+    match node.attribute(name) {
+        Some(text) => match numbers(text)?.as_slice() {
+            [x, y, z] => Ok(Vector3::new(*x, *y, *z)),
+            _ => Err(invalid_data(format!("{} on <{}> needs 3 numbers", name, node.tag_name().name())))
+        },
+        None => Ok(default)
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, tag: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.has_tag_name(tag))
+}
+
+// Symmetric inertia matrix from its six components
+fn inertia_matrix(xx: f64, yy: f64, zz: f64, xy: f64, xz: f64, yz: f64) -> Matrix3<f64> {
+    Matrix3::new(xx, xy, xz, xy, yy, yz, xz, yz, zz)
+}
+
+// Rotation taking the local Y axis of capsules onto the Z axis of the descriptions
+fn y_to_z() -> Quaternion<f64> {
+    Quaternion::from_angle_x(Rad(FRAC_PI_2))
+}
+
+impl RobotModel {
+    pub fn load_urdf(path: &Path) -> io::Result<Self> {
+        Self::parse_urdf(&fs::read_to_string(path)?)
+    }
+
+    pub fn load_mjcf(path: &Path) -> io::Result<Self> {
+        Self::parse_mjcf(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse_urdf(text: &str) -> io::Result<Self> {
+        let document = Document::parse(text).map_err(invalid_data)?;
+        let root = document.root_element();
+        if !root.has_tag_name("robot") {
+            return Err(invalid_data("URDF root element has to be <robot>"));
+        }
+
+        let mut model = Self {name: root.attribute("name").unwrap_or("robot").to_string(), links: Vec::new(), joints: Vec::new(), warnings: Vec::new()};
+
+        let origin = |node: Option<Node>| -> io::Result<Pose> {
+            match node {
+                Some(node) => Ok(Pose::from_xyz_rpy(vector_attribute(node, "xyz", Vector3::zero())?, vector_attribute(node, "rpy", Vector3::zero())?)),
+                None => Ok(Pose::identity())
+            }
+        };
+
+        for node in children(root, "link") {
+            let name = node.attribute("name").ok_or_else(|| invalid_data("<link> without a name"))?;
+            let mut link = RobotLink::new(name);
+
+            if let Some(inertial) = child(node, "inertial") {
+                link.inertial = origin(child(inertial, "origin"))?;
+                link.mass = child(inertial, "mass").map(|m| number(m, "value")).transpose()?.flatten().unwrap_or(0.0);
+
+                if let Some(i) = child(inertial, "inertia") {
+                    let get = |name| number(i, name).map(|v| v.unwrap_or(0.0));
+                    link.inertia = inertia_matrix(get("ixx")?, get("iyy")?, get("izz")?, get("ixy")?, get("ixz")?, get("iyz")?);
+                }
+            }
+
+            for (tag, list) in [("collision", &mut link.collisions), ("visual", &mut link.visuals)] {
+                for element in children(node, tag) {
+                    let geometry = match child(element, "geometry").and_then(|g| g.children().find(|n| n.is_element())) {
+                        Some(geometry) => geometry,
+                        None => continue
+                    };
+
+                    let length = |node: Node| number(node, "length").map(|l| l.unwrap_or(0.0) * 0.5);
+                    let shape = match geometry.tag_name().name() {
+                        "box" => GeometryShape::Box {half_extents: vector_attribute(geometry, "size", Vector3::zero())? * 0.5},
+                        "sphere" => GeometryShape::Sphere {radius: number(geometry, "radius")?.unwrap_or(0.0)},
+                        "cylinder" => GeometryShape::Cylinder {radius: number(geometry, "radius")?.unwrap_or(0.0), half_length: length(geometry)?},
+                        "capsule" => GeometryShape::Capsule {radius: number(geometry, "radius")?.unwrap_or(0.0), half_length: length(geometry)?},
+                        "mesh" => GeometryShape::Mesh {
+                            filename: geometry.attribute("filename").unwrap_or("").to_string(),
+                            scale: vector_attribute(geometry, "scale", Vector3::new(1.0, 1.0, 1.0))?
+                        },
+                        other => {
+                            model.warnings.push(format!("link {}: unknown geometry <{}>", name, other));
+                            continue;
+                        }
+                    };
+
+                    list.push(RobotGeometry {origin: origin(child(element, "origin"))?, shape});
+                }
+            }
+
+            model.links.push(link);
+        }
+
+        for node in children(root, "joint") {
+            let name = node.attribute("name").unwrap_or("");
+            let joint_type = match node.attribute("type").unwrap_or("") {
+                "fixed" => RobotJointType::Fixed,
+                "revolute" => RobotJointType::Revolute,
+                "continuous" => RobotJointType::Continuous,
+                "prismatic" => RobotJointType::Prismatic,
+                "floating" => RobotJointType::Floating,
+                "planar" => RobotJointType::Planar,
+                other => return Err(invalid_data(format!("joint {}: unknown type {}", name, other)))
+            };
+
+            let link = |tag| child(node, tag).and_then(|n| n.attribute("link"))
+                .ok_or_else(|| invalid_data(format!("joint {} needs a <{}> link", name, tag)));
+            let mut joint = RobotJoint::new(name, joint_type, link("parent")?, link("child")?);
+
+            joint.origin = origin(child(node, "origin"))?;
+            if let Some(axis) = child(node, "axis") {
+                joint.axis = vector_attribute(axis, "xyz", Vector3::unit_x())?.normalize();
+            }
+
+            if let Some(limit) = child(node, "limit") {
+                if joint_type == RobotJointType::Revolute || joint_type == RobotJointType::Prismatic {
+                    joint.limits = Some((number(limit, "lower")?.unwrap_or(0.0), number(limit, "upper")?.unwrap_or(0.0)));
+                }
+                joint.effort = number(limit, "effort")?;
+                joint.velocity = number(limit, "velocity")?;
+            }
+
+            model.joints.push(joint);
+        }
+
+        model.validate()?;
+        Ok(model)
+    }
+
+    /// Bodies, geoms, joints and inertials of `<worldbody>`. Only the root `<default>` is used,
+    /// `class` attributes are ignored.
+    pub fn parse_mjcf(text: &str) -> io::Result<Self> {
+        let document = Document::parse(text).map_err(invalid_data)?;
+        let root = document.root_element();
+        if !root.has_tag_name("mujoco") {
+            return Err(invalid_data("MJCF root element has to be <mujoco>"));
+        }
+
+        let mut parser = MjcfParser {
+            radians: child(root, "compiler").and_then(|c| c.attribute("angle")) == Some("radian"),
+            geom_defaults: HashMap::new(),
+            joint_defaults: HashMap::new(),
+            model: Self {name: root.attribute("model").unwrap_or("robot").to_string(), links: Vec::new(), joints: Vec::new(), warnings: Vec::new()}
+        };
+
+        if let Some(defaults) = child(root, "default") {
+            for (tag, map) in [("geom", &mut parser.geom_defaults), ("joint", &mut parser.joint_defaults)] {
+                if let Some(node) = child(defaults, tag) {
+                    map.extend(node.attributes().map(|a| (a.name().to_string(), a.value().to_string())));
+                }
+            }
+            if children(defaults, "default").next().is_some() {
+                parser.model.warnings.push("default classes are ignored".to_string());
+            }
+        }
+
+        let world = child(root, "worldbody").ok_or_else(|| invalid_data("MJCF needs a <worldbody>"))?;
+
+        // Geoms directly in the world body belong to a static world link
+        let mut world_link = RobotLink::new("world");
+        for geom in children(world, "geom") {
+            if let Some(geometry) = parser.geom(geom, &mut world_link)? {
+                world_link.collisions.push(geometry);
+            }
+        }
+        parser.model.links.push(world_link);
+
+        for body in children(world, "body") {
+            parser.body(body, "world")?;
+        }
+
+        parser.model.validate()?;
+        Ok(parser.model)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        for joint in self.joints.iter() {
+            for link in [&joint.parent, &joint.child] {
+                if !self.links.iter().any(|l| &l.name == link) {
+                    return Err(invalid_data(format!("joint {} refers to unknown link {}", joint.name, link)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parent_joint(&self, link: &str) -> Option<&RobotJoint> {
+        self.joints.iter().find(|j| j.child == link)
+    }
+
+    /// Adds a body for every link and a joint for every joint, with the root links at `base`.
+    /// Root links without mass, and all of them with `fixed_base`, become static bodies.
+    pub fn spawn(&self, physics: &mut PhysicsWorld, base: Pose, fixed_base: bool) -> Robot {
+        let mut warnings = Vec::new();
+
+        // Link frames at a joint position of zero, parents first
+        let mut frames: HashMap<&str, Pose> = HashMap::new();
+        let mut order: Vec<&RobotLink> = Vec::new();
+        for link in self.links.iter().filter(|l| self.parent_joint(&l.name).is_none()) {
+            frames.insert(&link.name, base);
+            order.push(link);
+        }
+
+        let mut i = 0;
+        while i < order.len() {
+            let parent = order[i].name.as_str();
+            for joint in self.joints.iter().filter(|j| j.parent == parent) {
+                if frames.contains_key(joint.child.as_str()) {
+                    continue;
+                }
+
+                frames.insert(&joint.child, frames[parent].compose(&joint.origin));
+                order.push(self.links.iter().find(|l| l.name == joint.child).unwrap());
+            }
+            i += 1;
+        }
+
+        let mut robot = Robot {links: Vec::new(), joints: Vec::new(), visuals: Vec::new(), warnings: Vec::new()};
+
+        for link in order.iter() {
+            let frame = frames[link.name.as_str()];
+            let is_root = self.parent_joint(&link.name).is_none();
+            let body_type = if (is_root && (fixed_base || link.mass <= 0.0)) || link.name == "world" {
+                BodyType::Static
+            } else {
+                BodyType::Dynamic
+            };
+
+            // First primitive collision shape, in the link frame
+            let collision = link.collisions.iter().find_map(|geometry| {
+                let (collider, rotation) = match geometry.shape {
+                    GeometryShape::Box {half_extents: h} => (Collider::cuboid(h.x, h.y, h.z), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+                    GeometryShape::Sphere {radius} => (Collider::sphere(radius), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+                    GeometryShape::Capsule {radius, half_length} => (Collider::capsule(radius, half_length), y_to_z()),
+                    // Cylinders are approximated by capsules of the same length
+                    GeometryShape::Cylinder {radius, half_length} => (Collider::capsule(radius, (half_length - radius).max(0.0)), y_to_z()),
+                    GeometryShape::Mesh {..} => return None
+                };
+                Some((collider, frame.compose(&Pose::new(geometry.origin.position, geometry.origin.rotation * rotation))))
+            });
+
+            if collision.is_none() && !link.collisions.is_empty() {
+                warnings.push(format!("link {}: mesh collisions are not supported", link.name));
+            }
+            if link.collisions.len() > 1 {
+                warnings.push(format!("link {}: only the first of {} collision shapes is used", link.name, link.collisions.len()));
+            }
+
+            // Dynamic links sit at the center of mass along the principal axes of the inertia,
+            // static ones in the link frame
+            let inertial = frame.compose(&link.inertial);
+            let (mut moments, axes) = principal_axes(link.inertia);
+            let pose = match body_type {
+                BodyType::Dynamic => Pose::new(inertial.position, inertial.rotation * axes),
+                _ => frame
+            };
+
+            let mut body = RigidBody::new(body_type, pose.position);
+            body.rotation = pose.rotation;
+            if let Some((collider, shape)) = collision {
+                let offset = pose.rotation.conjugate().rotate_vector(shape.position - pose.position);
+                body = body.with_collider(collider.with_offset(offset, pose.rotation.conjugate() * shape.rotation), 1.0);
+            }
+
+            if body_type == BodyType::Dynamic {
+                let mass = if link.mass > 0.0 {
+                    link.mass
+                } else {
+                    warnings.push(format!("link {}: no mass, using 0.01 kg", link.name));
+                    0.01
+                };
+
+                if moments.x <= 0.0 || moments.y <= 0.0 || moments.z <= 0.0 {
+                    warnings.push(format!("link {}: no inertia, using a small sphere", link.name));
+                    let i = 0.4 * mass * 0.05 * 0.05;
+                    moments = Vector3::new(i, i, i);
+                }
+
+                body.set_mass_properties(mass, moments);
+            }
+
+            let handle = physics.add_body(body);
+            robot.links.push((link.name.clone(), handle));
+
+            let body = pose;
+            for visual in link.visuals.iter() {
+                let world = frame.compose(&visual.origin);
+                let origin = Pose::new(
+                    body.rotation.conjugate().rotate_vector(world.position - body.position),
+                    body.rotation.conjugate() * world.rotation
+                );
+                robot.visuals.push(RobotVisual {body: handle, origin, shape: visual.shape.clone()});
+            }
+        }
+
+        for joint in self.joints.iter() {
+            let (a, b) = match (robot.body(&joint.parent), robot.body(&joint.child)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue
+            };
+
+            let frame = frames[joint.child.as_str()];
+            let point = frame.transform_point(joint.anchor);
+            let axis = frame.rotation.rotate_vector(joint.axis);
+
+            let bodies = physics.bodies();
+            let (body_a, body_b) = (&bodies[a.0], &bodies[b.0]);
+            let local_axis = body_a.rotation.conjugate().rotate_vector(axis);
+
+            let kind = match joint.joint_type {
+                RobotJointType::Fixed => JointKind::Fixed,
+                RobotJointType::Revolute | RobotJointType::Continuous => JointKind::Hinge {axis: local_axis, limits: joint.limits},
+                RobotJointType::Prismatic => JointKind::Prismatic {axis: local_axis, limits: joint.limits},
+                RobotJointType::Ball => JointKind::Ball,
+                RobotJointType::Floating => continue,
+                RobotJointType::Planar => {
+                    warnings.push(format!("joint {}: planar joints are not supported", joint.name));
+                    continue;
+                }
+            };
+
+            let built = Joint::new(kind, a, body_a.world_to_local(point), Some(b), body_b.world_to_local(point))
+                .with_collide_connected(false)
+                .with_motor_limits(joint.effort.unwrap_or(f64::INFINITY), joint.velocity.unwrap_or(f64::INFINITY));
+            if let Some(handle) = physics.add_joint(built) {
+                robot.joints.push((joint.name.clone(), handle));
+            }
+        }
+
+        robot.warnings = warnings;
+        robot
+    }
+}
+
+struct MjcfParser {
+    radians: bool,
+    geom_defaults: HashMap<String, String>,
+    joint_defaults: HashMap<String, String>,
+    model: RobotModel
+}
+
+impl MjcfParser {
+    fn attribute<'a>(node: &'a Node, defaults: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+        node.attribute(name).or_else(|| defaults.get(name).map(|s| s.as_str()))
+    }
+
+    fn angle(&self, value: f64) -> f64 {
+        if self.radians { value } else { value.to_radians() }
+    }
+
+    // `pos` with `quat` (w x y z) or `euler` (intrinsic x y z)
+    fn pose(&self, node: Node) -> io::Result<Pose> {
+        let position = vector_attribute(node, "pos", Vector3::zero())?;
+
+        let rotation = if let Some(quat) = node.attribute("quat") {
+            match numbers(quat)?.as_slice() {
+                [w, x, y, z] => Quaternion::new(*w, *x, *y, *z).normalize(),
+                _ => return Err(invalid_data("quat needs 4 numbers"))
+            }
+        } else if node.attribute("euler").is_some() {
+            let e = vector_attribute(node, "euler", Vector3::zero())?;
+            Quaternion::from_angle_x(Rad(self.angle(e.x))) * Quaternion::from_angle_y(Rad(self.angle(e.y))) * Quaternion::from_angle_z(Rad(self.angle(e.z)))
+        } else {
+            Quaternion::new(1.0, 0.0, 0.0, 0.0)
+        };
+
+        Ok(Pose::new(position, rotation))
+    }
+
+    // Adds the mass of the geom to the link when it has no explicit inertial
+    fn geom(&mut self, node: Node, link: &mut RobotLink) -> io::Result<Option<RobotGeometry>> {
+        let defaults = self.geom_defaults.clone();
+        let kind = Self::attribute(&node, &defaults, "type").unwrap_or("sphere");
+        let size = numbers(Self::attribute(&node, &defaults, "size").unwrap_or(""))?;
+        let size = |i: usize| size.get(i).copied().unwrap_or(0.0);
+
+        let mut origin = self.pose(node)?;
+        let mut half_length = size(1);
+
+        // Capsules and cylinders between two points
+        if let Some(fromto) = node.attribute("fromto") {
+            let p = numbers(fromto)?;
+            if p.len() != 6 {
+                return Err(invalid_data("fromto needs 6 numbers"));
+            }
+            let (from, to) = (Vector3::new(p[0], p[1], p[2]), Vector3::new(p[3], p[4], p[5]));
+            let d = to - from;
+            half_length = d.magnitude() * 0.5;
+            let rotation = if half_length > 0.0 {
+                Quaternion::from_arc(Vector3::unit_z(), d.normalize(), None)
+            } else {
+                Quaternion::new(1.0, 0.0, 0.0, 0.0)
+            };
+            origin = Pose::new((from + to) * 0.5, rotation);
+        }
+
+        let shape = match kind {
+            "sphere" => GeometryShape::Sphere {radius: size(0)},
+            "box" => GeometryShape::Box {half_extents: Vector3::new(size(0), size(1), size(2))},
+            "capsule" => GeometryShape::Capsule {radius: size(0), half_length},
+            "cylinder" => GeometryShape::Cylinder {radius: size(0), half_length},
+            "mesh" => GeometryShape::Mesh {filename: node.attribute("mesh").unwrap_or("").to_string(), scale: Vector3::new(1.0, 1.0, 1.0)},
+            other => {
+                self.model.warnings.push(format!("body {}: geom type {} is not supported", link.name, other));
+                return Ok(None);
+            }
+        };
+
+        // Mass from density, MuJoCo defaults to water
+        let volume = match &shape {
+            GeometryShape::Sphere {radius} => Collider::sphere(*radius).volume(),
+            GeometryShape::Box {half_extents: h} => 8.0 * h.x * h.y * h.z,
+            GeometryShape::Capsule {radius, half_length} => Collider::capsule(*radius, *half_length).volume(),
+            GeometryShape::Cylinder {radius, half_length} => std::f64::consts::PI * radius * radius * 2.0 * half_length,
+            GeometryShape::Mesh {..} => 0.0
+        };
+        let density = number(node, "density")?.or(defaults.get("density").and_then(|d| d.parse().ok())).unwrap_or(1000.0);
+        let mass = number(node, "mass")?.unwrap_or(volume * density);
+
+        if mass > 0.0 {
+            // Cylinders use the inertia of a capsule of the same length
+            let (collider, rotation) = match &shape {
+                GeometryShape::Sphere {radius} => (Some(Collider::sphere(*radius)), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+                GeometryShape::Box {half_extents: h} => (Some(Collider::cuboid(h.x, h.y, h.z)), Quaternion::new(1.0, 0.0, 0.0, 0.0)),
+                GeometryShape::Capsule {radius, half_length} | GeometryShape::Cylinder {radius, half_length} => {
+                    (Some(Collider::capsule(*radius, *half_length)), y_to_z())
+                },
+                GeometryShape::Mesh {..} => (None, Quaternion::new(1.0, 0.0, 0.0, 0.0))
+            };
+            let local = match collider.map(|c| c.mass_properties(1.0)) {
+                Some((unit, inertia)) if unit > 0.0 => inertia * (mass / unit),
+                _ => Vector3::zero()
+            };
+
+            // Shift both inertias to the combined center of mass in the link frame
+            let axes: Matrix3<f64> = (origin.rotation * rotation).into();
+            let geom_inertia = axes * Matrix3::from_diagonal(local) * axes.transpose();
+            let link_inertia = {
+                let r: Matrix3<f64> = link.inertial.rotation.into();
+                r * link.inertia * r.transpose()
+            };
+
+            let total = link.mass + mass;
+            let center = (link.inertial.position * link.mass + origin.position * mass) / total;
+            let shift = |m: f64, p: Vector3<f64>| {
+                let d = p - center;
+                (Matrix3::identity() * d.magnitude2() - outer(d)) * m
+            };
+
+            link.inertia = link_inertia + shift(link.mass, link.inertial.position) + geom_inertia + shift(mass, origin.position);
+            link.inertial = Pose::new(center, Quaternion::new(1.0, 0.0, 0.0, 0.0));
+            link.mass = total;
+        }
+
+        Ok(Some(RobotGeometry {origin, shape}))
+    }
+
+    fn body(&mut self, node: Node, parent: &str) -> io::Result<()> {
+        let name = match node.attribute("name") {
+            Some(name) => name.to_string(),
+            None => format!("body{}", self.model.links.len())
+        };
+
+        let mut link = RobotLink::new(&name);
+        let explicit = child(node, "inertial");
+
+        for geom in children(node, "geom") {
+            let mut scratch = RobotLink::new(&name);
+            let target = if explicit.is_some() { &mut scratch } else { &mut link };
+            if let Some(geometry) = self.geom(geom, target)? {
+                let visible = geom.attribute("contype") == Some("0") && geom.attribute("conaffinity") == Some("0");
+                if visible {
+                    link.visuals.push(geometry);
+                } else {
+                    link.visuals.push(geometry.clone());
+                    link.collisions.push(geometry);
+                }
+            }
+        }
+
+        if let Some(inertial) = explicit {
+            link.inertial = self.pose(inertial)?;
+            link.mass = number(inertial, "mass")?.unwrap_or(0.0);
+
+            if let Some(diagonal) = inertial.attribute("diaginertia") {
+                let d = numbers(diagonal)?;
+                if d.len() == 3 {
+                    link.inertia = Matrix3::from_diagonal(Vector3::new(d[0], d[1], d[2]));
+                }
+            } else if let Some(full) = inertial.attribute("fullinertia") {
+                let f = numbers(full)?;
+                if f.len() == 6 {
+                    link.inertia = inertia_matrix(f[0], f[1], f[2], f[3], f[4], f[5]);
+                }
+            }
+        }
+
+        let joints: Vec<Node> = node.children().filter(|n| n.has_tag_name("joint") || n.has_tag_name("freejoint")).collect();
+        if joints.len() > 1 {
+            self.model.warnings.push(format!("body {}: only the first of {} joints is used", name, joints.len()));
+        }
+
+        let defaults = self.joint_defaults.clone();
+        let joint_type = match joints.first() {
+            None => RobotJointType::Fixed,
+            Some(joint) if joint.has_tag_name("freejoint") => RobotJointType::Floating,
+            Some(joint) => match Self::attribute(joint, &defaults, "type").unwrap_or("hinge") {
+                "hinge" => RobotJointType::Revolute,
+                "slide" => RobotJointType::Prismatic,
+                "ball" => RobotJointType::Ball,
+                "free" => RobotJointType::Floating,
+                other => return Err(invalid_data(format!("body {}: unknown joint type {}", name, other)))
+            }
+        };
+
+        let joint_name = joints.first().and_then(|j| j.attribute("name")).map(|n| n.to_string()).unwrap_or(format!("{}_joint", name));
+        let mut joint = RobotJoint::new(&joint_name, joint_type, parent, &name);
+        joint.origin = self.pose(node)?;
+
+        if let Some(node) = joints.first().filter(|j| j.has_tag_name("joint")) {
+            joint.anchor = vector_attribute(*node, "pos", Vector3::zero())?;
+            joint.axis = match Self::attribute(node, &defaults, "axis") {
+                Some(axis) => match numbers(axis)?.as_slice() {
+                    [x, y, z] => Vector3::new(*x, *y, *z).normalize(),
+                    _ => return Err(invalid_data("axis needs 3 numbers"))
+                },
+                None => Vector3::unit_z()
+            };
+
+            let limited = Self::attribute(node, &defaults, "limited") != Some("false");
+            if let Some(range) = Self::attribute(node, &defaults, "range").filter(|_| limited) {
+                if let [lower, upper] = numbers(range)?.as_slice() {
+                    joint.limits = Some(match joint_type {
+                        RobotJointType::Revolute => (self.angle(*lower), self.angle(*upper)),
+                        _ => (*lower, *upper)
+                    });
+                }
+            }
+        }
+
+        self.model.links.push(link);
+        self.model.joints.push(joint);
+
+        for body in children(node, "body") {
+            self.body(body, &name)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn outer(d: Vector3<f64>) -> Matrix3<f64> {
+    Matrix3::from_cols(d * d.x, d * d.y, d * d.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+    use super::{Pose, RobotModel};
+    use crate::engine::physics::PhysicsWorld;
+    use crate::engine::physics::collider::compute_aabb;
+
+    #[test]
+    fn robot_collision_keeps_its_offset_from_the_center_of_mass() {
+        let urdf = r#"<robot name="arm">
+            <link name="base"/>
+            <link name="upper">
+                <inertial><origin xyz="0 0 0.1"/><mass value="2"/><inertia ixx="0.02" iyy="0.03" izz="0.01" ixy="0.005" ixz="0" iyz="0"/></inertial>
+                <collision><origin xyz="0 0 0.25"/><geometry><box size="0.1 0.1 0.5"/></geometry></collision>
+            </link>
+            <joint name="shoulder" type="revolute">
+                <parent link="base"/><child link="upper"/><origin xyz="0 0 1"/><axis xyz="0 1 0"/>
+                <limit lower="-1" upper="1" effort="3" velocity="2"/>
+            </joint>
+        </robot>"#;
+
+        let model = RobotModel::parse_urdf(urdf).unwrap();
+        let mut physics = PhysicsWorld::new();
+        let robot = model.spawn(&mut physics, Pose::identity(), true);
+        assert!(robot.warnings.is_empty(), "{:?}", robot.warnings);
+
+        let upper = physics.get_body(robot.body("upper").unwrap()).unwrap();
+        assert!((upper.position - Vector3::new(0.0, 0.0, 1.1)).magnitude() < 1.0e-9, "body at {:?}", upper.position);
+
+        let aabb = compute_aabb(upper).unwrap();
+        assert!((aabb.min - Vector3::new(-0.05, -0.05, 1.0)).magnitude() < 1.0e-9, "box from {:?}", aabb.min);
+        assert!((aabb.max - Vector3::new(0.05, 0.05, 1.5)).magnitude() < 1.0e-9, "box to {:?}", aabb.max);
+
+        let joint = &physics.joints()[robot.joint("shoulder").unwrap().0];
+        assert_eq!((joint.max_motor_force, joint.max_motor_speed), (3.0, 2.0));
+    }
+}
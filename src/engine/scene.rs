@@ -0,0 +1,396 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use cgmath::{InnerSpace, Quaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use super::physics::PhysicsWorld;
+use super::physics::body::{BodyHandle, BodyType, RigidBody};
+use super::physics::collider::{Collider, Shape};
+use super::physics::force::Gravity;
+use super::physics::joint::{Joint, JointKind, JointMotor};
+use super::physics::mesh::{Heightfield, TriMesh};
+
+// Scene files are JSON, vectors are [x, y, z] and rotations [w, x, y, z]
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SceneBodyType {
+    Dynamic,
+    Kinematic,
+    Static
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SceneShape {
+    Sphere { radius: f64 },
+    Cuboid { half_extents: [f64; 3] },
+    Capsule { radius: f64, half_height: f64 },
+    Plane { normal: [f64; 3], offset: f64 },
+    Heightfield { heights: Vec<Vec<f64>>, scale: [f64; 3] },
+    TriMesh { vertices: Vec<[f64; 3]>, indices: Vec<[usize; 3]> }
+}
+
+fn identity() -> [f64; 4] {
+    [1.0, 0.0, 0.0, 0.0]
+}
+
+fn default_density() -> f64 {
+    1.0
+}
+
+fn default_restitution() -> f64 {
+    0.2
+}
+
+fn default_friction() -> f64 {
+    0.5
+}
+
+fn default_collide() -> bool {
+    true
+}
+
+fn default_gravity() -> [f64; 3] {
+    [0.0, -9.81, 0.0]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SceneBody {
+    #[serde(rename = "type")]
+    pub body_type: SceneBodyType,
+    pub position: [f64; 3],
+    #[serde(default = "identity")]
+    pub rotation: [f64; 4],
+    #[serde(default)]
+    pub velocity: [f64; 3],
+    #[serde(default)]
+    pub angular_velocity: [f64; 3],
+    #[serde(default)]
+    pub shape: Option<SceneShape>,
+    // Pose of the shape relative to the body
+    #[serde(default)]
+    pub shape_offset: [f64; 3],
+    #[serde(default = "identity")]
+    pub shape_rotation: [f64; 4],
+    #[serde(default = "default_density")]
+    pub density: f64,
+    // Override the mass properties from the shape and density
+    #[serde(default)]
+    pub mass: Option<f64>,
+    #[serde(default)]
+    pub inertia: Option<[f64; 3]>,
+    #[serde(default = "default_restitution")]
+    pub restitution: f64,
+    #[serde(default = "default_friction")]
+    pub friction: f64
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SceneJointKind {
+    Ball,
+    Distance { length: f64 },
+    Fixed,
+    Hinge { axis: [f64; 3], #[serde(default)] limits: Option<[f64; 2]> },
+    Prismatic { axis: [f64; 3], #[serde(default)] limits: Option<[f64; 2]> }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SceneJointMotor {
+    Velocity { speed: f64, max_force: f64 },
+    Position { target: f64, max_speed: f64, max_force: f64 },
+    Pd { target: f64, #[serde(default)] target_speed: f64, kp: f64, kd: f64, max_force: f64 }
+}
+
+impl SceneJointMotor {
+    fn motor(&self) -> JointMotor {
+        match *self {
+            Self::Velocity {speed, max_force} => JointMotor::Velocity {speed, max_force},
+            Self::Position {target, max_speed, max_force} => JointMotor::Position {target, max_speed, max_force},
+            Self::Pd {target, target_speed, kp, kd, max_force} => JointMotor::Pd {target, target_speed, kp, kd, max_force}
+        }
+    }
+
+    fn from_motor(motor: &JointMotor) -> Self {
+        match *motor {
+            JointMotor::Velocity {speed, max_force} => Self::Velocity {speed, max_force},
+            JointMotor::Position {target, max_speed, max_force} => Self::Position {target, max_speed, max_force},
+            JointMotor::Pd {target, target_speed, kp, kd, max_force} => Self::Pd {target, target_speed, kp, kd, max_force}
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SceneJoint {
+    pub kind: SceneJointKind,
+    // Index into the bodies of the scene
+    pub body_a: usize,
+    // None - attached to the world at `anchor_b`
+    #[serde(default)]
+    pub body_b: Option<usize>,
+    #[serde(default)]
+    pub anchor_a: [f64; 3],
+    #[serde(default)]
+    pub anchor_b: [f64; 3],
+    // Rotation of `body_b` relative to `body_a` at angle zero, the starting one when missing
+    #[serde(default)]
+    pub rest_rotation: Option<[f64; 4]>,
+    #[serde(default = "default_collide")]
+    pub collide_connected: bool,
+    #[serde(default)]
+    pub motor: Option<SceneJointMotor>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Scene {
+    #[serde(default = "default_gravity")]
+    pub gravity: [f64; 3],
+    #[serde(default)]
+    pub bodies: Vec<SceneBody>,
+    #[serde(default)]
+    pub joints: Vec<SceneJoint>
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn vector(v: [f64; 3]) -> Vector3<f64> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn array(v: Vector3<f64>) -> [f64; 3] {
+    [v.x, v.y, v.z]
+}
+
+impl SceneShape {
+    fn validate(&self) -> io::Result<()> {
+        match self {
+            Self::Heightfield {heights, ..} => {
+                let columns = heights.first().map(|r| r.len()).unwrap_or(0);
+                if heights.len() < 2 || columns < 2 {
+                    return Err(invalid_data("a heightfield needs at least 2x2 samples"));
+                }
+            },
+            Self::TriMesh {vertices, indices} => {
+                if indices.is_empty() {
+                    return Err(invalid_data("a triangle mesh needs at least one triangle"));
+                }
+                if indices.iter().flatten().any(|i| *i >= vertices.len()) {
+                    return Err(invalid_data(format!("triangle mesh index out of {} vertices", vertices.len())));
+                }
+            },
+            Self::Plane {normal, ..} if vector(*normal).magnitude2() == 0.0 => {
+                return Err(invalid_data("plane normal is zero"));
+            },
+            _ => ()
+        }
+
+        Ok(())
+    }
+
+    fn collider(&self) -> Collider {
+        match self {
+            Self::Sphere {radius} => Collider::sphere(*radius),
+            Self::Cuboid {half_extents: h} => Collider::cuboid(h[0], h[1], h[2]),
+            Self::Capsule {radius, half_height} => Collider::capsule(*radius, *half_height),
+            Self::Plane {normal, offset} => Collider::plane(vector(*normal), *offset),
+            Self::Heightfield {heights, scale} => Collider::heightfield(Heightfield::new(heights.clone(), vector(*scale))),
+            Self::TriMesh {vertices, indices} => {
+                Collider::trimesh(TriMesh::new(vertices.iter().map(|v| vector(*v)).collect(), indices.clone()))
+            }
+        }
+    }
+
+    fn from_shape(shape: &Shape) -> Self {
+        match shape {
+            Shape::Sphere {radius} => Self::Sphere {radius: *radius},
+            Shape::Cuboid {half_extents} => Self::Cuboid {half_extents: array(*half_extents)},
+            Shape::Capsule {radius, half_height} => Self::Capsule {radius: *radius, half_height: *half_height},
+            Shape::Plane {normal, offset} => Self::Plane {normal: array(*normal), offset: *offset},
+            Shape::Heightfield {field} => Self::Heightfield {heights: field.heights(), scale: array(field.scale())},
+            Shape::TriMesh {mesh} => Self::TriMesh {
+                vertices: mesh.vertices().iter().map(|v| array(*v)).collect(),
+                indices: mesh.indices().clone()
+            }
+        }
+    }
+}
+
+impl SceneBody {
+    pub fn build(&self) -> RigidBody {
+        let body_type = match self.body_type {
+            SceneBodyType::Dynamic => BodyType::Dynamic,
+            SceneBodyType::Kinematic => BodyType::Kinematic,
+            SceneBodyType::Static => BodyType::Static
+        };
+
+        let mut body = RigidBody::new(body_type, vector(self.position));
+        if let Some(shape) = self.shape.as_ref() {
+            let [w, x, y, z] = self.shape_rotation;
+            let collider = shape.collider().with_offset(vector(self.shape_offset), Quaternion::new(w, x, y, z));
+            body = body.with_collider(collider, self.density);
+        }
+
+        if let (Some(mass), BodyType::Dynamic) = (self.mass, body_type) {
+            // Keep the shape of the inertia when only the mass is given
+            let inertia = match self.inertia {
+                Some(inertia) => vector(inertia),
+                None if body.mass() > 0.0 => body.inertia() * (mass / body.mass()),
+                None => Vector3::new(mass, mass, mass)
+            };
+            body.set_mass_properties(mass, inertia);
+        }
+
+        let [w, x, y, z] = self.rotation;
+        body.rotation = Quaternion::new(w, x, y, z).normalize();
+        body.velocity = vector(self.velocity);
+        body.angular_velocity = vector(self.angular_velocity);
+        body.restitution = self.restitution;
+        body.friction = self.friction;
+
+        body
+    }
+
+    pub fn capture(body: &RigidBody) -> Self {
+        let body_type = match body.body_type() {
+            BodyType::Dynamic => SceneBodyType::Dynamic,
+            BodyType::Kinematic => SceneBodyType::Kinematic,
+            BodyType::Static => SceneBodyType::Static
+        };
+        let dynamic = body_type == SceneBodyType::Dynamic;
+        let q = body.rotation;
+
+        Self {
+            body_type,
+            position: array(body.position),
+            rotation: [q.s, q.v.x, q.v.y, q.v.z],
+            velocity: array(body.velocity),
+            angular_velocity: array(body.angular_velocity),
+            shape: body.shape().map(SceneShape::from_shape),
+            shape_offset: body.collider().map(|c| array(c.offset)).unwrap_or_default(),
+            shape_rotation: body.collider().map(|c| [c.rotation.s, c.rotation.v.x, c.rotation.v.y, c.rotation.v.z]).unwrap_or(identity()),
+            density: default_density(),
+            mass: if dynamic { Some(body.mass()) } else { None },
+            inertia: if dynamic { Some(array(body.inertia())) } else { None },
+            restitution: body.restitution,
+            friction: body.friction
+        }
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {gravity: default_gravity(), bodies: Vec::new(), joints: Vec::new()}
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let scene: Self = serde_json::from_reader(reader).map_err(invalid_data)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Checks what `build` relies on: shapes it can construct, joints between existing bodies and usable axes.
+    pub fn validate(&self) -> io::Result<()> {
+        for shape in self.bodies.iter().filter_map(|b| b.shape.as_ref()) {
+            shape.validate()?;
+        }
+
+        if self.bodies.iter().any(|b| b.shape_rotation.iter().all(|x| *x == 0.0)) {
+            return Err(invalid_data("shape rotation is zero"));
+        }
+
+        for joint in self.joints.iter() {
+            let count = self.bodies.len();
+            if joint.body_a >= count || joint.body_b.is_some_and(|b| b >= count) {
+                return Err(invalid_data(format!("joint refers to a body out of {} bodies", count)));
+            }
+
+            match joint.kind {
+                SceneJointKind::Hinge {axis, ..} | SceneJointKind::Prismatic {axis, ..} if vector(axis).magnitude2() == 0.0 => {
+                    return Err(invalid_data("joint axis is zero"));
+                },
+                _ => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(invalid_data)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Adds the scene to the world, replacing its gravity. Returns the handles in scene order.
+    /// Scenes that were not loaded from a file should pass `validate` first.
+    pub fn build(&self, physics: &mut PhysicsWorld) -> Vec<BodyHandle> {
+        physics.clear_forces();
+        let gravity = vector(self.gravity);
+        if gravity.magnitude2() > 0.0 {
+            physics.add_force(Gravity::new(gravity).build());
+        }
+
+        let handles: Vec<BodyHandle> = self.bodies.iter().map(|b| physics.add_body(b.build())).collect();
+
+        for joint in self.joints.iter() {
+            let limits = |l: Option<[f64; 2]>| l.map(|[lower, upper]| (lower, upper));
+            let kind = match joint.kind {
+                SceneJointKind::Ball => JointKind::Ball,
+                SceneJointKind::Distance {length} => JointKind::Distance {length},
+                SceneJointKind::Fixed => JointKind::Fixed,
+                SceneJointKind::Hinge {axis, limits: l} => JointKind::Hinge {axis: vector(axis).normalize(), limits: limits(l)},
+                SceneJointKind::Prismatic {axis, limits: l} => JointKind::Prismatic {axis: vector(axis).normalize(), limits: limits(l)}
+            };
+
+            let body_b = joint.body_b.map(|b| handles[b]);
+            let mut built = Joint::new(kind, handles[joint.body_a], vector(joint.anchor_a), body_b, vector(joint.anchor_b))
+                .with_collide_connected(joint.collide_connected);
+            if let Some([w, x, y, z]) = joint.rest_rotation {
+                built = built.with_rest_rotation(Quaternion::new(w, x, y, z));
+            }
+            built.motor = joint.motor.map(|m| m.motor());
+
+            physics.add_joint(built);
+        }
+
+        handles
+    }
+
+    /// Current state of the bodies and joints of the world, forces other than gravity are not kept.
+    pub fn capture(physics: &PhysicsWorld, gravity: Vector3<f64>) -> Self {
+        let bodies = physics.bodies().iter().map(SceneBody::capture).collect();
+
+        let limits = |l: Option<(f64, f64)>| l.map(|(lower, upper)| [lower, upper]);
+        let joints = physics.joints().iter().map(|joint| SceneJoint {
+            kind: match joint.kind {
+                JointKind::Ball => SceneJointKind::Ball,
+                JointKind::Distance {length} => SceneJointKind::Distance {length},
+                JointKind::Fixed => SceneJointKind::Fixed,
+                JointKind::Hinge {axis, limits: l} => SceneJointKind::Hinge {axis: array(axis), limits: limits(l)},
+                JointKind::Prismatic {axis, limits: l} => SceneJointKind::Prismatic {axis: array(axis), limits: limits(l)}
+            },
+            body_a: joint.body_a.0,
+            body_b: joint.body_b.map(|b| b.0),
+            anchor_a: array(joint.anchor_a),
+            anchor_b: array(joint.anchor_b),
+            rest_rotation: joint.rest_rotation.map(|q| [q.s, q.v.x, q.v.y, q.v.z]),
+            collide_connected: joint.collide_connected,
+            motor: joint.motor.as_ref().map(SceneJointMotor::from_motor)
+        }).collect();
+
+        Self {gravity: array(gravity), bodies, joints}
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use super::{Camera, World};
+use crate::engine::physics::PhysicsSnapshot;
+use crate::engine::physics::character::CharacterController;
+
+#[derive(Clone)]
+pub struct WorldSnapshot {
+    camera: Camera,
+    character: Option<CharacterController>,
+    objects: Vec<Vec<f64>>,
+    physics: PhysicsSnapshot
+}
+
+impl WorldSnapshot {
+    pub fn capture(world: &World) -> Self {
+        let camera = world.camera.clone();
+        let character = world.character.clone();
+        let objects = world.objects.iter().map(|o| o.save_state()).collect();
+        let physics = world.physics.snapshot();
+
+        Self {camera, character, objects, physics}
+    }
+
+    /// Objects created after the snapshot was taken are removed.
+    pub fn restore(&self, world: &mut World) {
+        world.camera = self.camera.clone();
+        world.character = self.character.clone();
+
+        world.objects.truncate(self.objects.len());
+        for (object, state) in world.objects.iter_mut().zip(self.objects.iter()) {
+            object.load_state(state);
+        }
+
+        world.physics.restore(&self.physics);
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.physics.ticks()
+    }
+}
+
+// Ring buffer of the last `capacity` ticks with a cursor for stepping through them
+pub struct SnapshotHistory {
+    capacity: usize,
+    snapshots: VecDeque<WorldSnapshot>,
+    cursor: usize
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {capacity, snapshots: VecDeque::with_capacity(capacity), cursor: 0}
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.snapshots.len() > capacity {
+            self.snapshots.pop_front();
+        }
+        self.cursor = self.cursor.min(self.snapshots.len().saturating_sub(1));
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.cursor = 0;
+    }
+
+    /// Drops everything after the cursor, the simulation continues from there.
+    pub fn push(&mut self, snapshot: WorldSnapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if !self.snapshots.is_empty() {
+            self.snapshots.truncate(self.cursor + 1);
+        }
+
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(snapshot);
+        self.cursor = self.snapshots.len() - 1;
+    }
+
+    pub fn current(&self) -> Option<&WorldSnapshot> {
+        self.snapshots.get(self.cursor)
+    }
+
+    pub fn latest(&self) -> Option<&WorldSnapshot> {
+        self.snapshots.back()
+    }
+
+    pub fn is_at_latest(&self) -> bool {
+        self.snapshots.is_empty() || self.cursor == self.snapshots.len() - 1
+    }
+
+    pub fn step_back(&mut self) -> Option<&WorldSnapshot> {
+        if self.cursor == 0 {
+            return None;
+        }
+
+        self.cursor -= 1;
+        self.snapshots.get(self.cursor)
+    }
+
+    pub fn step_forward(&mut self) -> Option<&WorldSnapshot> {
+        if self.is_at_latest() {
+            return None;
+        }
+
+        self.cursor += 1;
+        self.snapshots.get(self.cursor)
+    }
+
+    pub fn find(&self, tick: u64) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().find(|s| s.tick() == tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+    use crate::engine::physics::body::RigidBody;
+    use crate::engine::physics::collider::Collider;
+    use crate::engine::physics::force::Wind;
+    use crate::engine::world::World;
+    use crate::engine::world::context::InputContext;
+
+    fn run(world: &mut World, ticks: usize) {
+        let input = InputContext::new();
+        for _ in 0..ticks {
+            world.step_forward(&input, 1.0 / 240.0);
+        }
+    }
+
+    #[test]
+    fn restore_matches_force_state_by_handle() {
+        let world = World::new("Gusts");
+        let physics = world.get_physics();
+        physics.clear_forces();
+        let gusts = physics.add_force(Wind::new(Vector3::new(5.0, 0.0, 0.0), 0.5).with_turbulence(1.0, 4.0).build());
+        physics.add_body(RigidBody::dynamic(Vector3::zero()).with_collider(Collider::sphere(0.5), 1.0));
+        run(world, 120);
+
+        let snapshot = world.snapshot();
+        let gust = world.get_physics().get_force_mut(gusts).unwrap().save_state();
+        assert!(gust.iter().any(|g| *g != 0.0));
+
+        run(world, 120);
+        world.restore(&snapshot);
+        assert_eq!(world.get_physics().get_force_mut(gusts).unwrap().save_state(), gust);
+
+        // A new generator in the same slot is not the one that was saved
+        let physics = world.get_physics();
+        physics.clear_forces();
+        let calm = physics.add_force(Wind::new(Vector3::new(5.0, 0.0, 0.0), 0.5).with_turbulence(1.0, 4.0).build());
+        assert_eq!(calm, gusts);
+        world.restore(&snapshot);
+        assert_eq!(world.get_physics().get_force_mut(calm).unwrap().save_state(), vec![0.0; 3]);
+    }
+}
@@ -0,0 +1,206 @@
+// Reference scenarios checked against analytic solutions or against a second run,
+// they only step the physics of a `World` and need no window or GPU
+use std::f64::consts::PI;
+use cgmath::{InnerSpace, Vector3, Zero};
+use super::world::World;
+use super::world::context::InputContext;
+use super::physics::body::{BodyHandle, RigidBody};
+use super::physics::collider::Collider;
+use super::physics::joint::{Joint, JointKind};
+
+const GRAVITY: f64 = 9.81;
+const TIMESTEP: f64 = 1.0 / 240.0;
+
+fn world(name: &str) -> &'static mut World {
+    let world = World::new(name);
+    world.get_physics().settings.sleep_enabled = false;
+    world
+}
+
+fn body(world: &mut World, handle: BodyHandle) -> &RigidBody {
+    &world.get_physics().bodies()[handle.0]
+}
+
+fn run(world: &mut World, seconds: f64) {
+    let input = InputContext::new();
+    for _ in 0..(seconds / TIMESTEP).round() as usize {
+        world.step_forward(&input, TIMESTEP);
+    }
+}
+
+#[test]
+fn free_fall_matches_analytic_solution() {
+    let world = world("Free fall");
+    let start = Vector3::new(0.0, 100.0, 0.0);
+    let ball = world.get_physics().add_body(RigidBody::dynamic(start).with_collider(Collider::sphere(0.5), 1.0));
+
+    let time = 2.0;
+    run(world, time);
+
+    let expected_height = start.y - 0.5 * GRAVITY * time * time;
+    let expected_velocity = -GRAVITY * time;
+    let body = body(world, ball);
+
+    // Semi-implicit Euler is ahead by g * t * dt / 2
+    assert!((body.position.y - expected_height).abs() < GRAVITY * time * TIMESTEP, "height {} expected {}", body.position.y, expected_height);
+    assert!((body.velocity.y - expected_velocity).abs() < 1.0e-9, "velocity {} expected {}", body.velocity.y, expected_velocity);
+    assert!(body.position.x.abs() < 1.0e-12 && body.position.z.abs() < 1.0e-12);
+}
+
+#[test]
+fn elastic_collision_conserves_momentum_and_energy() {
+    let world = world("Elastic collision");
+    world.get_physics().clear_forces();
+
+    let mut add = |x: f64, velocity: f64, radius: f64| {
+        let mut ball = RigidBody::dynamic(Vector3::new(x, 0.0, 0.0)).with_collider(Collider::sphere(radius), 1.0);
+        ball.velocity = Vector3::new(velocity, 0.0, 0.0);
+        ball.restitution = 1.0;
+        ball.friction = 0.0;
+        world.get_physics().add_body(ball)
+    };
+    let a = add(-2.0, 4.0, 0.5);
+    let b = add(2.0, -1.0, 0.8);
+
+    let momentum = |world: &mut World| {
+        [a, b].iter().fold(Vector3::zero(), |sum, h| {
+            let body = body(world, *h);
+            sum + body.velocity * body.mass()
+        })
+    };
+    let energy = |world: &mut World| {
+        [a, b].iter().map(|h| {
+            let body = body(world, *h);
+            0.5 * body.mass() * body.velocity.magnitude2()
+        }).sum::<f64>()
+    };
+
+    let (p0, e0) = (momentum(world), energy(world));
+    run(world, 2.0);
+    let (p1, e1) = (momentum(world), energy(world));
+
+    // The balls have to have bounced off each other
+    assert!(body(world, a).velocity.x < 0.0 && body(world, b).velocity.x > 0.0);
+    assert!((p1 - p0).magnitude() < 1.0e-9 * p0.magnitude().max(1.0), "momentum {:?} -> {:?}", p0, p1);
+    assert!((e1 - e0).abs() < 0.02 * e0, "energy {} -> {}", e0, e1);
+}
+
+#[test]
+fn pendulum_period_matches_small_angle_solution() {
+    let world = world("Pendulum");
+    let length = 2.0;
+    let pivot = Vector3::new(0.0, 10.0, 0.0);
+    let angle: f64 = 0.05;
+
+    let start = pivot + Vector3::new(angle.sin(), -angle.cos(), 0.0) * length;
+    let bob = world.get_physics().add_body(RigidBody::dynamic(start).with_collider(Collider::sphere(0.05), 1.0));
+    world.get_physics().add_joint(Joint::to_world(bob, Vector3::zero(), pivot, JointKind::Distance {length})).unwrap();
+
+    // Times the bob passes the lowest point moving in the same direction
+    let input = InputContext::new();
+    let mut crossings = Vec::new();
+    let mut previous = start.x;
+    let mut time = 0.0;
+    while crossings.len() < 4 && time < 20.0 {
+        world.step_forward(&input, TIMESTEP);
+        time += TIMESTEP;
+
+        let x = body(world, bob).position.x;
+        if previous > 0.0 && x <= 0.0 {
+            crossings.push(time - TIMESTEP * x / (x - previous));
+        }
+        previous = x;
+    }
+
+    assert_eq!(crossings.len(), 4, "pendulum stopped swinging");
+    let period = (crossings[3] - crossings[0]) / 3.0;
+    let expected = 2.0 * PI * (length / GRAVITY).sqrt();
+    assert!((period - expected).abs() < 0.01 * expected, "period {} expected {}", period, expected);
+
+    let distance = (body(world, bob).position - pivot).magnitude();
+    assert!((distance - length).abs() < 0.01 * length, "rod length {}", distance);
+}
+
+#[test]
+fn box_stack_settles_and_sleeps() {
+    // Sleeping is what keeps stacks still, so it stays on here
+    let world = World::new("Box stack");
+    world.get_physics().add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+
+    let size = 0.5;
+    let boxes: Vec<BodyHandle> = (0..5).map(|i| {
+        let position = Vector3::new(0.0, size + 2.0 * size * i as f64, 0.0);
+        world.get_physics().add_body(RigidBody::dynamic(position).with_collider(Collider::cuboid(size, size, size), 1.0))
+    }).collect();
+
+    run(world, 4.0);
+
+    for (i, handle) in boxes.iter().enumerate() {
+        let body = body(world, *handle);
+        let expected = size + 2.0 * size * i as f64;
+
+        assert!(body.is_sleeping(), "box {} is still awake", i);
+        assert!((body.position.y - expected).abs() < 0.05, "box {} at height {} expected {}", i, body.position.y, expected);
+        assert!(Vector3::new(body.position.x, 0.0, body.position.z).magnitude() < 0.05, "box {} slid to {:?}", i, body.position);
+    }
+}
+
+#[test]
+fn projectile_range_matches_analytic_solution() {
+    let world = world("Projectile");
+    let speed: f64 = 20.0;
+    let angle = PI / 4.0;
+    let start = Vector3::new(0.0, 50.0, 0.0);
+
+    let mut ball = RigidBody::dynamic(start).with_collider(Collider::sphere(0.1), 1.0);
+    ball.velocity = Vector3::new(angle.cos(), angle.sin(), 0.0) * speed;
+    let ball = world.get_physics().add_body(ball);
+
+    // Range where the ball comes back down to the launch height
+    let input = InputContext::new();
+    let mut previous = start;
+    let mut range = None;
+    for _ in 0..(10.0 / TIMESTEP) as usize {
+        world.step_forward(&input, TIMESTEP);
+        let position = body(world, ball).position;
+
+        if previous.y > start.y && position.y <= start.y {
+            let t = (previous.y - start.y) / (previous.y - position.y);
+            range = Some(previous.x + (position.x - previous.x) * t);
+            break;
+        }
+        previous = position;
+    }
+
+    let range = range.expect("projectile never came back down");
+    let expected = speed * speed * (2.0 * angle).sin() / GRAVITY;
+    assert!((range - expected).abs() < 0.01 * expected, "range {} expected {}", range, expected);
+}
+
+#[test]
+fn identical_worlds_hash_equal() {
+    let build = |name: &str| {
+        let world = World::new(name);
+        world.get_physics().set_deterministic(TIMESTEP, 7);
+        world.get_physics().add_body(RigidBody::fixed(Vector3::zero()).with_collider(Collider::plane(Vector3::unit_y(), 0.0), 1.0));
+
+        for i in 0..6 {
+            let mut body = RigidBody::dynamic(Vector3::new(0.3 * i as f64, 1.0 + 1.2 * i as f64, 0.0)).with_collider(Collider::cuboid(0.5, 0.5, 0.5), 1.0);
+            body.angular_velocity = Vector3::new(0.0, 0.5 * i as f64, 1.0);
+            world.get_physics().add_body(body);
+        }
+        world
+    };
+
+    let a = build("Determinism A");
+    let b = build("Determinism B");
+
+    let input = InputContext::new();
+
+    for tick in 0..600 {
+        a.step_forward(&input, TIMESTEP);
+        b.step_forward(&input, TIMESTEP);
+        assert_eq!(a.state_hash(), b.state_hash(), "worlds diverged at tick {}", tick);
+    }
+    assert_eq!(a.get_physics().ticks(), 600);
+}
@@ -1,14 +1,41 @@
 use std::borrow::BorrowMut;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
 use std::sync::Arc;
-use cgmath::{InnerSpace, Rotation, Rotation3, Vector3, Zero};
+use cgmath::{InnerSpace, Rotation, Rotation3, Vector2, Vector3, Zero};
+use winit::event::{MouseButton, VirtualKeyCode};
 
 #[path="./context.rs"]
 pub mod context;
-use context::EngineContext;
+use context::{EngineContext, InputContext};
+
+#[path="./snapshot.rs"]
+pub mod snapshot;
+use snapshot::{SnapshotHistory, WorldSnapshot};
+
+#[path="./camera.rs"]
+pub mod camera;
+pub use camera::{Camera, CameraController, CameraControllerType, CameraInput, Damping, FollowController, FreeFlyController, OrbitController, Projection};
+
+use super::physics::PhysicsWorld;
+use super::physics::force::{ForceHandle, ForceType};
+use super::physics::determinism::StateHasher;
+use super::physics::softbody::{SoftBody, SoftBodyHandle};
+use super::physics::fluid::{Fluid, FluidHandle};
+use super::physics::nbody::{ForceModel, NBodySettings};
+use super::physics::character::CharacterController;
+use super::physics::vehicle::{Vehicle, VehicleHandle};
+use super::physics::fracture::{Destructible, DestructibleHandle};
+use super::physics::debug::DebugDraw;
+use super::particles::{Emitter, EmitterHandle, ParticleSystem};
+use super::export::{DataRecorder, ExportSettings};
+use super::robot::{Pose, Robot, RobotModel};
+use super::replay::ReplayPlayer;
 
 pub(crate) type ObjectType = Box<dyn Object + Sync + Send>;
 
+#[derive(Clone)]
 pub struct Transform {
     position: Vector3<f64>,
     direction: Vector3<f64>,
@@ -30,13 +57,29 @@ impl Transform {
 
         Self {position, direction, scale}
     }
+
+    pub fn get_position(&self) -> Vector3<f64> {
+        self.position
+    }
+
+    pub fn get_direction(&self) -> Vector3<f64> {
+        self.direction
+    }
 }
 
 pub trait Object {
     fn new(_name: &str, transform: Transform) -> ObjectType where Self: Sized;
 
-    fn on_update(&mut self, _ctx: &EngineContext) { /* Empty */ }
+    fn on_update(&mut self, _input: &InputContext) { /* Empty */ }
+    // Before the physics step, for driving joint motors and applying forces
+    fn on_physics_update(&mut self, _physics: &mut PhysicsWorld, _delta: f64) { /* Empty */ }
     fn on_draw(&self, _ctx: &EngineContext);
+
+    fn get_transform(&self) -> Option<&Transform> { None }
+
+    // Object data for snapshots
+    fn save_state(&self) -> Vec<f64> { Vec::new() }
+    fn load_state(&mut self, _state: &[f64]) { /* Empty */ }
 }
 
 pub struct Rectangle {
@@ -54,44 +97,46 @@ impl Object for Rectangle {
     fn on_draw(&self, _ctx: &EngineContext) {
         // pass
     }
-}
-
-pub struct Camera {
-    pub transform: Transform,
-    pub velocity: Vector3<f64>,
-    pub fov: f64,
 
-    max_speed: Vector3<f64>,
-    speed: Vector3<f64>
-}
-
-impl Camera {
-    pub fn new(transform: Transform, fov: f64) -> Self {
-        let velocity = Vector3::zero();
-
-        let max_speed = Vector3::new(10.0, 5.0, 10.0);
-        let speed = Vector3::new(0.2, 0.1, 0.2);
+    fn get_transform(&self) -> Option<&Transform> {
+        Some(&self.transform)
+    }
 
-        Self {transform, max_speed, speed, fov, velocity}
+    fn save_state(&self) -> Vec<f64> {
+        let t = &self.transform;
+        vec![
+            t.position.x, t.position.y, t.position.z,
+            t.direction.x, t.direction.y, t.direction.z,
+            t.scale.x, t.scale.y, t.scale.z
+        ]
     }
 
-    pub fn update(&mut self, delta: f64) {
-        self.transform.position += self.velocity * delta;
-        self.velocity -= 0.01 * self.max_speed;
-        self.velocity.x = r_float(self.velocity.x, 3).clamp(-self.max_speed.x, self.max_speed.x);
-        self.velocity.y = r_float(self.velocity.y, 3).clamp(-self.max_speed.y, self.max_speed.y);
-        self.velocity.z = r_float(self.velocity.z, 3).clamp(-self.max_speed.z, self.max_speed.z);
+    fn load_state(&mut self, state: &[f64]) {
+        if state.len() != 9 {
+            return;
+        }
+
+        self.transform.position = Vector3::new(state[0], state[1], state[2]);
+        self.transform.direction = Vector3::new(state[3], state[4], state[5]);
+        self.transform.scale = Vector3::new(state[6], state[7], state[8]);
     }
 }
 
 pub struct World {
     name: &'static str,
     camera: Camera,
-    objects: &'static mut Vec<ObjectType>
-}
-
-fn r_float(x: f64, a: u32) -> f64 {
-    (x * (10_i32.pow(a) as f64)).round() / (10_i32.pow(a) as f64)
+    camera_controller: CameraControllerType,
+    camera_input: CameraInput,
+    objects: &'static mut Vec<ObjectType>,
+    physics: PhysicsWorld,
+    particles: ParticleSystem,
+    character: Option<CharacterController>,
+    debug: DebugDraw,
+    recorder: Option<DataRecorder>,
+    debug_visible: bool,
+
+    history: SnapshotHistory,
+    paused: bool
 }
 
 impl World {
@@ -106,18 +151,193 @@ impl World {
         let camera_transform = Transform::new([2.0, 2.0, 2.0].into());
 
         let camera = Camera::new(camera_transform, 70.0);
+        let camera_controller: CameraControllerType = Box::new(FreeFlyController::new());
+
+        let physics = PhysicsWorld::new();
+        let particles = ParticleSystem::new(16384);
+        let history = SnapshotHistory::new(0);
+
+        Box::leak(Box::new(Self {name, camera, camera_controller, camera_input: CameraInput::new(), objects, physics, particles, character: None, debug: DebugDraw::new(), recorder: None, debug_visible: false, history, paused: false}))
+    }
+
+    pub fn update(&mut self, input: &InputContext, delta: f64) {
+        if self.paused {
+            // Keeps showing the state stepped to, recordings only get simulated frames
+            if self.debug_visible && !self.debug.is_recording() {
+                self.debug.draw_world(&self.physics);
+            }
+            return;
+        }
+
+        self.tick(input, delta);
+    }
+
+    /// One tick driven by `input`: recorded events of the tick, the update and the keys the world
+    /// reacts to. The caller ends the tick with `InputContext::update` after its own keys.
+    pub fn process_tick(&mut self, input: &mut InputContext, delta: f64) {
+        input.play_inputs();
+        self.update(input, delta);
+        self.apply_input(input, delta);
+    }
+
+    // Character or camera movement for the next tick, pausing and stepping through the history
+    fn apply_input(&mut self, input: &InputContext, delta: f64) {
+        let keyboard = &input.keyboard;
+
+        // Character movement is relative to where the camera looks, flattened onto the ground
+        let look = self.camera.forward();
+        let forward = match Vector3::new(look.x, 0.0, look.z) {
+            flat if flat.magnitude2() > 1.0e-6 => flat.normalize(),
+            _ => -Vector3::unit_z()
+        };
+        let right = forward.cross(Vector3::unit_y());
+
+        if let Some(character) = self.character.as_mut() {
+            let mut movement = Vector3::zero();
+            if keyboard.is_key_pressed(VirtualKeyCode::W) { movement += forward; }
+            if keyboard.is_key_pressed(VirtualKeyCode::S) { movement -= forward; }
+            if keyboard.is_key_pressed(VirtualKeyCode::A) { movement -= right; }
+            if keyboard.is_key_pressed(VirtualKeyCode::D) { movement += right; }
+            character.set_movement(movement);
+
+            if keyboard.is_key_pressed(VirtualKeyCode::Space) {
+                character.jump();
+            }
+        } else {
+            let mut camera_input = CameraInput::new();
+            if keyboard.is_key_pressed(VirtualKeyCode::W) { camera_input.movement.z += 1.0; }
+            if keyboard.is_key_pressed(VirtualKeyCode::S) { camera_input.movement.z -= 1.0; }
+            if keyboard.is_key_pressed(VirtualKeyCode::D) { camera_input.movement.x += 1.0; }
+            if keyboard.is_key_pressed(VirtualKeyCode::A) { camera_input.movement.x -= 1.0; }
+            if keyboard.is_key_pressed(VirtualKeyCode::Space) { camera_input.movement.y += 1.0; }
+            if keyboard.is_key_pressed(VirtualKeyCode::LShift) { camera_input.movement.y -= 1.0; }
+            camera_input.boost = keyboard.is_key_pressed(VirtualKeyCode::LControl);
+
+            // Looking around while the right button is held
+            let mouse = &input.mouse;
+            if mouse.is_button_pressed(MouseButton::Right) {
+                let (x, y) = mouse.motion();
+                camera_input.look = Vector2::new(x, y);
+            }
+            camera_input.zoom = mouse.scroll();
+
+            self.camera_input = camera_input;
+        }
+
+        // History
+        if keyboard.is_key_just_released(VirtualKeyCode::P) {
+            self.paused = !self.paused;
+        }
+
+        if self.paused {
+            if keyboard.is_key_just_released(VirtualKeyCode::Left) {
+                self.step_back();
+            }
 
-        Box::leak(Box::new(Self {name, camera, objects}))
+            if keyboard.is_key_just_released(VirtualKeyCode::Right) {
+                self.step_forward(input, delta);
+            }
+        }
+    }
+
+    /// Records every input event from now on into `path`, the physics switches to deterministic
+    /// mode so a replay reproduces the session.
+    pub fn start_input_recording(&mut self, input: &mut InputContext, path: &Path, seed: u64, timestep: f64) -> io::Result<()> {
+        self.physics.set_deterministic(timestep, seed);
+        input.start_recording(path, seed, timestep)
+    }
+
+    /// Plays back a recorded session, live input is ignored until it ends.
+    pub fn start_input_replay(&mut self, input: &mut InputContext, path: &Path) -> io::Result<()> {
+        let player = ReplayPlayer::load(path)?;
+        self.physics.set_deterministic(player.timestep(), player.seed());
+        input.start_playback(player);
+        Ok(())
+    }
+
+    // One simulated tick, shared by `update` and `step_forward`
+    fn tick(&mut self, input: &InputContext, delta: f64) {
+        self.update_controllers(delta);
+        self.physics.update(delta);
+        self.update_character(delta);
+
+        for object in self.objects.iter_mut() {
+            object.on_update(input);
+        }
+
+        self.update_particles(delta);
+        self.update_debug(self.debug_visible);
+        self.export_data(delta);
+        self.record();
+    }
+
+    // A failing recording is stopped instead of failing the update
+    fn export_data(&mut self, delta: f64) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(&self.physics, delta) {
+                println!("Failed to record simulation data: {e}");
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Writes the quantities chosen in `settings` to `path` every tick until stopped.
+    pub fn start_data_recording(&mut self, path: &Path, settings: ExportSettings) -> io::Result<()> {
+        self.recorder = Some(DataRecorder::create(path, settings)?);
+        Ok(())
     }
 
-    pub fn update(&mut self, _ctx: &EngineContext, delta: f64) {
-        self.camera.update(delta);
+    /// Returns the number of rows written.
+    pub fn stop_data_recording(&mut self) -> io::Result<usize> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(0)
+        }
+    }
+
+    pub fn is_data_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
 
-        for object in self.objects.into_iter() {
-            object.on_update(_ctx);
+    /// Builds the debug lines every tick, they are always built while the debug draw records.
+    pub fn set_debug_visible(&mut self, visible: bool) {
+        self.debug_visible = visible;
+    }
+
+    // Debug lines are only built when shown or recorded
+    fn update_debug(&mut self, visible: bool) {
+        if visible || self.debug.is_recording() {
+            self.debug.draw_world(&self.physics);
+        } else {
+            self.debug.clear();
         }
     }
 
+    fn update_controllers(&mut self, delta: f64) {
+        for object in self.objects.iter_mut() {
+            object.on_physics_update(&mut self.physics, delta);
+        }
+    }
+
+    // The camera follows the character when there is one
+    fn update_character(&mut self, delta: f64) {
+        match self.character.as_mut() {
+            Some(character) => {
+                character.update(self.physics.bodies(), delta);
+                self.camera.transform.position = character.eye_position();
+                self.camera.velocity = character.velocity;
+            },
+            None => self.camera_controller.update(&mut self.camera, &self.camera_input, &self.physics, delta)
+        }
+    }
+
+    fn update_particles(&mut self, delta: f64) {
+        let objects = &self.objects;
+        self.particles.update(delta, |i| {
+            objects.get(i).and_then(|o| o.get_transform()).map(|t| t.get_position())
+        });
+    }
+
     pub fn add_object(&mut self, object: ObjectType) {
         self.objects.push(object);
     }
@@ -129,4 +349,147 @@ impl World {
     pub fn get_camera(&mut self) -> &mut Camera {
         &mut self.camera
     }
+
+    pub fn set_camera_controller(&mut self, controller: CameraControllerType) {
+        self.camera_controller = controller;
+    }
+
+    pub fn get_camera_controller(&mut self) -> &mut CameraControllerType {
+        &mut self.camera_controller
+    }
+
+    /// Input for the camera controller, used until replaced.
+    pub fn set_camera_input(&mut self, input: CameraInput) {
+        self.camera_input = input;
+    }
+
+    pub fn set_character(&mut self, character: Option<CharacterController>) {
+        self.character = character;
+    }
+
+    pub fn get_character(&mut self) -> Option<&mut CharacterController> {
+        self.character.as_mut()
+    }
+
+    pub fn get_physics(&mut self) -> &mut PhysicsWorld {
+        &mut self.physics
+    }
+
+    pub fn get_debug_draw(&mut self) -> &mut DebugDraw {
+        &mut self.debug
+    }
+
+    pub fn get_particles(&mut self) -> &mut ParticleSystem {
+        &mut self.particles
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> EmitterHandle {
+        self.particles.add_emitter(emitter)
+    }
+
+    /// Spawns the links and joints of an imported robot description.
+    pub fn add_robot(&mut self, model: &RobotModel, base: Pose, fixed_base: bool) -> Robot {
+        model.spawn(&mut self.physics, base, fixed_base)
+    }
+
+    pub fn add_force_generator(&mut self, generator: ForceType) -> ForceHandle {
+        self.physics.add_force(generator)
+    }
+
+    pub fn remove_force_generator(&mut self, handle: ForceHandle) {
+        self.physics.remove_force(handle);
+    }
+
+    // Snapshots
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot::capture(self)
+    }
+
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        snapshot.restore(self);
+    }
+
+    /// Keeps the last `ticks` states in memory, 0 disables the history.
+    pub fn set_history_capacity(&mut self, ticks: usize) {
+        self.history.set_capacity(ticks);
+    }
+
+    pub fn get_history(&mut self) -> &mut SnapshotHistory {
+        &mut self.history
+    }
+
+    fn record(&mut self) {
+        if self.history.capacity() > 0 {
+            let snapshot = self.snapshot();
+            self.history.push(snapshot);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        match self.history.step_back().cloned() {
+            Some(snapshot) => {
+                snapshot.restore(self);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Replays recorded history first, simulates a new tick once it runs out. In fixed step
+    /// mode `delta` goes through the accumulator like any other update.
+    pub fn step_forward(&mut self, input: &InputContext, delta: f64) {
+        if let Some(snapshot) = self.history.step_forward().cloned() {
+            snapshot.restore(self);
+            return;
+        }
+
+        self.tick(input, delta);
+    }
+
+    pub fn add_soft_body(&mut self, body: SoftBody) -> SoftBodyHandle {
+        self.physics.add_soft_body(body)
+    }
+
+    pub fn add_fluid(&mut self, fluid: Fluid) -> FluidHandle {
+        self.physics.add_fluid(fluid)
+    }
+
+    pub fn add_vehicle(&mut self, vehicle: Vehicle) -> VehicleHandle {
+        self.physics.add_vehicle(vehicle)
+    }
+
+    pub fn add_destructible(&mut self, destructible: Destructible) -> DestructibleHandle {
+        self.physics.add_destructible(destructible)
+    }
+
+    /// Orbital mode, bodies only attract each other.
+    pub fn enable_nbody(&mut self, settings: NBodySettings) {
+        self.physics.clear_forces();
+        self.physics.settings.force_model = ForceModel::NBody(settings);
+        self.physics.settings.sleep_enabled = false;
+        self.physics.reset_nbody_stats();
+    }
+
+    /// Hash of the simulated state, equal hashes mean bit-identical worlds.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+
+        hasher.write_vector(self.camera.transform.position);
+        hasher.write_vector(self.camera.velocity);
+        if let Some(character) = self.character.as_ref() {
+            hasher.write_vector(character.position);
+            hasher.write_vector(character.velocity);
+        }
+        self.physics.hash_state(&mut hasher);
+
+        hasher.finish()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,170 @@
+// Runs a scene without a window, for batch runs and parameter sweeps on servers.
+
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use dengine::engine::export::{DataRecorder, ExportFormat, ExportSettings};
+use dengine::engine::physics::PhysicsWorld;
+use dengine::engine::physics::determinism::StateHasher;
+use dengine::engine::physics::profiler::PhysicsProfiler;
+use dengine::engine::scene::Scene;
+
+const USAGE: &str = "Usage: headless <scene.json> [options]
+
+Options:
+    --ticks <n>             Ticks to simulate (default 600)
+    --timestep <seconds>    Length of a tick (default 1/60)
+    --seed <n>              Seed of the physics random generator (default 0)
+    --deterministic         Fixed steps and quantized state, prints the state hash
+    --output <file>         Writes the final scene
+    --trajectory <file>     Writes every body each tick, .jsonl for JSON Lines else CSV
+    --sample-rate <hz>      Trajectory samples per simulated second (default every tick)
+    --profile <file>        Writes the timings of every step as CSV";
+
+struct Options {
+    scene: String,
+    ticks: u64,
+    timestep: f64,
+    seed: u64,
+    deterministic: bool,
+    output: Option<String>,
+    trajectory: Option<String>,
+    sample_rate: f64,
+    profile: Option<String>
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn value<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, name: &str) -> T {
+    let value = args.next().unwrap_or_else(|| fail(&format!("{} needs a value", name)));
+    value.parse().unwrap_or_else(|_| fail(&format!("Invalid value for {}: {}", name, value)))
+}
+
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options {
+        scene: String::new(),
+        ticks: 600,
+        timestep: 1.0 / 60.0,
+        seed: 0,
+        deterministic: false,
+        output: None,
+        trajectory: None,
+        sample_rate: 0.0,
+        profile: None
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => options.ticks = value(&mut args, "--ticks"),
+            "--timestep" => options.timestep = value(&mut args, "--timestep"),
+            "--seed" => options.seed = value(&mut args, "--seed"),
+            "--deterministic" => options.deterministic = true,
+            "--output" => options.output = Some(value(&mut args, "--output")),
+            "--trajectory" => options.trajectory = Some(value(&mut args, "--trajectory")),
+            "--sample-rate" => options.sample_rate = value(&mut args, "--sample-rate"),
+            "--profile" => options.profile = Some(value(&mut args, "--profile")),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            _ if arg.starts_with("--") => fail(&format!("Unknown option {}", arg)),
+            _ if options.scene.is_empty() => options.scene = arg,
+            _ => fail(&format!("Unexpected argument {}", arg))
+        }
+    }
+
+    if options.scene.is_empty() {
+        fail("No scene file given");
+    }
+    if options.timestep <= 0.0 {
+        fail("--timestep has to be positive");
+    }
+
+    options
+}
+
+fn main() {
+    let options = parse_args();
+
+    let scene = Scene::load(Path::new(&options.scene)).unwrap_or_else(|e| {
+        eprintln!("Failed to load scene {}: {}", options.scene, e);
+        process::exit(1);
+    });
+
+    let mut physics = PhysicsWorld::new();
+    scene.build(&mut physics);
+
+    if options.deterministic {
+        physics.set_deterministic(options.timestep, options.seed);
+    } else {
+        physics.set_seed(options.seed);
+    }
+
+    let mut recorder = options.trajectory.as_ref().map(|path| {
+        let format = match path.ends_with(".jsonl") {
+            true => ExportFormat::JsonLines,
+            false => ExportFormat::Csv
+        };
+        let settings = ExportSettings::new(format)
+            .with_sample_rate(options.sample_rate)
+            .with_gravity(scene.gravity.into());
+
+        DataRecorder::create(Path::new(path), settings).unwrap_or_else(|e| {
+            eprintln!("Failed to create trajectory file {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let mut profiler = PhysicsProfiler::new(options.ticks as usize);
+
+    println!("Simulating {} bodies for {} ticks of {}s...", scene.bodies.len(), options.ticks, options.timestep);
+    let start = Instant::now();
+
+    for _ in 0..options.ticks {
+        physics.update(options.timestep);
+        profiler.record(physics.step_stats());
+
+        if let Some(Err(e)) = recorder.as_mut().map(|recorder| recorder.record(&physics, options.timestep)) {
+            eprintln!("Failed to record trajectory: {}", e);
+            recorder = None;
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("Done in {:.3}s ({:.1} ticks/s)", elapsed, options.ticks as f64 / elapsed.max(1.0e-9));
+
+    if let Some(recorder) = recorder {
+        match recorder.finish() {
+            Ok(rows) => println!("Trajectory: {} rows", rows),
+            Err(e) => eprintln!("Failed to write trajectory: {}", e)
+        }
+    }
+
+    if let Some(path) = options.profile.as_ref() {
+        let average = profiler.average();
+        println!("Average step: {:.1}us", average.total.as_secs_f64() * 1.0e6);
+
+        if let Err(e) = profiler.export_csv(Path::new(path)) {
+            eprintln!("Failed to write profile {}: {}", path, e);
+        }
+    }
+
+    if let Some(path) = options.output.as_ref() {
+        let scene = Scene::capture(&physics, scene.gravity.into());
+        if let Err(e) = scene.save(Path::new(path)) {
+            eprintln!("Failed to write scene {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
+    if options.deterministic {
+        let mut hasher = StateHasher::new();
+        physics.hash_state(&mut hasher);
+        println!("State hash: {:016x}", hasher.finish());
+    }
+}
@@ -0,0 +1,7 @@
+// The simulation without a window, shared by the windowed and the headless binary
+
+pub mod engine {
+    pub mod physics;
+    pub mod scene;
+    pub mod export;
+}
@@ -8,9 +8,11 @@
 )]
 
 
+use std::path::Path;
 use std::time::{Duration, Instant};
 use vulkano::sync::GpuFuture;
-use winit::event::{ElementState, Event, WindowEvent};
+use winit::event::{DeviceEvent, ElementState, Event, MouseScrollDelta, WindowEvent};
+use engine::world::context::InputEvent;
 use winit::event_loop::{ControlFlow, EventLoop};
 
 mod engine;
@@ -28,6 +30,24 @@ fn main() {
     println!("Creating Main Application...");
     let app = EngineApplication::new(&event_loop);
 
+    // --record <file> or --replay <file>
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--record") {
+        let path = args.get(i + 1).expect("--record needs a file path");
+        app.start_recording(Path::new(path), 0).expect("Failed to create replay file");
+        println!("Recording input to {}", path);
+    } else if let Some(i) = args.iter().position(|a| a == "--replay") {
+        let path = args.get(i + 1).expect("--replay needs a file path");
+        app.start_replay(Path::new(path)).expect("Failed to load replay file");
+        println!("Playing replay {}", path);
+    }
+
+    // --history <seconds> keeps ticks for stepping back while paused
+    if let Some(i) = args.iter().position(|a| a == "--history") {
+        let seconds = args.get(i + 1).and_then(|s| s.parse::<f64>().ok()).expect("--history needs a number of seconds");
+        app.enable_history(seconds);
+    }
+
     println!("Initialization completed in {}s", Instant::now().duration_since(engine_init_time).as_secs_f32());
 
     println!("Starting main loop...");
@@ -45,26 +65,43 @@ fn main() {
                         control_flow.set_exit();
                     },
                     WindowEvent::KeyboardInput {input, ..} => {
-                        let keyboard = &mut app.get_context_mut().keyboard;
-
                         if input.virtual_keycode.is_none() {
                             return;
                         }
 
+                        let key = input.virtual_keycode.unwrap();
                         if input.state == ElementState::Pressed {
-                            keyboard.pressed_keys().insert(input.virtual_keycode.unwrap());
-                            println!("Key \"{:?}\" has been pressed", input.virtual_keycode.unwrap());
+                            app.get_context_mut().input.handle_input(InputEvent::Pressed(key));
+                            println!("Key \"{:?}\" has been pressed", key);
                         } else {
-                            keyboard.pressed_keys().remove(&input.virtual_keycode.unwrap());
-                            keyboard.just_released_keys().insert(input.virtual_keycode.unwrap());
-                            println!("Key \"{:?}\" has been released", input.virtual_keycode.unwrap());
+                            app.get_context_mut().input.handle_input(InputEvent::Released(key));
+                            println!("Key \"{:?}\" has been released", key);
                         }
                     },
 
+                    WindowEvent::MouseInput {state, button, ..} => {
+                        let event = match state {
+                            ElementState::Pressed => InputEvent::MousePressed(button),
+                            ElementState::Released => InputEvent::MouseReleased(button)
+                        };
+                        app.get_context_mut().input.handle_input(event);
+                    },
+                    WindowEvent::MouseWheel {delta, ..} => {
+                        // Pixel deltas of touchpads are about 20 pixels per line
+                        let lines = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y as f64,
+                            MouseScrollDelta::PixelDelta(position) => position.y / 20.0
+                        };
+                        app.get_context_mut().input.handle_input(InputEvent::Scrolled(lines));
+                    },
+
                     WindowEvent::Resized(..) | WindowEvent::ScaleFactorChanged { .. } => renderer.resize(),
                     _ => ()
                 }
             },
+            Event::DeviceEvent {event: DeviceEvent::MouseMotion {delta}, ..} => {
+                app.get_context_mut().input.handle_input(InputEvent::MouseMoved(delta.0, delta.1));
+            },
             Event::RedrawRequested(_) => {
                 let draw_start = Instant::now();
                 app.compute_then_render();
@@ -79,13 +116,12 @@ fn main() {
 
         match *control_flow {
             ControlFlow::Poll => {
-
                 // Update World
                 let phys_delta = 1.0 / app.settings.fps_limit as f64;
                 app.update_world(phys_delta);
 
                 let context = app.get_context();
-                let time = &context.time;
+                let time = &context.input.time;
 
                 let delta = time.delta();
                 //println!("{:?}", delta);
@@ -98,7 +134,7 @@ fn main() {
                 // match input
                 app.match_input();
                 //Update context
-                app.get_context_mut().update();
+                app.get_context_mut().input.update();
             },
             _ => ()
         }